@@ -1,53 +1,408 @@
-use flo_canvas::{Color, Draw, GraphicsContext, GraphicsPrimitives, Transform2D};
+mod scene;
 
-const WIDTH: f32 = 1280.;
-const HEIGHT: f32 = 720.;
+use std::sync::Arc;
+
+use flo_canvas::{
+    CanvasFontFace, Color, Draw, FontId, GraphicsContext, GraphicsPrimitives, Transform2D,
+};
+
+pub use scene::{ChatLine, ChatPart, DefinitionCard, PollView, Scene};
+
+const FONT: FontId = FontId(1);
+const MARGIN: f32 = 60.;
+const TITLE_SIZE: f32 = 28.;
+const TEXT_SIZE: f32 = 42.;
+const LINE_HEIGHT: f32 = 1.4;
+/// Rough average glyph width relative to the font size, used to wrap lines
+const GLYPH_WIDTH: f32 = 0.5;
+/// Part of the frame width taken by the chat panel
+const CHAT_PANEL: f32 = 0.28;
+const CHAT_SIZE: f32 = 22.;
+const CHAT_PADDING: f32 = 16.;
+const POLL_SIZE: f32 = 22.;
+const POLL_WIDTH: f32 = 520.;
+const DEFINITION_SIZE: f32 = 24.;
+/// Longer definitions are cut, the card must not cover the text
+const DEFINITION_LINES: usize = 4;
+const CAPTION_SIZE: f32 = 30.;
+const CAPTION_LINES: usize = 3;
 
 pub struct EbookContext {
-    x: f32,
-    y: f32,
+    width: f32,
+    height: f32,
+    font: Option<Arc<CanvasFontFace>>,
 }
 
 #[no_mangle]
-pub fn init() -> EbookContext {
-    EbookContext { x: 0.0, y: 0.0 }
+pub fn init(width: f32, height: f32, font: Option<&[u8]>) -> EbookContext {
+    EbookContext {
+        width,
+        height,
+        font: font.map(CanvasFontFace::from_slice),
+    }
 }
 
 #[no_mangle]
-pub fn render(mut drawing: Vec<Draw>, context: &mut EbookContext) -> Vec<Draw> {
-    context.x += 2.0;
-    if context.x >= WIDTH {
-        context.x = 0.;
-    }
-    let x = context.x;
-
-    context.y += 2.0;
-    if context.y >= HEIGHT {
-        context.y = 0.;
-    }
-    let y = context.y;
+pub fn render(mut drawing: Vec<Draw>, context: &mut EbookContext, scene: &Scene) -> Vec<Draw> {
+    let (width, height) = (context.width, context.height);
+    let chat_width = if scene.show_chat {
+        width * CHAT_PANEL
+    } else {
+        0.
+    };
+    let text_width = width - chat_width - MARGIN * 2.;
 
     // FIXME: Same error, canvas sizes are some weird. It wraps around some
     // unknown value.
     drawing.clear_canvas(Color::Rgba(0.9, 0.8, 0.8, 1.0));
-    drawing.canvas_height(HEIGHT);
+    drawing.canvas_height(height);
     drawing.transform(Transform2D::scale(1.0, 1.0));
-    drawing.center_region(0., 0., WIDTH, HEIGHT);
+    drawing.center_region(0., 0., width, height);
 
+    // Progress bar
     drawing.new_path();
-    drawing.rect(-20., -20., 20., 20.);
-    drawing.fill_color(Color::Rgba(0., 0., 1., 1.));
+    drawing.rect(0., 0., width * scene.progress.clamp(0., 1.), 8.);
+    drawing.fill_color(Color::Rgba(0.6, 0.2, 0.2, 1.0));
     drawing.fill();
 
+    let Some(font) = &context.font else {
+        return drawing;
+    };
+    drawing.define_font_data(FONT, font.clone());
+
+    drawing.fill_color(Color::Rgba(0.3, 0.2, 0.2, 1.0));
+    let header = if scene.chapter.is_empty() {
+        scene.title.clone()
+    } else {
+        format!("{} — {}", scene.title, scene.chapter)
+    };
+    text_block(
+        &mut drawing,
+        &header,
+        MARGIN,
+        height - MARGIN,
+        TITLE_SIZE,
+        text_width,
+    );
+
+    drawing.fill_color(Color::Rgba(0.1, 0.1, 0.1, 1.0));
+    text_block(
+        &mut drawing,
+        &scene.text,
+        MARGIN,
+        height / 2. + TEXT_SIZE,
+        TEXT_SIZE,
+        text_width,
+    );
+
+    if let Some(poll) = &scene.poll {
+        poll_card(
+            &mut drawing,
+            poll,
+            MARGIN,
+            height - MARGIN - TITLE_SIZE * 2.,
+            text_width.min(POLL_WIDTH),
+        );
+    }
+
+    let mut bottom = MARGIN;
+    if let Some(caption) = &scene.caption {
+        let top = caption_band(&mut drawing, caption, MARGIN, CHAT_PADDING, text_width);
+        bottom = bottom.max(top + CHAT_PADDING);
+    }
+
+    if let Some(definition) = &scene.definition {
+        definition_card(&mut drawing, definition, MARGIN, bottom, text_width);
+    }
+
+    if scene.show_chat {
+        chat_panel(
+            &mut drawing,
+            &scene.chat,
+            width - chat_width,
+            chat_width,
+            height,
+        );
+    }
+
+    if scene.ending {
+        ending_card(&mut drawing, width, height);
+    }
+
+    drawing
+}
+
+fn ending_card(drawing: &mut Vec<Draw>, width: f32, height: f32) {
     drawing.new_path();
-    drawing.rect(x, y, x + 200., y + 200.);
-    drawing.fill_color(Color::Rgba(1.0, 0.0, 0.0, 1.0));
+    drawing.rect(0., 0., width, height);
+    drawing.fill_color(Color::Rgba(0.1, 0.05, 0.05, 0.9));
     drawing.fill();
 
+    drawing.fill_color(Color::Rgba(0.9, 0.8, 0.8, 1.0));
+    text_block(
+        drawing,
+        "Stream ending. Thanks for listening!",
+        MARGIN,
+        height / 2.,
+        TEXT_SIZE,
+        width - MARGIN * 2.,
+    );
+}
+
+/// Options with a bar for their share of the votes, hanging from `top`
+fn poll_card(drawing: &mut Vec<Draw>, poll: &PollView, left: f32, top: f32, width: f32) {
+    let row = POLL_SIZE * LINE_HEIGHT;
+    let bottom = top - row * (poll.options.len() + 1) as f32 - CHAT_PADDING;
+    let total = poll
+        .options
+        .iter()
+        .map(|(_, votes)| votes)
+        .sum::<usize>()
+        .max(1);
+
     drawing.new_path();
-    drawing.rect(x - 20., y - 20., x + 20., y + 20.);
-    drawing.fill_color(Color::Rgba(1.0, 0.0, 0.0, 1.0));
+    drawing.rect(left, bottom, left + width, top);
+    drawing.fill_color(Color::Rgba(0.3, 0.2, 0.2, 0.15));
     drawing.fill();
 
-    drawing
+    drawing.set_font_size(FONT, POLL_SIZE);
+    let x = left + CHAT_PADDING;
+    let mut y = top - row;
+
+    let status = match poll.winner {
+        Some(_) => "done".to_owned(),
+        None => format!("{}s", poll.seconds_left),
+    };
+    drawing.fill_color(Color::Rgba(0.3, 0.2, 0.2, 1.0));
+    drawing.draw_text(FONT, format!("{} · {status}", poll.title), x, y);
+
+    for (idx, (label, votes)) in poll.options.iter().enumerate() {
+        y -= row;
+
+        let share = *votes as f32 / total as f32;
+        drawing.new_path();
+        drawing.rect(
+            x,
+            y - POLL_SIZE * 0.3,
+            x + (width - CHAT_PADDING * 2.) * share,
+            y + POLL_SIZE * 0.9,
+        );
+        drawing.fill_color(Color::Rgba(0.6, 0.2, 0.2, 0.3));
+        drawing.fill();
+
+        let alpha = match poll.winner {
+            Some(winner) if winner != idx => 0.4,
+            _ => 1.0,
+        };
+        drawing.fill_color(Color::Rgba(0.1, 0.1, 0.1, alpha));
+        drawing.draw_text(FONT, format!("{}. {label} ({votes})", idx + 1), x, y);
+    }
+}
+
+/// The word and its definition, standing on `bottom`
+fn definition_card(
+    drawing: &mut Vec<Draw>,
+    card: &DefinitionCard,
+    left: f32,
+    bottom: f32,
+    width: f32,
+) {
+    let row = DEFINITION_SIZE * LINE_HEIGHT;
+    let max_chars =
+        ((width - CHAT_PADDING * 2.) / (DEFINITION_SIZE * GLYPH_WIDTH)).max(1.) as usize;
+
+    let mut lines = wrap(&card.definition, max_chars);
+    if lines.len() > DEFINITION_LINES {
+        lines.truncate(DEFINITION_LINES);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+
+    let top = bottom + row * (lines.len() + 1) as f32 + CHAT_PADDING;
+    drawing.new_path();
+    drawing.rect(left, bottom, left + width, top);
+    drawing.fill_color(Color::Rgba(0.3, 0.2, 0.2, 0.15));
+    drawing.fill();
+
+    drawing.set_font_size(FONT, DEFINITION_SIZE);
+    let x = left + CHAT_PADDING;
+    let mut y = top - row;
+
+    drawing.fill_color(Color::Rgba(0.6, 0.2, 0.2, 1.0));
+    drawing.draw_text(FONT, card.word.clone(), x, y);
+
+    drawing.fill_color(Color::Rgba(0.1, 0.1, 0.1, 1.0));
+    for line in lines {
+        y -= row;
+        drawing.draw_text(FONT, line, x, y);
+    }
+}
+
+/// The words being heard on a dark band standing on `bottom`, returns the
+/// top of the band
+fn caption_band(drawing: &mut Vec<Draw>, caption: &str, left: f32, bottom: f32, width: f32) -> f32 {
+    let row = CAPTION_SIZE * LINE_HEIGHT;
+    let max_chars = ((width - CHAT_PADDING * 2.) / (CAPTION_SIZE * GLYPH_WIDTH)).max(1.) as usize;
+
+    let mut lines = wrap(caption, max_chars);
+    if lines.len() > CAPTION_LINES {
+        lines.truncate(CAPTION_LINES);
+        if let Some(last) = lines.last_mut() {
+            last.push('…');
+        }
+    }
+
+    let top = bottom + row * lines.len() as f32 + CHAT_PADDING;
+    drawing.new_path();
+    drawing.rect(left, bottom, left + width, top);
+    drawing.fill_color(Color::Rgba(0.1, 0.05, 0.05, 0.8));
+    drawing.fill();
+
+    drawing.set_font_size(FONT, CAPTION_SIZE);
+    drawing.fill_color(Color::Rgba(0.95, 0.95, 0.95, 1.0));
+    let mut y = top - row;
+    for line in lines {
+        drawing.draw_text(FONT, line, left + CHAT_PADDING, y);
+        y -= row;
+    }
+
+    top
+}
+
+/// Messages stacked from the bottom, the oldest ones are dropped when the
+/// panel is full
+fn chat_panel(drawing: &mut Vec<Draw>, chat: &[ChatLine], left: f32, width: f32, height: f32) {
+    drawing.new_path();
+    drawing.rect(left, 0., left + width, height);
+    drawing.fill_color(Color::Rgba(0.3, 0.2, 0.2, 0.1));
+    drawing.fill();
+
+    drawing.set_font_size(FONT, CHAT_SIZE);
+
+    let line_height = CHAT_SIZE * LINE_HEIGHT;
+    let max_width = width - CHAT_PADDING * 2.;
+    let mut bottom = CHAT_PADDING;
+
+    for line in chat.iter().rev() {
+        let tokens = chat_layout(line, max_width);
+        let rows = tokens.last().map_or(0, |token| token.row + 1);
+        let top = bottom + rows as f32 * line_height;
+        if top > height - CHAT_PADDING {
+            break;
+        }
+
+        for token in tokens {
+            let x = left + CHAT_PADDING + token.x;
+            let y = top - CHAT_SIZE - token.row as f32 * line_height;
+
+            match token.kind {
+                ChatToken::Name(name) => {
+                    let (r, g, b) = line.color;
+                    drawing.fill_color(Color::Rgba(r, g, b, line.opacity));
+                    drawing.draw_text(FONT, name, x, y);
+                }
+                ChatToken::Word(word) => {
+                    drawing.fill_color(Color::Rgba(0.1, 0.1, 0.1, line.opacity));
+                    drawing.draw_text(FONT, word.to_owned(), x, y);
+                }
+                ChatToken::Emote => {
+                    drawing.new_path();
+                    drawing.rect(x, y - CHAT_SIZE * 0.2, x + CHAT_SIZE, y + CHAT_SIZE * 0.8);
+                    drawing.fill_color(Color::Rgba(0.6, 0.2, 0.2, 0.6 * line.opacity));
+                    drawing.fill();
+                }
+            }
+        }
+
+        bottom = top + CHAT_PADDING / 2.;
+    }
+}
+
+enum ChatToken<'a> {
+    Name(String),
+    Word(&'a str),
+    Emote,
+}
+
+struct PlacedToken<'a> {
+    kind: ChatToken<'a>,
+    x: f32,
+    row: usize,
+}
+
+/// Place the name, words and emotes of `line` in rows of `max_width`
+fn chat_layout(line: &ChatLine, max_width: f32) -> Vec<PlacedToken<'_>> {
+    let char_width = CHAT_SIZE * GLYPH_WIDTH;
+    let text_width = |text: &str| text.chars().count() as f32 * char_width;
+
+    let name = format!("{}:", line.user);
+    let mut tokens = vec![(text_width(&name), ChatToken::Name(name))];
+    for part in &line.parts {
+        match part {
+            ChatPart::Text(text) => tokens.extend(
+                text.split_whitespace()
+                    .map(|word| (text_width(word), ChatToken::Word(word))),
+            ),
+            ChatPart::Emote(_) => tokens.push((CHAT_SIZE, ChatToken::Emote)),
+        }
+    }
+
+    let (mut x, mut row) = (0., 0);
+    tokens
+        .into_iter()
+        .map(|(width, kind)| {
+            if x > 0. && x + width > max_width {
+                x = 0.;
+                row += 1;
+            }
+            let placed = PlacedToken { kind, x, row };
+            x += width + char_width;
+            placed
+        })
+        .collect()
+}
+
+/// Draw `text` wrapped to `max_width`, starting with the baseline at `y`.
+/// Returns the baseline of the next line.
+fn text_block(
+    drawing: &mut Vec<Draw>,
+    text: &str,
+    x: f32,
+    mut y: f32,
+    size: f32,
+    max_width: f32,
+) -> f32 {
+    drawing.set_font_size(FONT, size);
+
+    let max_chars = (max_width / (size * GLYPH_WIDTH)).max(1.) as usize;
+    for line in wrap(text, max_chars) {
+        drawing.draw_text(FONT, line, x, y);
+        y -= size * LINE_HEIGHT;
+    }
+
+    y
+}
+
+fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let len = line.chars().count();
+        if len > 0 && len + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
 }
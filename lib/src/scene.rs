@@ -0,0 +1,60 @@
+/// Everything shown in the frame. The reader updates it and [`crate::render`]
+/// draws it on every tick.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    /// Title of the book being read
+    pub title: String,
+    /// Title of the current chapter
+    pub chapter: String,
+    /// Sentence being read aloud
+    pub text: String,
+    /// Reading progress of the book, between 0 and 1
+    pub progress: f32,
+    /// Show the "stream ending" card over everything
+    pub ending: bool,
+    /// Reserve the chat side panel, even while [`Scene::chat`] is empty
+    pub show_chat: bool,
+    /// Last chat messages, oldest first
+    pub chat: Vec<ChatLine>,
+    /// Viewer poll running or just finished
+    pub poll: Option<PollView>,
+    /// Word looked up with `!define`
+    pub definition: Option<DefinitionCard>,
+    /// What is being heard right now, for burnt-in captions
+    pub caption: Option<String>,
+}
+
+/// A chat message shown in the side panel
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatLine {
+    pub user: String,
+    /// Color of the user name, RGB between 0 and 1
+    pub color: (f32, f32, f32),
+    pub parts: Vec<ChatPart>,
+    /// 1 while the message is fresh, down to 0 before it disappears
+    pub opacity: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatPart {
+    Text(String),
+    /// Emote name, drawn as a placeholder
+    Emote(String),
+}
+
+/// Tally of a viewer poll
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PollView {
+    pub title: String,
+    /// Label and votes of every option, `!vote 1` is the first one
+    pub options: Vec<(String, usize)>,
+    pub seconds_left: u64,
+    /// Index of the winning option once the poll is over
+    pub winner: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DefinitionCard {
+    pub word: String,
+    pub definition: String,
+}
@@ -0,0 +1,610 @@
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info, warn};
+use serde_json::{json, Value};
+
+use crate::audio::AudioQueue;
+use crate::control::ReaderControl;
+use crate::encoder::AudioSettings;
+use crate::error::{EbookError, EbookResult};
+use crate::render::Theme;
+use crate::tts::Locale;
+use crate::API_LOG;
+
+/// Larger bodies are refused, every request fits in a few bytes
+const MAX_BODY_LEN: usize = 64 * 1024;
+const MAX_HEADERS: usize = 64;
+/// Longest request line or header
+const MAX_LINE_LEN: usize = 8 * 1024;
+/// Clients served at once, the rest are turned away
+const MAX_CLIENTS: usize = 16;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// `GET /status`, everything else is `POST`
+const ROUTES: &[&str] = &[
+    "/status",
+    "/pause",
+    "/resume",
+    "/skip",
+    "/seek",
+    "/voice",
+    "/speed",
+    "/pitch",
+    "/volume",
+    "/language",
+    "/theme/reload",
+    "/enqueue",
+];
+
+#[derive(Debug, Clone)]
+pub struct ControlApiConfig {
+    pub addr: SocketAddr,
+    /// Sent by clients as `Authorization: Bearer <token>`
+    pub token: String,
+}
+
+impl fmt::Display for ControlApiConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}", self.addr)
+    }
+}
+
+pub type SharedHealth = Arc<Mutex<OutputHealth>>;
+
+/// What the pipeline bus said about the output
+#[derive(Debug, Clone, Default)]
+pub struct OutputHealth {
+    /// State of the pipeline, like `Playing`
+    pub state: String,
+    pub warnings: u64,
+    pub last_warning: Option<String>,
+}
+
+/// Everything the API can look at or change
+#[derive(Debug, Clone)]
+pub struct ControlApi {
+    control: ReaderControl,
+    queue: AudioQueue,
+    theme: Theme,
+    health: SharedHealth,
+    audio: AudioSettings,
+    /// Directories `/enqueue` takes books from, the ones of the first book
+    /// and of the playlist
+    library: Arc<[PathBuf]>,
+    started: Instant,
+}
+
+impl ControlApi {
+    pub fn new(
+        control: ReaderControl,
+        queue: AudioQueue,
+        theme: Theme,
+        health: SharedHealth,
+        audio: AudioSettings,
+        books: &[PathBuf],
+    ) -> Self {
+        let mut library = vec![];
+        for book in books {
+            let dir = book
+                .canonicalize()
+                .ok()
+                .and_then(|book| book.parent().map(Path::to_owned));
+            if let Some(dir) = dir.filter(|dir| !library.contains(dir)) {
+                library.push(dir);
+            }
+        }
+
+        Self {
+            control,
+            queue,
+            theme,
+            health,
+            audio,
+            library: library.into(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Serve the API on `config.addr`, one thread per connection up to
+    /// [`MAX_CLIENTS`]
+    pub fn serve(self, config: ControlApiConfig) -> EbookResult<()> {
+        let listener = TcpListener::bind(config.addr)
+            .map_err(|err| EbookError::ControlServer(err.to_string()))?;
+        info!(target: API_LOG, "Control API on {config}");
+
+        let token: Arc<str> = config.token.into();
+        let clients = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(mut stream) => {
+                        if clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+                            clients.fetch_sub(1, Ordering::SeqCst);
+                            debug!(target: API_LOG, "Too many clients");
+                            _ = Response::error(503, "Service Unavailable").write(&mut stream);
+                            continue;
+                        }
+
+                        let api = self.clone();
+                        let token = token.clone();
+                        let clients = clients.clone();
+                        thread::spawn(move || {
+                            let peer = stream.peer_addr().ok();
+                            if let Err(err) = api.handle(stream, &token) {
+                                debug!(target: API_LOG, "Client {peer:?}: {err}");
+                            }
+                            clients.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    }
+                    Err(err) => warn!(target: API_LOG, "{err}"),
+                }
+            }
+
+            error!(target: API_LOG, "Control API stopped");
+        });
+
+        Ok(())
+    }
+
+    fn handle(&self, stream: TcpStream, token: &str) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+
+        self.respond(&mut BufReader::new(stream), token)?
+            .write(&mut writer)
+    }
+
+    fn respond(&self, reader: &mut impl BufRead, token: &str) -> io::Result<Response> {
+        Ok(match Request::read(reader)? {
+            Err(response) => response,
+            Ok(request) if !request.is_authorized(token) => Response::unauthorized(),
+            Ok(request) => {
+                debug!(target: API_LOG, "{} {}", request.method, request.path);
+                self.route(&request)
+            }
+        })
+    }
+
+    fn route(&self, request: &Request) -> Response {
+        let body = match request.json() {
+            Ok(body) => body,
+            Err(response) => return response,
+        };
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/status") => Response::ok(self.status()),
+            ("POST", "/pause") => {
+                self.control.pause();
+                Response::ok(self.status())
+            }
+            ("POST", "/resume") => {
+                self.control.resume();
+                Response::ok(self.status())
+            }
+            ("POST", "/skip") => {
+                if body["chapter"].as_bool().unwrap_or(false) {
+                    self.control.skip_chapter();
+                } else {
+                    self.control.skip();
+                }
+                Response::ok(self.status())
+            }
+            ("POST", "/seek") => {
+                if let Some(chapter) = body["chapter"].as_u64() {
+                    self.control.seek_chapter(chapter as usize);
+                } else if let Some(position) = body["position"].as_u64() {
+                    self.control.seek(position as usize);
+                } else {
+                    return Response::bad_request("Expected \"position\" or \"chapter\"");
+                }
+                Response::ok(self.status())
+            }
+            ("POST", "/voice") => {
+                match body["voice"].as_str() {
+                    Some(voice) => {
+                        if let Err(err) = self.control.set_voice(voice) {
+                            return Response::bad_request(&err.to_string());
+                        }
+                    }
+                    None => self.control.next_voice(),
+                }
+                Response::ok(self.status())
+            }
+            ("POST", "/speed") => {
+                let Some(speed) = body["speed"].as_f64() else {
+                    return Response::bad_request("Expected \"speed\"");
+                };
+                self.control.set_speed(speed as f32);
+                Response::ok(self.status())
+            }
+            ("POST", "/pitch") => {
+                let Some(pitch) = body["pitch"].as_f64() else {
+                    return Response::bad_request("Expected \"pitch\"");
+                };
+                self.control.set_pitch(pitch as f32);
+                Response::ok(self.status())
+            }
+            ("POST", "/volume") => {
+                let Some(volume) = body["volume"].as_f64() else {
+                    return Response::bad_request("Expected \"volume\"");
+                };
+                self.control.set_volume(volume as f32);
+                Response::ok(self.status())
+            }
+            ("POST", "/language") => {
+                let language = body["language"].as_str().map(str::parse::<Locale>);
+                match language {
+                    Some(Ok(language)) => self.control.set_language(language),
+                    Some(Err(err)) => return Response::bad_request(&err),
+                    None => return Response::bad_request("Expected \"language\""),
+                }
+                Response::ok(self.status())
+            }
+            ("POST", "/theme/reload") => {
+                self.theme.reload();
+                Response::ok(self.status())
+            }
+            ("POST", "/enqueue") => {
+                let Some(path) = body["path"].as_str() else {
+                    return Response::bad_request("Expected \"path\"");
+                };
+                let Some(path) = self.in_library(Path::new(path)) else {
+                    return Response::bad_request(&format!("{path} not found in the library"));
+                };
+
+                if body["next"].as_bool().unwrap_or(false) {
+                    self.control.play_next(path);
+                } else {
+                    self.control.enqueue(path);
+                }
+                Response::ok(self.status())
+            }
+            (_, path) if ROUTES.contains(&path) => Response::error(405, "Method Not Allowed"),
+            _ => Response::error(404, "Not Found"),
+        }
+    }
+
+    /// The real path of `path` when it is a book next to the first one or
+    /// the playlist, any other file on the host is refused
+    fn in_library(&self, path: &Path) -> Option<PathBuf> {
+        let path = path.canonicalize().ok()?;
+        let allowed = path.is_file() && self.library.iter().any(|dir| path.starts_with(dir));
+        allowed.then_some(path)
+    }
+
+    fn status(&self) -> Value {
+        let voice = self.control.voice();
+        let samples_per_second = (self.audio.sample_rate * self.audio.channels).max(1) as f32;
+        let health = self.health.lock().unwrap().clone();
+
+        let session = self.control.session();
+        let book = session.book();
+        let chapter = session.current_chapter();
+
+        json!({
+            "book": {
+                "title": book.title,
+                "path": book.path,
+                "chunks": book.chunks.len(),
+            },
+            "chapter": {
+                "index": chapter,
+                "title": book.chapters.get(chapter).map(|chapter| chapter.to_string()),
+            },
+            "position": session.current_position(),
+            "progress": session.progress(),
+            "paused": session.is_paused(),
+            "upcoming": session.upcoming(),
+            "voice": {
+                "language": voice.language,
+                "voice": voice.tld,
+                "speed": voice.speed,
+                "pitch": voice.pitch,
+                "volume": voice.volume,
+            },
+            "uptime_seconds": self.started.elapsed().as_secs(),
+            "output": {
+                "healthy": health.state == "Playing",
+                "state": health.state,
+                "warnings": health.warnings,
+                "last_warning": health.last_warning,
+                "buffered_seconds": self.queue.len() as f32 / samples_per_second,
+                "underruns": self.queue.underruns(),
+                "starved_seconds": self.queue.starved() as f32 / samples_per_second,
+            },
+        })
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// `Err` holds the response for a malformed request
+    fn read(reader: &mut impl BufRead) -> io::Result<Result<Self, Response>> {
+        let mut line = String::new();
+        if !read_line(reader, &mut line)? {
+            return Ok(Err(Response::error(414, "URI Too Long")));
+        }
+
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Ok(Err(Response::bad_request("Malformed request line")));
+        };
+        let method = method.to_owned();
+        // The query string is not used
+        let path = target.split('?').next().unwrap_or_default().to_owned();
+
+        let mut authorization = None;
+        let mut content_length = 0;
+        for _ in 0..MAX_HEADERS {
+            if !read_line(reader, &mut line)? {
+                return Ok(Err(Response::error(431, "Request Header Fields Too Large")));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+
+            let Some((name, value)) = header.split_once(':') else {
+                continue;
+            };
+            match name.trim().to_ascii_lowercase().as_str() {
+                "authorization" => authorization = Some(value.trim().to_owned()),
+                "content-length" => match value.trim().parse() {
+                    Ok(len) => content_length = len,
+                    Err(_) => return Ok(Err(Response::bad_request("Invalid Content-Length"))),
+                },
+                _ => {}
+            }
+        }
+
+        if content_length > MAX_BODY_LEN {
+            return Ok(Err(Response::error(413, "Payload Too Large")));
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+
+        Ok(Ok(Self {
+            method,
+            path,
+            authorization,
+            body,
+        }))
+    }
+
+    fn is_authorized(&self, token: &str) -> bool {
+        self.authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|given| constant_time_eq(given.trim().as_bytes(), token.as_bytes()))
+    }
+
+    /// The body as JSON, `null` when empty
+    fn json(&self) -> Result<Value, Response> {
+        if self.body.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Value::Null);
+        }
+
+        serde_json::from_slice(&self.body)
+            .map_err(|err| Response::bad_request(&format!("Invalid JSON: {err}")))
+    }
+}
+
+struct Response {
+    status: u16,
+    reason: &'static str,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self {
+            status: 200,
+            reason: "OK",
+            body,
+        }
+    }
+
+    fn error(status: u16, reason: &'static str) -> Self {
+        Self {
+            status,
+            reason,
+            body: json!({ "error": reason }),
+        }
+    }
+
+    fn bad_request(message: &str) -> Self {
+        Self {
+            status: 400,
+            reason: "Bad Request",
+            body: json!({ "error": message }),
+        }
+    }
+
+    fn unauthorized() -> Self {
+        Self::error(401, "Unauthorized")
+    }
+
+    fn write(&self, stream: &mut impl Write) -> io::Result<()> {
+        let body = self.body.to_string();
+        let authenticate = if self.status == 401 {
+            "WWW-Authenticate: Bearer\r\n"
+        } else {
+            ""
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\n\
+            Cache-Control: no-cache\r\n\
+            Connection: close\r\n\
+            Content-Type: application/json\r\n\
+            {authenticate}\
+            Content-Length: {}\r\n\r\n\
+            {body}",
+            self.status,
+            self.reason,
+            body.len()
+        )?;
+        stream.flush()
+    }
+}
+
+/// Replace `line` with the next one, `false` if it is longer than
+/// [`MAX_LINE_LEN`]
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> io::Result<bool> {
+    line.clear();
+    (&mut *reader).take(MAX_LINE_LEN as u64).read_line(line)?;
+    Ok(line.len() < MAX_LINE_LEN || line.ends_with('\n'))
+}
+
+/// Compare without leaking where the first difference is
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::encoder::EncoderConfig;
+    use crate::testing;
+
+    const TOKEN: &str = "secret";
+
+    fn api() -> ControlApi {
+        let audio = EncoderConfig::default().audio;
+        let control = testing::control();
+        let book = control.session().book().path.clone();
+        ControlApi::new(
+            control,
+            AudioQueue::new(&audio),
+            Theme::new(None),
+            SharedHealth::default(),
+            audio,
+            &[book],
+        )
+    }
+
+    fn request(api: &ControlApi, method: &str, path: &str, body: &str) -> Response {
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nAuthorization: Bearer {TOKEN}\r\n\
+            Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        api.respond(&mut request.as_bytes(), TOKEN).unwrap()
+    }
+
+    #[test]
+    fn routes() {
+        let api = api();
+
+        let response = request(&api, "GET", "/status?pretty", "");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["paused"], false);
+        assert_eq!(response.body["voice"]["voice"], "com");
+
+        let response = request(&api, "POST", "/pause", "");
+        assert_eq!(response.body["paused"], true);
+
+        let response = request(&api, "POST", "/voice", r#"{"voice": "com.mx"}"#);
+        assert_eq!(response.body["voice"]["voice"], "com.mx");
+        let response = request(&api, "POST", "/voice", "");
+        assert_eq!(response.body["voice"]["voice"], "es");
+
+        let response = request(&api, "POST", "/speed", r#"{"speed": 1.5}"#);
+        assert_eq!(response.body["voice"]["speed"], 1.5);
+
+        for (path, body) in [
+            ("/voice", r#"{"voice": "xx"}"#),
+            ("/speed", ""),
+            ("/seek", "{}"),
+            ("/pitch", "{not json"),
+        ] {
+            assert_eq!(request(&api, "POST", path, body).status, 400, "{path}");
+        }
+        assert_eq!(request(&api, "GET", "/nowhere", "").status, 404);
+    }
+
+    #[test]
+    fn enqueue_only_from_the_library() {
+        let api = api();
+        let dir = api.library[0].clone();
+        fs::write(dir.join("next.txt"), testing::BOOK).unwrap();
+        let outside = testing::book(testing::BOOK);
+
+        let enqueue = |path: &Path| {
+            let body = json!({ "path": path, "next": true }).to_string();
+            request(&api, "POST", "/enqueue", &body)
+        };
+        let response = enqueue(&dir.join("next.txt"));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body["upcoming"][0], json!(dir.join("next.txt")));
+
+        for path in [
+            outside,
+            dir.join("../../../etc/passwd"),
+            dir.join("missing.txt"),
+            dir.clone(),
+        ] {
+            assert_eq!(enqueue(&path).status, 400, "{}", path.display());
+        }
+        assert_eq!(api.control.session().upcoming().len(), 1);
+    }
+
+    #[test]
+    fn wrong_method() {
+        let api = api();
+        assert_eq!(request(&api, "POST", "/status", "").status, 405);
+        assert_eq!(request(&api, "GET", "/pause", "").status, 405);
+    }
+
+    #[test]
+    fn unauthorized() {
+        let api = api();
+        for authorization in [
+            "",
+            "Authorization: secret\r\n",
+            "Authorization: Bearer secreto\r\n",
+        ] {
+            let request = format!("POST /pause HTTP/1.1\r\n{authorization}\r\n");
+            let response = api.respond(&mut request.as_bytes(), TOKEN).unwrap();
+            assert_eq!(response.status, 401, "{authorization:?}");
+        }
+        assert!(!api.control.session().is_paused());
+    }
+
+    #[test]
+    fn too_large() {
+        let api = api();
+        let respond = |request: String| api.respond(&mut request.as_bytes(), TOKEN).unwrap();
+
+        let body = format!(
+            "POST /seek HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_LEN + 1
+        );
+        assert_eq!(respond(body).status, 413);
+
+        let header = format!(
+            "GET /status HTTP/1.1\r\nX-Long: {}\r\n\r\n",
+            "a".repeat(MAX_LINE_LEN)
+        );
+        assert_eq!(respond(header).status, 431);
+
+        let path = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE_LEN));
+        assert_eq!(respond(path).status, 414);
+    }
+}
@@ -0,0 +1,156 @@
+mod effects;
+mod mastering;
+mod mixer;
+mod music;
+mod queue;
+mod sounds;
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use log::error;
+
+use crate::captions::Captions;
+use crate::config::EbookConfig;
+use crate::encoder::AudioSettings;
+use crate::error::{EbookError, EbookResult};
+use crate::AUDIO_LOG;
+
+pub use effects::Effects;
+pub use mastering::{Mastering, Pause, MAX_LOUDNESS, MAX_PAUSE_SECONDS, MIN_LOUDNESS};
+pub use mixer::Mixer;
+pub use music::{play_music, Music, MusicConfig, MAX_DUCK, MAX_MUSIC_VOLUME, MIN_MUSIC_VOLUME};
+pub use queue::AudioQueue;
+pub use sounds::{Sound, Sounds};
+
+/// Name of the `appsrc` element the narration is pushed into
+pub const AUDIO_SRC_NAME: &str = "audio";
+
+/// Frames pushed on every `need-data`, ~23ms at 44.1kHz
+const FRAMES_PER_BUFFER: usize = 1024;
+/// Time to wait for a decoded buffer before giving up
+const DECODE_TIMEOUT_SECONDS: u64 = 5;
+
+/// Feed the `appsrc` named [`AUDIO_SRC_NAME`] from `mixer`, silence is
+/// pushed while there is nothing to play. `captions` follow what is heard.
+pub fn attach_mixer(
+    pipeline: &gst::Pipeline,
+    config: &EbookConfig,
+    mut mixer: Mixer,
+    captions: Captions,
+) -> EbookResult<()> {
+    let audio = config.encoder.audio;
+
+    let appsrc = pipeline
+        .by_name(AUDIO_SRC_NAME)
+        .expect("Audio source element not found")
+        .downcast::<gst_app::AppSrc>()
+        .expect("Expected a gst_app::AppSrc");
+
+    let caps = gst::Caps::from_str(&audio.caps()).map_err(EbookError::GlibBool)?;
+    appsrc.set_caps(Some(&caps));
+    appsrc.set_format(gst::Format::Time);
+
+    let rate = audio.sample_rate as u64;
+    let mut frames_sent = 0;
+    let mut samples = vec![0.; FRAMES_PER_BUFFER * audio.channels as usize];
+
+    appsrc.set_callbacks(
+        gst_app::AppSrcCallbacks::builder()
+            .need_data(move |appsrc, _| {
+                mixer.pull(&mut samples);
+                captions.advance(Duration::from_secs_f64(frames_sent as f64 / rate as f64));
+
+                let bytes = samples
+                    .iter()
+                    .flat_map(|sample| sample.to_le_bytes())
+                    .collect::<Vec<u8>>();
+
+                let mut buffer = gst::Buffer::from_mut_slice(bytes);
+                {
+                    let buffer = buffer.get_mut().unwrap();
+                    let pts = gst::ClockTime::SECOND.mul_div_floor(frames_sent, rate);
+                    let duration =
+                        gst::ClockTime::SECOND.mul_div_floor(FRAMES_PER_BUFFER as u64, rate);
+                    buffer.set_pts(pts);
+                    buffer.set_duration(duration);
+                }
+                frames_sent += FRAMES_PER_BUFFER as u64;
+
+                if let Err(err) = appsrc.push_buffer(buffer) {
+                    error!(target: AUDIO_LOG, "{err}");
+                }
+            })
+            .build(),
+    );
+
+    Ok(())
+}
+
+/// Decode any audio GStreamer understands (mp3 from gTTS) into interleaved
+/// f32 samples matching `audio`. Speed, pitch and volume are [`Effects`],
+/// level and silence [`Mastering`].
+pub fn decode(encoded: Vec<u8>, audio: &AudioSettings) -> EbookResult<Vec<f32>> {
+    let decode_err = |err: &dyn std::fmt::Display| EbookError::AudioDecode(err.to_string());
+
+    let pipeline = gst::parse::launch(&format!(
+        "appsrc name=src \
+        ! decodebin \
+        ! audioconvert \
+        ! audioresample \
+        ! {} \
+        ! appsink name=sink sync=false",
+        audio.caps()
+    ))
+    .map_err(EbookError::Glib)?
+    .downcast::<gst::Pipeline>()
+    .expect("Expected a gst::Pipeline");
+
+    let src = pipeline
+        .by_name("src")
+        .and_then(|src| src.downcast::<gst_app::AppSrc>().ok())
+        .expect("Expected a gst_app::AppSrc");
+    let sink = pipeline
+        .by_name("sink")
+        .and_then(|sink| sink.downcast::<gst_app::AppSink>().ok())
+        .expect("Expected a gst_app::AppSink");
+
+    src.push_buffer(gst::Buffer::from_mut_slice(encoded))
+        .map_err(|err| decode_err(&err))?;
+    src.end_of_stream().map_err(|err| decode_err(&err))?;
+
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(|err| decode_err(&err))?;
+
+    let mut samples = vec![];
+    while let Some(sample) =
+        sink.try_pull_sample(gst::ClockTime::from_seconds(DECODE_TIMEOUT_SECONDS))
+    {
+        let Some(buffer) = sample.buffer() else {
+            continue;
+        };
+        let map = buffer.map_readable().map_err(|err| decode_err(&err))?;
+        samples.extend(
+            map.chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        );
+    }
+
+    let error = pipeline
+        .bus()
+        .and_then(|bus| bus.pop_filtered(&[gst::MessageType::Error]));
+
+    _ = pipeline.set_state(gst::State::Null);
+
+    if let Some(msg) = error {
+        if let gst::MessageView::Error(err) = msg.view() {
+            return Err(decode_err(&err.error()));
+        }
+    }
+
+    Ok(samples)
+}
@@ -0,0 +1,290 @@
+use crate::encoder::AudioSettings;
+
+/// Window of the time stretch, long enough for the lowest voices
+const WINDOW_SECONDS: f32 = 0.03;
+/// How far from its place a window can be taken to match the previous one
+const SEEK_SECONDS: f32 = 0.01;
+/// Only one of every this many frames is compared while seeking
+const SEEK_STRIDE: usize = 4;
+/// Smaller changes are not worth processing
+const MIN_CHANGE: f32 = 0.01;
+
+/// Stages applied to the synthesized speech, in order: time stretch, pitch
+/// shift and gain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Effects {
+    /// Tempo, keeping the pitch
+    pub speed: f32,
+    /// Pitch, keeping the tempo
+    pub pitch: f32,
+    /// Gain in dB
+    pub volume: f32,
+}
+
+impl Effects {
+    pub fn apply(&self, mut samples: Vec<f32>, audio: &AudioSettings) -> Vec<f32> {
+        let channels = audio.channels as usize;
+
+        // Stretched for both, resampling takes the tempo back while it
+        // changes the pitch
+        let stretch = self.speed / self.pitch;
+        if (stretch - 1.).abs() > MIN_CHANGE {
+            samples = time_stretch(&samples, channels, audio.sample_rate, stretch);
+        }
+        if (self.pitch - 1.).abs() > MIN_CHANGE {
+            samples = resample(&samples, channels, self.pitch);
+        }
+        if self.volume.abs() > MIN_CHANGE {
+            gain(&mut samples, self.volume);
+        }
+
+        samples
+    }
+}
+
+/// WSOLA: Hann windows overlapped by half, each one taken near its place in
+/// the input where it best continues the previous one, so the pitch stays
+fn time_stretch(samples: &[f32], channels: usize, rate: u32, speed: f32) -> Vec<f32> {
+    let frames = samples.len() / channels;
+    let window = (rate as f32 * WINDOW_SECONDS) as usize & !1;
+    let hop = window / 2;
+    let seek = (rate as f32 * SEEK_SECONDS) as usize;
+    if hop == 0 || frames < window + seek {
+        return samples.to_vec();
+    }
+
+    let fade = (0..window)
+        .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / window as f32).cos())
+        .collect::<Vec<_>>();
+    let mono = samples
+        .chunks_exact(channels)
+        .map(|frame| frame.iter().sum::<f32>())
+        .collect::<Vec<_>>();
+
+    let out_frames = (frames as f32 / speed) as usize;
+    let mut out = vec![0.; (out_frames + window) * channels];
+    let mut written = 0;
+    let mut prev = 0;
+    let mut out_pos = 0;
+    while out_pos < out_frames {
+        let target = (out_pos as f64 * speed as f64) as usize;
+        let pos = if out_pos == 0 {
+            0
+        } else {
+            best_match(&mono, prev + hop, target, seek, hop, frames - window)
+        };
+        if pos + window > frames {
+            break;
+        }
+
+        for (i, fade) in fade.iter().enumerate() {
+            // Nothing before the first window to fade from
+            let weight = if out_pos == 0 && i < hop { 1. } else { *fade };
+            for c in 0..channels {
+                out[(out_pos + i) * channels + c] += samples[(pos + i) * channels + c] * weight;
+            }
+        }
+
+        prev = pos;
+        written = out_pos + window;
+        out_pos += hop;
+    }
+
+    out.truncate(out_frames.min(written) * channels);
+    out
+}
+
+/// Start around `target` whose first `len` frames look the most like the
+/// ones at `natural`, the continuation of the previous window
+fn best_match(
+    mono: &[f32],
+    natural: usize,
+    target: usize,
+    seek: usize,
+    len: usize,
+    max: usize,
+) -> usize {
+    let low = target.saturating_sub(seek).min(max);
+    let high = (target + seek).min(max);
+    if natural + len > mono.len() {
+        return target.min(max);
+    }
+
+    let reference = &mono[natural..natural + len];
+    (low..=high)
+        .map(|start| {
+            let candidate = &mono[start..start + len];
+            let (correlation, energy) = reference
+                .iter()
+                .zip(candidate)
+                .step_by(SEEK_STRIDE)
+                .fold((0., 0.), |(correlation, energy), (a, b)| {
+                    (correlation + a * b, energy + b * b)
+                });
+            (start, correlation / (energy + f32::EPSILON).sqrt())
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(target.min(max), |(start, _)| start)
+}
+
+/// Read `ratio` times faster, with linear interpolation
+fn resample(samples: &[f32], channels: usize, ratio: f32) -> Vec<f32> {
+    let frames = samples.len() / channels;
+    if frames == 0 {
+        return vec![];
+    }
+
+    let out_frames = (frames as f64 / ratio as f64) as usize;
+    let mut out = Vec::with_capacity(out_frames * channels);
+    for i in 0..out_frames {
+        let pos = i as f64 * ratio as f64;
+        let idx = (pos as usize).min(frames - 1);
+        let next = (idx + 1).min(frames - 1);
+        let frac = (pos - idx as f64) as f32;
+        for c in 0..channels {
+            let a = samples[idx * channels + c];
+            let b = samples[next * channels + c];
+            out.push(a + (b - a) * frac);
+        }
+    }
+
+    out
+}
+
+/// The whole line is lowered when it would clip
+pub fn gain(samples: &mut [f32], volume: f32) {
+    let peak = samples
+        .iter()
+        .fold(0f32, |peak, sample| peak.max(sample.abs()));
+    let factor = 10f32.powf(volume / 20.);
+    let factor = if peak * factor > 1. {
+        1. / peak
+    } else {
+        factor
+    };
+
+    for sample in samples {
+        *sample *= factor;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    fn audio() -> AudioSettings {
+        AudioSettings {
+            sample_rate: RATE,
+            channels: 2,
+            bitrate: 160,
+        }
+    }
+
+    /// `seconds` of a stereo 220 Hz sine
+    fn sine(amplitude: f32, seconds: f32) -> Vec<f32> {
+        let frames = (RATE as f32 * seconds) as usize;
+        (0..frames)
+            .flat_map(|frame| {
+                let phase = std::f32::consts::TAU * 220. * frame as f32 / RATE as f32;
+                [amplitude * phase.sin(); 2]
+            })
+            .collect()
+    }
+
+    fn effects(speed: f32, pitch: f32, volume: f32) -> Effects {
+        Effects {
+            speed,
+            pitch,
+            volume,
+        }
+    }
+
+    /// Sign changes of the left channel per second, twice the frequency
+    fn crossings_per_second(samples: &[f32]) -> f32 {
+        let left = samples.iter().step_by(2).collect::<Vec<_>>();
+        let crossings = left
+            .windows(2)
+            .filter(|pair| (*pair[0] < 0.) != (*pair[1] < 0.))
+            .count();
+        crossings as f32 * RATE as f32 / left.len() as f32
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples
+            .iter()
+            .fold(0., |peak, sample| peak.max(sample.abs()))
+    }
+
+    #[test]
+    fn neutral_is_identity() {
+        let samples = sine(0.5, 1.);
+        assert_eq!(
+            effects(1., 1., 0.).apply(samples.clone(), &audio()),
+            samples
+        );
+        // Too small to be worth it
+        assert_eq!(
+            effects(1.004, 0.996, 0.005).apply(samples.clone(), &audio()),
+            samples
+        );
+    }
+
+    #[test]
+    fn length_follows_speed() {
+        let samples = sine(0.5, 2.);
+        let frames = samples.len() as f32 / 2.;
+        // The last window may be left out
+        let slack = RATE as f32 * (WINDOW_SECONDS + SEEK_SECONDS);
+
+        for speed in [0.5, 0.75, 1.25, 2.] {
+            let out = effects(speed, 1., 0.).apply(samples.clone(), &audio());
+            assert_eq!(out.len() % 2, 0);
+            let out_frames = out.len() as f32 / 2.;
+            assert!(out_frames <= frames / speed, "{speed}");
+            assert!(out_frames > frames / speed - slack, "{speed}");
+            // Same pitch, WSOLA only moves windows around
+            let crossings = crossings_per_second(&out);
+            assert!((crossings / 440. - 1.).abs() < 0.05, "{speed}: {crossings}");
+        }
+    }
+
+    #[test]
+    fn pitch_keeps_the_length() {
+        let samples = sine(0.5, 2.);
+        let out = effects(1., 1.25, 0.).apply(samples.clone(), &audio());
+
+        let frames = samples.len() as f32 / 2.;
+        let slack = RATE as f32 * (WINDOW_SECONDS + SEEK_SECONDS);
+        assert!((out.len() as f32 / 2. - frames).abs() < slack * 1.25);
+        let crossings = crossings_per_second(&out);
+        assert!((crossings / (440. * 1.25) - 1.).abs() < 0.05, "{crossings}");
+    }
+
+    #[test]
+    fn short_lines_are_not_stretched() {
+        let samples = sine(0.5, 0.01);
+        assert_eq!(time_stretch(&samples, 2, RATE, 1.5), samples);
+        assert!(resample(&[], 2, 1.5).is_empty());
+    }
+
+    #[test]
+    fn gain_never_clips() {
+        let mut samples = sine(0.5, 0.1);
+        gain(&mut samples, 20.);
+        assert!((peak(&samples) - 1.).abs() < 1e-6);
+
+        let mut samples = sine(0.5, 0.1);
+        gain(&mut samples, -6.);
+        assert!((peak(&samples) - 0.5 * 10f32.powf(-6. / 20.)).abs() < 1e-6);
+
+        for volume in [0., 6., 40.] {
+            let out = effects(1.5, 0.8, volume).apply(sine(0.9, 0.5), &audio());
+            assert!(peak(&out) <= 1., "{volume}");
+        }
+        let mut silence = vec![0.; 100];
+        gain(&mut silence, 40.);
+        assert_eq!(silence, [0.; 100]);
+    }
+}
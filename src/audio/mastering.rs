@@ -0,0 +1,292 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+
+use super::effects::gain;
+use crate::encoder::AudioSettings;
+
+/// Integrated loudness targets, in LUFS. Streams are usually -14 to -16.
+pub const MIN_LOUDNESS: f32 = -40.0;
+pub const MAX_LOUDNESS: f32 = -5.0;
+/// Longest silence after a chunk
+pub const MAX_PAUSE_SECONDS: f32 = 10.0;
+
+/// Quieter frames are silence
+const SILENCE_DB: f32 = -50.0;
+/// Silence left at both ends of the speech so it does not start abruptly
+const SILENCE_PADDING_SECONDS: f32 = 0.03;
+/// Gating blocks of BS.1770, 400ms every 100ms
+const BLOCK_SECONDS: f64 = 0.4;
+const BLOCK_STEP_SECONDS: f64 = 0.1;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Evens out the synthesized speech, every response of the engine comes
+/// with its own level and silence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mastering {
+    /// Integrated loudness target, `None` keeps the level of the engine
+    pub loudness: Option<f32>,
+    pub sentence_pause: Duration,
+    pub paragraph_pause: Duration,
+    pub chapter_pause: Duration,
+}
+
+/// What ends with the chunk, it picks the silence after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pause {
+    Sentence,
+    Paragraph,
+    Chapter,
+}
+
+impl Mastering {
+    /// Trim the silence and normalize the loudness of `samples`
+    pub fn apply(&self, samples: Vec<f32>, audio: &AudioSettings) -> Vec<f32> {
+        let channels = audio.channels as usize;
+        let mut samples = trim_silence(samples, channels, audio.sample_rate);
+
+        let measured = self
+            .loudness
+            .and_then(|target| Some((target, loudness(&samples, channels, audio.sample_rate)?)));
+        if let Some((target, measured)) = measured {
+            gain(&mut samples, target - measured);
+        }
+
+        samples
+    }
+
+    /// Always the same silence for the same `pause`
+    pub fn pause(&self, pause: Pause, audio: &AudioSettings) -> Vec<f32> {
+        let duration = match pause {
+            Pause::Sentence => self.sentence_pause,
+            Pause::Paragraph => self.paragraph_pause,
+            Pause::Chapter => self.chapter_pause,
+        };
+        let frames = (duration.as_secs_f64() * audio.sample_rate as f64).round() as usize;
+        vec![0.; frames * audio.channels as usize]
+    }
+}
+
+/// Without the silence at both ends, but [`SILENCE_PADDING_SECONDS`]
+fn trim_silence(mut samples: Vec<f32>, channels: usize, rate: u32) -> Vec<f32> {
+    let threshold = 10f32.powf(SILENCE_DB / 20.);
+    let is_sound = |frame: &[f32]| frame.iter().any(|sample| sample.abs() > threshold);
+
+    let mut frames = samples.chunks_exact(channels);
+    let Some(first) = frames.position(is_sound) else {
+        return vec![];
+    };
+    let last = samples
+        .chunks_exact(channels)
+        .rposition(is_sound)
+        .unwrap_or(first);
+
+    let padding = (rate as f32 * SILENCE_PADDING_SECONDS) as usize;
+    let start = first.saturating_sub(padding);
+    let end = (last + 1 + padding).min(samples.len() / channels);
+    samples.truncate(end * channels);
+    samples.drain(..start * channels);
+    samples
+}
+
+/// Integrated loudness in LUFS (EBU R128 / ITU-R BS.1770), `None` for
+/// silence
+fn loudness(samples: &[f32], channels: usize, rate: u32) -> Option<f32> {
+    let frames = samples.len() / channels;
+    if frames == 0 {
+        return None;
+    }
+
+    // Squared K-weighted samples of every frame, added over the channels
+    let mut power = vec![0.; frames];
+    for channel in 0..channels {
+        let mut shelf = Biquad::shelf(rate as f64);
+        let mut high_pass = Biquad::high_pass(rate as f64);
+        for (frame, power) in power.iter_mut().enumerate() {
+            let sample = samples[frame * channels + channel] as f64;
+            let weighted = high_pass.process(shelf.process(sample));
+            *power += weighted * weighted;
+        }
+    }
+
+    // Shorter speech is a single block
+    let block = ((BLOCK_SECONDS * rate as f64) as usize).clamp(1, frames);
+    let step = ((BLOCK_STEP_SECONDS * rate as f64) as usize).max(1);
+    let blocks = (0..=frames - block)
+        .step_by(step)
+        .map(|start| power[start..start + block].iter().sum::<f64>() / block as f64)
+        .collect::<Vec<_>>();
+
+    let lufs = |power: f64| -0.691 + 10. * power.log10();
+    let gated_mean = |gate: f64| {
+        let gated = blocks
+            .iter()
+            .filter(|power| lufs(**power) > gate)
+            .collect::<Vec<_>>();
+        (!gated.is_empty()).then(|| gated.iter().copied().sum::<f64>() / gated.len() as f64)
+    };
+
+    let relative_gate = lufs(gated_mean(ABSOLUTE_GATE_LUFS)?) + RELATIVE_GATE_LU;
+    let integrated = gated_mean(relative_gate.max(ABSOLUTE_GATE_LUFS))?;
+    Some(lufs(integrated) as f32)
+}
+
+/// The two stages of the K-weighting filter, for any sample rate
+#[derive(Debug)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    /// Head effects, +4dB above ~1.7kHz
+    fn shelf(rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1. + k / q + k * k;
+
+        Self::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2. * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+        )
+    }
+
+    /// RLB weighting, cuts below ~40Hz
+    fn high_pass(rate: f64) -> Self {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1. + k / q + k * k;
+
+        Self::new(
+            [1., -2., 1.],
+            [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+        )
+    }
+
+    fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.; 2],
+            y: [0.; 2],
+        }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48000;
+
+    fn audio(channels: u32) -> AudioSettings {
+        AudioSettings {
+            sample_rate: RATE,
+            channels,
+            bitrate: 160,
+        }
+    }
+
+    /// `seconds` of a 997 Hz sine at `amplitude`, the same in every channel
+    fn sine(amplitude: f32, seconds: f32, channels: usize) -> Vec<f32> {
+        let frames = (RATE as f32 * seconds) as usize;
+        (0..frames)
+            .flat_map(|frame| {
+                let phase = 2. * PI * 997. * frame as f64 / RATE as f64;
+                vec![amplitude * phase.sin() as f32; channels]
+            })
+            .collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples
+            .iter()
+            .fold(0., |peak, sample| peak.max(sample.abs()))
+    }
+
+    fn mastering(loudness: f32) -> Mastering {
+        Mastering {
+            loudness: Some(loudness),
+            sentence_pause: Duration::ZERO,
+            paragraph_pause: Duration::ZERO,
+            chapter_pause: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn loudness_of_a_sine() {
+        // BS.1770: a 0 dBFS sine in one channel reads -3.01 LKFS
+        let measured = loudness(&sine(1., 3., 1), 1, RATE).unwrap();
+        assert!((measured + 3.01).abs() < 0.05, "{measured}");
+        let measured = loudness(&sine(0.1, 3., 1), 1, RATE).unwrap();
+        assert!((measured + 23.01).abs() < 0.05, "{measured}");
+        // Channels add up
+        let measured = loudness(&sine(0.1, 3., 2), 2, RATE).unwrap();
+        assert!((measured + 20.).abs() < 0.05, "{measured}");
+
+        assert_eq!(loudness(&[], 2, RATE), None);
+        assert_eq!(loudness(&[0.; 4800], 1, RATE), None);
+    }
+
+    #[test]
+    fn normalize_loudness() {
+        let samples = mastering(-16.).apply(sine(0.05, 2., 2), &audio(2));
+        let measured = loudness(&samples, 2, RATE).unwrap();
+        assert!((measured + 16.).abs() < 0.1, "{measured}");
+    }
+
+    #[test]
+    fn gain_is_peak_limited() {
+        // A click makes the peak, the quiet sine the loudness
+        let mut samples = sine(0.05, 2., 1);
+        samples[RATE as usize] = 0.9;
+        let samples = mastering(MAX_LOUDNESS).apply(samples, &audio(1));
+
+        assert!((peak(&samples) - 1.).abs() < 1e-6, "{}", peak(&samples));
+        assert!(loudness(&samples, 1, RATE).unwrap() < MAX_LOUDNESS);
+    }
+
+    #[test]
+    fn trim_silence_keeps_padding() {
+        let padding = (RATE as f32 * SILENCE_PADDING_SECONDS) as usize;
+        let mut samples = vec![0.; RATE as usize];
+        samples.extend([0.5; 1000]);
+        samples.extend(vec![0.; RATE as usize]);
+
+        let trimmed = trim_silence(samples, 1, RATE);
+        assert_eq!(trimmed.len(), 1000 + 2 * padding);
+        assert_eq!(trimmed[padding - 1], 0.);
+        assert_eq!(trimmed[padding], 0.5);
+        assert_eq!(trimmed[padding + 999], 0.5);
+
+        // No room for the padding, stereo frames are kept whole
+        let mut samples = vec![0.5; 20];
+        samples.extend([0.; 10]);
+        samples.extend([0., 0.5]);
+        assert_eq!(trim_silence(samples.clone(), 2, RATE), samples);
+
+        assert!(trim_silence(vec![0.001; 1000], 2, RATE).is_empty());
+    }
+}
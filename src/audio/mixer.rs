@@ -0,0 +1,205 @@
+use super::{AudioQueue, Music};
+use crate::encoder::AudioSettings;
+
+/// Louder speech ducks the music
+const SPEECH_DB: f32 = -45.0;
+/// The music stays down this long after the speech, so it does not pump
+/// between sentences but comes back in longer pauses
+const HOLD_SECONDS: f32 = 0.5;
+/// Time to go most of the way down and back up
+const ATTACK_SECONDS: f32 = 0.05;
+const RELEASE_SECONDS: f32 = 0.6;
+
+/// Last stage before the sink: the speech and the sounds, with the music
+/// under them
+#[derive(Debug)]
+pub struct Mixer {
+    speech: AudioQueue,
+    sounds: AudioQueue,
+    music: Option<Music>,
+    channels: usize,
+    threshold: f32,
+    hold_frames: usize,
+    attack: f32,
+    release: f32,
+    /// Current gain of the music and frames it stays ducked
+    gain: f32,
+    hold: usize,
+    buffer: Vec<f32>,
+}
+
+impl Mixer {
+    pub fn new(
+        speech: AudioQueue,
+        sounds: AudioQueue,
+        music: Option<Music>,
+        audio: &AudioSettings,
+    ) -> Self {
+        let rate = audio.sample_rate as f32;
+        let coefficient = |seconds: f32| 1. - (-1. / (seconds * rate)).exp();
+
+        Self {
+            speech,
+            sounds,
+            gain: music.as_ref().map_or(0., |music| amplitude(music.volume)),
+            music,
+            channels: audio.channels as usize,
+            threshold: amplitude(SPEECH_DB),
+            hold_frames: (HOLD_SECONDS * rate) as usize,
+            attack: coefficient(ATTACK_SECONDS),
+            release: coefficient(RELEASE_SECONDS),
+            hold: 0,
+            buffer: vec![],
+        }
+    }
+
+    /// Fill `out` with the next samples, silence when there is nothing to
+    /// play
+    pub fn pull(&mut self, out: &mut [f32]) {
+        self.speech.pull(out, !self.speech.is_idle());
+        self.buffer.resize(out.len(), 0.);
+        if !self.sounds.is_empty() {
+            self.sounds.pull(&mut self.buffer, false);
+            for (sample, sound) in out.iter_mut().zip(&self.buffer) {
+                *sample = (*sample + sound).clamp(-1., 1.);
+            }
+        }
+
+        let Some(music) = &self.music else {
+            return;
+        };
+        music.queue.pull(&mut self.buffer, false);
+        let full = amplitude(music.volume);
+        let ducked = amplitude(music.volume - music.duck);
+
+        for (frame, music) in out
+            .chunks_exact_mut(self.channels)
+            .zip(self.buffer.chunks_exact(self.channels))
+        {
+            if frame.iter().any(|sample| sample.abs() > self.threshold) {
+                self.hold = self.hold_frames;
+            } else {
+                self.hold = self.hold.saturating_sub(1);
+            }
+
+            let (target, coefficient) = if self.hold > 0 {
+                (ducked, self.attack)
+            } else {
+                (full, self.release)
+            };
+            self.gain += (target - self.gain) * coefficient;
+
+            for (sample, music) in frame.iter_mut().zip(music) {
+                *sample = (*sample + music * self.gain).clamp(-1., 1.);
+            }
+        }
+    }
+}
+
+fn amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::EncoderConfig;
+
+    /// 10 ms of stereo
+    const PULL: usize = 960;
+
+    fn queue(samples: &[f32]) -> AudioQueue {
+        let queue = AudioQueue::new(&EncoderConfig::default().audio);
+        queue.push(samples);
+        queue
+    }
+
+    fn seconds(seconds: f32, sample: f32) -> Vec<f32> {
+        let audio = EncoderConfig::default().audio;
+        vec![sample; ((audio.sample_rate * audio.channels) as f32 * seconds) as usize]
+    }
+
+    fn music(samples: &[f32]) -> Music {
+        Music {
+            queue: queue(samples),
+            volume: -6.,
+            duck: 12.,
+        }
+    }
+
+    fn mixer(speech: &[f32], sounds: &[f32], music: Option<Music>) -> Mixer {
+        Mixer::new(
+            queue(speech),
+            queue(sounds),
+            music,
+            &EncoderConfig::default().audio,
+        )
+    }
+
+    /// Pull for `seconds`, returns the last pull
+    fn play(mixer: &mut Mixer, seconds: f32) -> Vec<f32> {
+        let mut out = vec![0.; PULL];
+        for _ in 0..(seconds * 100.).round() as usize {
+            mixer.pull(&mut out);
+        }
+        out
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < b * 0.05
+    }
+
+    #[test]
+    fn speech_ducks_the_music() {
+        let (full, ducked) = (amplitude(-6.), amplitude(-18.));
+        let mut mixer = mixer(&seconds(0.5, 0.1), &[], Some(music(&seconds(4., 0.25))));
+        assert_eq!(mixer.gain, full);
+
+        let out = play(&mut mixer, 0.3);
+        assert!(close(mixer.gain, ducked), "{}", mixer.gain);
+        assert!(close(out[0], 0.1 + 0.25 * ducked), "{}", out[0]);
+
+        // Held after the speech, so pauses between sentences stay down
+        let out = play(&mut mixer, 0.6);
+        assert!(close(mixer.gain, ducked), "{}", mixer.gain);
+        assert!(close(out[0], 0.25 * ducked), "{}", out[0]);
+
+        // Then released
+        let out = play(&mut mixer, 2.5);
+        assert!(close(mixer.gain, full), "{}", mixer.gain);
+        assert!(close(out[0], 0.25 * full), "{}", out[0]);
+    }
+
+    #[test]
+    fn quiet_speech_does_not_duck() {
+        let mut mixer = mixer(&seconds(0.5, 0.001), &[], Some(music(&seconds(1., 0.25))));
+        play(&mut mixer, 0.5);
+        assert_eq!(mixer.gain, amplitude(-6.));
+    }
+
+    #[test]
+    fn empty_queues() {
+        // Silence, whatever was in `out`
+        let mut out = vec![1.; PULL];
+        mixer(&[], &[], None).pull(&mut out);
+        assert!(out.iter().all(|sample| *sample == 0.));
+
+        // Sounds alone, and over the speech without clipping
+        let mut out = vec![1.; 4];
+        mixer(&[], &[0.3, -0.3], None).pull(&mut out);
+        assert_eq!(out, [0.3, -0.3, 0., 0.]);
+        mixer(&[0.8, -0.8, 0.2], &[0.5, -0.5], None).pull(&mut out);
+        assert_eq!(out, [1., -1., 0.2, 0.]);
+
+        // Music alone, not ducked
+        let mut out = vec![1.; 4];
+        mixer(&[], &[], Some(music(&[0.5; 4]))).pull(&mut out);
+        assert!(out
+            .iter()
+            .all(|sample| close(*sample, 0.5 * amplitude(-6.))));
+
+        // Music that ran out
+        mixer(&[0.2; 4], &[], Some(music(&[]))).pull(&mut out);
+        assert_eq!(out, [0.2; 4]);
+    }
+}
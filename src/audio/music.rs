@@ -0,0 +1,98 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info, warn};
+
+use super::{decode, AudioQueue};
+use crate::encoder::AudioSettings;
+use crate::shutdown::Shutdown;
+use crate::AUDIO_LOG;
+
+/// Level of the music, and how much lower it can go under the speech
+pub const MIN_MUSIC_VOLUME: f32 = -60.0;
+pub const MAX_MUSIC_VOLUME: f32 = 0.0;
+pub const MAX_DUCK: f32 = 40.0;
+
+/// Music left in the queue when the next track is decoded
+const QUEUE_LOW_SECONDS: f32 = 5.0;
+const IDLE_WAIT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone)]
+pub struct MusicConfig {
+    /// Played in order, over and over
+    pub tracks: Vec<PathBuf>,
+    /// Level of the music in dB, while nobody speaks
+    pub volume: f32,
+    /// How much lower it goes under the speech, in dB
+    pub duck: f32,
+}
+
+impl fmt::Display for MusicConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} tracks {:+.1} dB, ducked {:.1} dB",
+            self.tracks.len(),
+            self.volume,
+            self.duck
+        )
+    }
+}
+
+/// Decoded music waiting for the mixer, with its levels
+#[derive(Debug, Clone)]
+pub struct Music {
+    pub queue: AudioQueue,
+    pub volume: f32,
+    pub duck: f32,
+}
+
+/// Decode the tracks of `config` one after the other, a few seconds ahead
+/// of the mixer. Tracks that cannot be decoded are skipped, the music stops
+/// when none can.
+pub fn play_music(config: MusicConfig, audio: AudioSettings, shutdown: Shutdown) -> Music {
+    let music = Music {
+        queue: AudioQueue::new(&audio),
+        volume: config.volume,
+        duck: config.duck,
+    };
+    let queue = music.queue.clone();
+    let low_mark = ((audio.sample_rate * audio.channels) as f32 * QUEUE_LOW_SECONDS) as usize;
+
+    thread::spawn(move || {
+        let mut failed = 0;
+        for path in config.tracks.iter().cycle() {
+            while queue.len() > low_mark && !shutdown.is_requested() {
+                thread::sleep(IDLE_WAIT);
+            }
+            if shutdown.is_requested() {
+                break;
+            }
+
+            let samples = fs::read(path)
+                .map_err(|err| err.to_string())
+                .and_then(|encoded| decode(encoded, &audio).map_err(|err| err.to_string()));
+            match samples {
+                Ok(samples) if !samples.is_empty() => {
+                    info!(target: AUDIO_LOG, "Queued track {}", path.display());
+                    queue.push(&samples);
+                    failed = 0;
+                    continue;
+                }
+                Ok(_) => warn!(target: AUDIO_LOG, "Skipping {}: no audio", path.display()),
+                Err(err) => warn!(target: AUDIO_LOG, "Skipping {}: {err}", path.display()),
+            }
+
+            failed += 1;
+            if failed >= config.tracks.len() {
+                error!(target: AUDIO_LOG, "No music can be played");
+                break;
+            }
+        }
+    });
+
+    music
+}
@@ -0,0 +1,269 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::encoder::AudioSettings;
+
+/// Longest audio the queue holds, a long sentence has to fit
+const CAPACITY_SECONDS: f32 = 60.0;
+/// Wait for the sink to make room
+const FULL_WAIT: Duration = Duration::from_millis(10);
+
+/// Interleaved f32 samples waiting to be played, at the encoder sample
+/// rate. A ring buffer without locks, for a single thread pushing and the
+/// sink pulling.
+#[derive(Debug, Clone)]
+pub struct AudioQueue {
+    ring: Arc<Ring>,
+}
+
+#[derive(Debug)]
+struct Ring {
+    /// The bits of every sample, atomics so no lock is needed
+    samples: Box<[AtomicU32]>,
+    /// Samples ever pushed and pulled, the position in the ring is modulo
+    /// its length
+    pushed: AtomicU64,
+    pulled: AtomicU64,
+    /// Everything pushed before it is dropped instead of played
+    discarded: AtomicU64,
+    /// Pulls that expected samples and did not get them all, and the
+    /// silence they filled
+    underruns: AtomicU64,
+    starved: AtomicU64,
+    /// Nothing is going to be pushed soon, like while paused
+    idle: AtomicBool,
+}
+
+impl AudioQueue {
+    pub fn new(audio: &AudioSettings) -> Self {
+        Self::with_seconds(audio, CAPACITY_SECONDS)
+    }
+
+    pub fn with_seconds(audio: &AudioSettings, seconds: f32) -> Self {
+        let capacity = ((audio.sample_rate * audio.channels) as f32 * seconds) as usize;
+
+        Self {
+            ring: Arc::new(Ring {
+                samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+                pushed: AtomicU64::new(0),
+                pulled: AtomicU64::new(0),
+                discarded: AtomicU64::new(0),
+                underruns: AtomicU64::new(0),
+                starved: AtomicU64::new(0),
+                idle: AtomicBool::new(true),
+            }),
+        }
+    }
+
+    /// Waits for the sink when the queue is full
+    pub fn push(&self, mut samples: &[f32]) {
+        while !samples.is_empty() {
+            let free = self.free();
+            if free == 0 {
+                thread::sleep(FULL_WAIT);
+                continue;
+            }
+
+            let (now, later) = samples.split_at(samples.len().min(free));
+            self.write(now);
+            samples = later;
+        }
+    }
+
+    /// Push all of `samples` right away, or nothing when they do not fit
+    pub fn try_push(&self, samples: &[f32]) -> bool {
+        if samples.len() > self.free() {
+            return false;
+        }
+
+        self.write(samples);
+        true
+    }
+
+    fn free(&self) -> usize {
+        let ring = &self.ring;
+        let queued = ring.pushed.load(Ordering::Relaxed) - ring.pulled.load(Ordering::Acquire);
+        ring.samples.len() - queued as usize
+    }
+
+    /// `samples` must fit
+    fn write(&self, samples: &[f32]) {
+        let ring = &self.ring;
+        let pushed = ring.pushed.load(Ordering::Relaxed);
+        for (i, sample) in samples.iter().enumerate() {
+            ring.slot(pushed + i as u64)
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+        ring.idle.store(false, Ordering::Relaxed);
+        ring.pushed
+            .store(pushed + samples.len() as u64, Ordering::Release);
+    }
+
+    /// Fill `out` with the next samples. Silence is only filled when there
+    /// are not enough, and counted as an underrun when `expecting` them.
+    pub fn pull(&self, out: &mut [f32], expecting: bool) {
+        let ring = &self.ring;
+        let pushed = ring.pushed.load(Ordering::Acquire);
+        let pulled = ring
+            .pulled
+            .load(Ordering::Relaxed)
+            .max(ring.discarded.load(Ordering::Acquire));
+
+        let available = (pushed - pulled).min(out.len() as u64) as usize;
+        for (i, sample) in out[..available].iter_mut().enumerate() {
+            *sample = f32::from_bits(ring.slot(pulled + i as u64).load(Ordering::Relaxed));
+        }
+        out[available..].fill(0.);
+        ring.pulled
+            .store(pulled + available as u64, Ordering::Release);
+
+        if expecting && available < out.len() {
+            ring.underruns.fetch_add(1, Ordering::Relaxed);
+            ring.starved
+                .fetch_add((out.len() - available) as u64, Ordering::Relaxed);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pushed().saturating_sub(self.played()) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Samples ever pushed, the position of the next one
+    pub fn pushed(&self) -> u64 {
+        self.ring.pushed.load(Ordering::Acquire)
+    }
+
+    /// Samples ever played or dropped
+    pub fn played(&self) -> u64 {
+        self.ring
+            .pulled
+            .load(Ordering::Acquire)
+            .max(self.ring.discarded.load(Ordering::Acquire))
+    }
+
+    /// Drop everything queued, cutting the sentence being played. Samples
+    /// pushed afterwards are played.
+    pub fn clear(&self) {
+        let pushed = self.ring.pushed.load(Ordering::Acquire);
+        self.ring.discarded.fetch_max(pushed, Ordering::AcqRel);
+    }
+
+    /// No more samples are coming for now, pulls finding the queue dry are
+    /// not underruns. Pushing ends it.
+    pub fn set_idle(&self) {
+        self.ring.idle.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.ring.idle.load(Ordering::Relaxed)
+    }
+
+    /// Pulls that expected samples and ran dry. Reading pushes the next
+    /// sentence well before, so only a slow TTS or a cut should count.
+    pub fn underruns(&self) -> u64 {
+        self.ring.underruns.load(Ordering::Relaxed)
+    }
+
+    /// Samples of silence filled by the underruns
+    pub fn starved(&self) -> u64 {
+        self.ring.starved.load(Ordering::Relaxed)
+    }
+}
+
+impl Ring {
+    fn slot(&self, position: u64) -> &AtomicU32 {
+        &self.samples[(position % self.samples.len() as u64) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::EncoderConfig;
+
+    fn queue() -> AudioQueue {
+        AudioQueue::with_seconds(&EncoderConfig::default().audio, 0.001)
+    }
+
+    #[test]
+    fn push_and_pull() {
+        let queue = queue();
+        queue.push(&[0.1, 0.2, 0.3]);
+        assert_eq!(queue.len(), 3);
+
+        let mut out = [1.; 2];
+        queue.pull(&mut out, true);
+        assert_eq!(out, [0.1, 0.2]);
+        queue.clear();
+        assert!(queue.is_empty());
+
+        queue.push(&[0.4]);
+        queue.pull(&mut out, false);
+        assert_eq!(out, [0.4, 0.]);
+        assert_eq!((queue.pushed(), queue.played()), (4, 4));
+    }
+
+    #[test]
+    fn try_push_all_or_nothing() {
+        let queue = queue();
+        let capacity = queue.ring.samples.len();
+
+        assert!(queue.try_push(&vec![0.1; capacity - 1]));
+        assert!(!queue.try_push(&[0.2, 0.2]));
+        assert_eq!(queue.len(), capacity - 1);
+        assert!(queue.try_push(&[0.3]));
+        assert!(!queue.try_push(&[0.4]));
+
+        let mut out = vec![0.; capacity];
+        queue.pull(&mut out, true);
+        assert_eq!(out[capacity - 1], 0.3);
+        assert!(queue.try_push(&[0.4]));
+    }
+
+    #[test]
+    fn wraps_around() {
+        let queue = queue();
+        let capacity = queue.ring.samples.len();
+        let samples = (0..capacity).map(|i| i as f32).collect::<Vec<_>>();
+        let mut out = vec![0.; capacity];
+
+        for _ in 0..3 {
+            queue.push(&samples[..capacity / 2]);
+            queue.push(&samples[capacity / 2..]);
+            queue.pull(&mut out, true);
+            assert_eq!(out, samples);
+        }
+    }
+
+    #[test]
+    fn underruns_only_when_expecting() {
+        let queue = queue();
+        let mut out = [0.; 4];
+
+        // Nothing pushed yet
+        queue.pull(&mut out, !queue.is_idle());
+        assert_eq!(queue.underruns(), 0);
+
+        queue.push(&[0.1, 0.2]);
+        queue.pull(&mut out, !queue.is_idle());
+        assert_eq!((queue.underruns(), queue.starved()), (1, 2));
+        // Dry, but the next sentence is late
+        queue.pull(&mut out, !queue.is_idle());
+        assert_eq!((queue.underruns(), queue.starved()), (2, 6));
+
+        queue.set_idle();
+        queue.pull(&mut out, !queue.is_idle());
+        queue.pull(&mut out, false);
+        assert_eq!(queue.underruns(), 2);
+
+        queue.push(&[0.1; 4]);
+        queue.pull(&mut out, true);
+        assert_eq!(queue.underruns(), 2);
+    }
+}
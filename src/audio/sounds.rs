@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use log::{debug, info, warn};
+
+use super::{decode, AudioQueue};
+use crate::encoder::AudioSettings;
+use crate::render::Theme;
+use crate::AUDIO_LOG;
+
+/// Sounds are short, they seldom overlap
+const QUEUE_SECONDS: f32 = 10.0;
+
+/// Events with a sound, the file in the sounds directory is named after
+/// them with any extension, like `chapter.ogg`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sound {
+    /// Before the first sentence of every chapter
+    Chapter,
+    /// After the last sentence of a book
+    BookEnd,
+    Poll,
+    /// A chat command was accepted
+    Command,
+}
+
+impl Sound {
+    const ALL: [Self; 4] = [Self::Chapter, Self::BookEnd, Self::Poll, Self::Command];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Chapter => "chapter",
+            Self::BookEnd => "book_end",
+            Self::Poll => "poll",
+            Self::Command => "command",
+        }
+    }
+}
+
+/// Decoded sounds of the theme. They are read again in the background when
+/// the theme is reloaded, the old ones are used meanwhile.
+#[derive(Debug, Clone)]
+pub struct Sounds {
+    dir: Option<PathBuf>,
+    audio: AudioSettings,
+    theme: Theme,
+    /// Theme generation they were read at
+    clips: Arc<Mutex<(u64, HashMap<Sound, Arc<[f32]>>)>>,
+    reloading: Arc<AtomicBool>,
+    /// Played over the narration as soon as they happen
+    queue: AudioQueue,
+    /// The queue takes a single writer
+    writer: Arc<Mutex<()>>,
+}
+
+impl Sounds {
+    pub fn new(dir: Option<PathBuf>, audio: AudioSettings, theme: Theme) -> Self {
+        let clips = (theme.generation(), load_clips(dir.as_deref(), &audio));
+
+        Self {
+            dir,
+            queue: AudioQueue::with_seconds(&audio, QUEUE_SECONDS),
+            audio,
+            theme,
+            clips: Arc::new(Mutex::new(clips)),
+            reloading: Arc::default(),
+            writer: Arc::default(),
+        }
+    }
+
+    /// Samples of `sound`, to be placed in the narration
+    pub fn clip(&self, sound: Sound) -> Option<Arc<[f32]>> {
+        let clips = self.clips.lock().unwrap();
+        self.reload_if_stale(clips.0);
+        clips.1.get(&sound).cloned()
+    }
+
+    /// Mix `sound` in right away. Never waits: it is dropped when the sounds
+    /// are busy, another one is being queued or there is no room left for it.
+    pub fn play(&self, sound: Sound) {
+        let Ok(clips) = self.clips.try_lock() else {
+            debug!(target: AUDIO_LOG, "Sound {} dropped, the sounds are busy", sound.name());
+            return;
+        };
+        self.reload_if_stale(clips.0);
+        let Some(clip) = clips.1.get(&sound).cloned() else {
+            return;
+        };
+        drop(clips);
+
+        let Ok(_writer) = self.writer.try_lock() else {
+            debug!(target: AUDIO_LOG, "Sound {} dropped, another one is queued", sound.name());
+            return;
+        };
+        if !self.queue.try_push(&clip) {
+            debug!(target: AUDIO_LOG, "Sound {} dropped, the queue is full", sound.name());
+        }
+    }
+
+    /// What [`Sounds::play`] queues, for the mixer
+    pub fn queue(&self) -> AudioQueue {
+        self.queue.clone()
+    }
+
+    /// Decoding takes a while, it is done on a thread of its own and the
+    /// new clips are swapped in when ready
+    fn reload_if_stale(&self, read_at: u64) {
+        let generation = self.theme.generation();
+        if read_at == generation || self.reloading.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let sounds = self.clone();
+        thread::spawn(move || {
+            let clips = load_clips(sounds.dir.as_deref(), &sounds.audio);
+            *sounds.clips.lock().unwrap() = (generation, clips);
+            sounds.reloading.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+/// The sounds found in `dir`, the missing ones are silent
+fn load_clips(dir: Option<&Path>, audio: &AudioSettings) -> HashMap<Sound, Arc<[f32]>> {
+    let Some(dir) = dir else {
+        return HashMap::new();
+    };
+    let files = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<_>>(),
+        Err(err) => {
+            warn!(target: AUDIO_LOG, "Cannot read sounds in {}: {err}", dir.display());
+            return HashMap::new();
+        }
+    };
+
+    let mut clips = HashMap::new();
+    for sound in Sound::ALL {
+        let Some(path) = files
+            .iter()
+            .find(|path| path.file_stem().is_some_and(|stem| stem == sound.name()))
+        else {
+            continue;
+        };
+
+        let samples = fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|encoded| decode(encoded, audio).map_err(|err| err.to_string()));
+        match samples {
+            Ok(samples) => {
+                clips.insert(sound, samples.into());
+            }
+            Err(err) => warn!(target: AUDIO_LOG, "Cannot load sound {}: {err}", path.display()),
+        }
+    }
+    info!(target: AUDIO_LOG, "{} sounds loaded from {}", clips.len(), dir.display());
+
+    clips
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::EncoderConfig;
+
+    #[test]
+    fn play_never_waits() {
+        let audio = EncoderConfig::default().audio;
+        let sounds = Sounds::new(None, audio, Theme::new(None));
+        let clip: Arc<[f32]> = vec![0.5; 100].into();
+        sounds.clips.lock().unwrap().1.insert(Sound::Poll, clip);
+
+        sounds.play(Sound::Poll);
+        sounds.play(Sound::Command);
+        assert_eq!(sounds.queue().len(), 100);
+
+        // Dropped while the clips or the queue are taken
+        let clips = sounds.clips.lock().unwrap();
+        sounds.play(Sound::Poll);
+        drop(clips);
+        let writer = sounds.writer.lock().unwrap();
+        sounds.play(Sound::Poll);
+        drop(writer);
+        assert_eq!(sounds.queue().len(), 100);
+
+        // And when it does not fit
+        let capacity = (audio.sample_rate * audio.channels) as f32 * QUEUE_SECONDS;
+        sounds.queue().push(&vec![0.; capacity as usize - 150]);
+        sounds.play(Sound::Poll);
+        assert_eq!(sounds.queue().len(), capacity as usize - 50);
+    }
+}
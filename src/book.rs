@@ -0,0 +1,255 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::dialogue::{Parser, Part};
+use crate::error::{EbookError, EbookResult};
+use crate::tts::{detect, detect_with_margin, tokenize, Languages, GOOGLE_TTS_MAX_CHARS};
+
+/// Longest paragraph considered a chapter title, "DON EUGENIO"
+const MAX_TITLE_LEN: usize = 60;
+/// A sentence only has a language of its own, like an English quote in a
+/// Spanish paragraph, when it is this clear. Short Spanish sentences come
+/// out as Catalan by less than 0.01, English ones by over 0.15.
+const SENTENCE_MARGIN: f32 = 0.1;
+
+#[derive(Debug, Clone)]
+pub struct Book {
+    pub title: String,
+    pub path: PathBuf,
+    pub chapters: Vec<Chapter>,
+    pub chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    /// Roman numeral of the chapter, `None` for the front matter
+    pub number: Option<String>,
+    pub title: String,
+    /// Index of the first chunk of the chapter in [`Book::chunks`]
+    pub first_chunk: usize,
+}
+
+/// The unit sent to the TTS engine, usually a sentence
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub text: String,
+    /// Index in [`Book::chapters`]
+    pub chapter: usize,
+    /// Index of the paragraph in the whole book
+    pub paragraph: usize,
+    /// Detected in the sentence, or in its paragraph, chapter or book when
+    /// it is too short to tell
+    pub language: Option<Languages>,
+    /// Narration and speech of the text, empty when it is all narration
+    pub dialogue: Vec<Part>,
+    /// Names in the narration of the paragraph, one of them is who speaks
+    pub speakers: Vec<String>,
+}
+
+impl fmt::Display for Chapter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.number, self.title.is_empty()) {
+            (Some(number), true) => write!(f, "{number}"),
+            (Some(number), false) => write!(f, "{number}. {}", self.title),
+            (None, _) => f.write_str(&self.title),
+        }
+    }
+}
+
+/// `book.txt` and `./book.txt` are the same book
+pub fn same_book(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+impl Book {
+    /// Open a plain text book. Paragraphs are separated by blank lines and
+    /// chapters start with a roman numeral alone, like `I.`, optionally
+    /// followed by an uppercase title.
+    pub fn open(path: &Path) -> EbookResult<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| EbookError::ReadBook(path.to_owned(), err.to_string()))?;
+
+        let title = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(Self::parse(title, path.to_owned(), &text))
+    }
+
+    pub fn parse(title: String, path: PathBuf, text: &str) -> Self {
+        let mut chapters = vec![Chapter {
+            number: None,
+            title: title.clone(),
+            first_chunk: 0,
+        }];
+        let mut chunks = vec![];
+        // To detect the language of every chapter
+        let mut chapter_texts = vec![String::new()];
+
+        let book_language = detect(text);
+
+        let mut paragraphs = paragraphs(text).into_iter().peekable();
+        let mut paragraph_idx = 0;
+
+        while let Some(paragraph) = paragraphs.next() {
+            if let Some(number) = chapter_number(&paragraph) {
+                let title = paragraphs
+                    .next_if(|next| is_title(next))
+                    .unwrap_or_default();
+
+                chapters.push(Chapter {
+                    number: Some(number),
+                    title,
+                    first_chunk: chunks.len(),
+                });
+                chapter_texts.push(String::new());
+
+                let chapter = chapters.last().unwrap();
+                chunks.push(Chunk {
+                    text: chapter.to_string(),
+                    chapter: chapters.len() - 1,
+                    paragraph: paragraph_idx,
+                    language: None,
+                    dialogue: vec![],
+                    speakers: vec![],
+                });
+                paragraph_idx += 1;
+                continue;
+            }
+
+            let paragraph_language = detect(&paragraph);
+            let first_chunk = chunks.len();
+            let mut parser = Parser::new(paragraph_language.or(book_language));
+            for sentence in tokenize(&paragraph, GOOGLE_TTS_MAX_CHARS) {
+                chunks.push(Chunk {
+                    language: detect_with_margin(&sentence, SENTENCE_MARGIN).or(paragraph_language),
+                    dialogue: parser.parse(&sentence),
+                    text: sentence,
+                    chapter: chapters.len() - 1,
+                    paragraph: paragraph_idx,
+                    speakers: vec![],
+                });
+            }
+            let speakers = parser.speakers();
+            for chunk in &mut chunks[first_chunk..] {
+                if !chunk.dialogue.is_empty() {
+                    chunk.speakers = speakers.clone();
+                }
+            }
+            paragraph_idx += 1;
+
+            let chapter_text = chapter_texts.last_mut().unwrap();
+            chapter_text.push_str(&paragraph);
+            chapter_text.push('\n');
+        }
+
+        let chapter_languages = chapter_texts
+            .iter()
+            .map(|text| detect(text).or(book_language))
+            .collect::<Vec<_>>();
+        for chunk in chunks.iter_mut().filter(|chunk| chunk.language.is_none()) {
+            chunk.language = chapter_languages[chunk.chapter];
+        }
+
+        Self {
+            title,
+            path,
+            chapters,
+            chunks,
+        }
+    }
+
+    /// Chapter of the chunk at `position`
+    pub fn chapter_at(&self, position: usize) -> Option<&Chapter> {
+        self.chunks
+            .get(position)
+            .and_then(|chunk| self.chapters.get(chunk.chapter))
+    }
+}
+
+/// Lines joined by spaces, paragraphs split at blank lines
+fn paragraphs(text: &str) -> Vec<String> {
+    text.split("\n\n")
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect()
+}
+
+/// `"IV."` -> `Some("IV")`
+fn chapter_number(paragraph: &str) -> Option<String> {
+    let number = paragraph.strip_suffix('.')?;
+    let is_roman = !number.is_empty()
+        && number
+            .chars()
+            .all(|c| matches!(c, 'I' | 'V' | 'X' | 'L' | 'C' | 'D' | 'M'));
+
+    is_roman.then(|| number.to_owned())
+}
+
+fn is_title(paragraph: &str) -> bool {
+    paragraph.len() <= MAX_TITLE_LEN && paragraph.to_uppercase() == paragraph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOOK: &str = include_str!("../book.txt");
+
+    fn parse(text: &str) -> Book {
+        Book::parse("book".to_owned(), PathBuf::from("book.txt"), text)
+    }
+
+    #[test]
+    fn spanish_book() {
+        let book = parse(BOOK);
+
+        assert_eq!(detect(BOOK), Some(Languages::Spanish));
+        assert!(book.chunks.len() > 20);
+        for chunk in &book.chunks {
+            assert_eq!(chunk.language, Some(Languages::Spanish), "{:?}", chunk.text);
+        }
+    }
+
+    #[test]
+    fn english_quote_in_spanish_paragraph() {
+        let text = format!(
+            "{BOOK}\n\nAviraneta se levantó despacio y dijo a los dos jóvenes que se marchaba \
+            aquella misma noche. I will never forget what you have done for me here, my friend. \
+            Luego salió de la fonda sin despedirse de nadie.\n"
+        );
+        let book = parse(&text);
+
+        let languages = book
+            .chunks
+            .iter()
+            .rev()
+            .take(3)
+            .map(|chunk| chunk.language)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            languages,
+            [
+                Some(Languages::Spanish),
+                Some(Languages::English),
+                Some(Languages::Spanish),
+            ]
+        );
+    }
+
+    #[test]
+    fn chapters() {
+        let book = parse(BOOK);
+
+        let chapter = &book.chapters[1];
+        assert_eq!(chapter.to_string(), "I. DON EUGENIO");
+        assert_eq!(book.chunks[chapter.first_chunk].text, "I. DON EUGENIO");
+        let next = book.chapter_at(chapter.first_chunk + 1).unwrap();
+        assert_eq!(next.first_chunk, chapter.first_chunk);
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use log::{info, warn};
+
+use crate::audio::AudioQueue;
+use crate::encoder::AudioSettings;
+use crate::error::{EbookError, EbookResult};
+use crate::render::SharedScene;
+
+/// Name of the `appsrc` element the embedded captions are pushed into
+pub const CAPTIONS_SRC_NAME: &str = "captions";
+
+#[derive(Debug, Clone, Default)]
+pub struct CaptionsConfig {
+    /// Drawn by the renderer at the bottom of the frame
+    pub burn_in: bool,
+    /// CEA-608 in the H.264 stream, needs a video preset
+    pub embed: bool,
+    /// Sidecar files, timed from the start of the stream. Every run writes
+    /// its own, numbered when the name is taken: `captions.1.srt`.
+    pub srt: Option<PathBuf>,
+    pub vtt: Option<PathBuf>,
+}
+
+impl CaptionsConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.burn_in || self.embed || self.srt.is_some() || self.vtt.is_some()
+    }
+}
+
+impl fmt::Display for CaptionsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut outputs = vec![];
+        if self.burn_in {
+            outputs.push("burn-in".to_owned());
+        }
+        if self.embed {
+            outputs.push("CEA-608".to_owned());
+        }
+        if let Some(srt) = &self.srt {
+            outputs.push(srt.display().to_string());
+        }
+        if let Some(vtt) = &self.vtt {
+            outputs.push(vtt.display().to_string());
+        }
+        f.write_str(&outputs.join(", "))
+    }
+}
+
+/// A sentence waiting to be heard, between two positions of the speech
+/// queue
+#[derive(Debug)]
+struct Pending {
+    text: String,
+    from: u64,
+    to: u64,
+    started: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    /// `duration` is how long it will be heard, unless it is cut
+    Start {
+        text: String,
+        at: Duration,
+        duration: Duration,
+    },
+    End {
+        at: Duration,
+    },
+}
+
+/// Captions of what is heard, timed by the samples the sink pulls from
+/// the speech queue
+#[derive(Debug, Clone)]
+pub struct Captions {
+    queue: AudioQueue,
+    samples_per_second: u64,
+    pending: Arc<Mutex<VecDeque<Pending>>>,
+    /// `None` when captions are off
+    events: Option<Sender<Event>>,
+}
+
+impl Captions {
+    /// Open the sidecar files and start writing the captions of `queue`.
+    /// Does nothing when `config` enables none.
+    pub fn spawn(
+        config: &CaptionsConfig,
+        queue: AudioQueue,
+        audio: &AudioSettings,
+        scene: SharedScene,
+        pipeline: &gst::Pipeline,
+    ) -> EbookResult<Self> {
+        let mut captions = Self {
+            queue,
+            samples_per_second: (audio.sample_rate * audio.channels) as u64,
+            pending: Arc::default(),
+            events: None,
+        };
+        if !config.is_enabled() {
+            return Ok(captions);
+        }
+
+        let writer = CaptionWriter {
+            scene: config.burn_in.then_some(scene),
+            // Only in the pipeline when they are embedded
+            appsrc: pipeline
+                .by_name(CAPTIONS_SRC_NAME)
+                .and_then(|appsrc| appsrc.downcast::<gst_app::AppSrc>().ok()),
+            srt: config
+                .srt
+                .as_deref()
+                .map(|path| create(path, b""))
+                .transpose()?,
+            vtt: config
+                .vtt
+                .as_deref()
+                .map(|path| create(path, b"WEBVTT\n\n"))
+                .transpose()?,
+            count: 0,
+            current: None,
+        };
+        if config.embed && writer.appsrc.is_none() {
+            warn!("Captions cannot be embedded without video");
+        }
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || writer.run(rx));
+        captions.events = Some(tx);
+        info!("Captions: {config}");
+
+        Ok(captions)
+    }
+
+    /// `text` is heard between the samples `from` and `to` of the queue
+    pub fn cue(&self, text: &str, from: u64, to: u64) {
+        if self.events.is_none() || from >= to {
+            return;
+        }
+
+        self.pending.lock().unwrap().push_back(Pending {
+            // A caption is a single line in every format
+            text: text.split_whitespace().collect::<Vec<_>>().join(" "),
+            from,
+            to,
+            started: false,
+        });
+    }
+
+    /// Called by the sink after every pull, `at` is the stream time of the
+    /// samples it got
+    pub fn advance(&self, at: Duration) {
+        let Some(events) = &self.events else {
+            return;
+        };
+
+        let played = self.queue.played();
+        let mut pending = self.pending.lock().unwrap();
+        while let Some(cue) = pending.front_mut() {
+            if !cue.started {
+                if played < cue.from {
+                    break;
+                }
+                if played >= cue.to {
+                    // Dropped before it was heard
+                    pending.pop_front();
+                    continue;
+                }
+                cue.started = true;
+                let left = cue.to.saturating_sub(played);
+                _ = events.send(Event::Start {
+                    text: cue.text.clone(),
+                    at,
+                    duration: Duration::from_secs_f64(left as f64 / self.samples_per_second as f64),
+                });
+            }
+
+            if played < cue.to {
+                break;
+            }
+            // Early when the queue was cleared, the caption is cut there
+            _ = events.send(Event::End { at });
+            pending.pop_front();
+        }
+    }
+}
+
+/// Every output of the captions, on their own thread so the sink never
+/// waits for the disk
+struct CaptionWriter {
+    scene: Option<SharedScene>,
+    appsrc: Option<gst_app::AppSrc>,
+    srt: Option<BufWriter<File>>,
+    vtt: Option<BufWriter<File>>,
+    /// SRT cues are numbered
+    count: usize,
+    current: Option<(String, Duration)>,
+}
+
+impl CaptionWriter {
+    fn run(mut self, events: Receiver<Event>) {
+        for event in events {
+            match event {
+                Event::Start { text, at, duration } => {
+                    if let Some(scene) = &self.scene {
+                        scene.lock().caption = Some(text.clone());
+                    }
+                    self.embed(&text, at, duration);
+                    self.current = Some((text, at));
+                }
+                Event::End { at } => {
+                    if let Some(scene) = &self.scene {
+                        scene.lock().caption = None;
+                    }
+                    if let Some((text, start)) = self.current.take() {
+                        if let Err(err) = self.write(&text, start, at) {
+                            warn!("Cannot write captions: {err}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn embed(&self, text: &str, at: Duration, duration: Duration) {
+        let Some(appsrc) = &self.appsrc else {
+            return;
+        };
+
+        let mut buffer = gst::Buffer::from_mut_slice(text.as_bytes().to_vec());
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(gst::ClockTime::from_nseconds(at.as_nanos() as u64));
+            buffer.set_duration(gst::ClockTime::from_nseconds(duration.as_nanos() as u64));
+        }
+        if let Err(err) = appsrc.push_buffer(buffer) {
+            warn!("Cannot embed captions: {err}");
+        }
+    }
+
+    fn write(&mut self, text: &str, start: Duration, end: Duration) -> io::Result<()> {
+        if end <= start {
+            return Ok(());
+        }
+
+        if let Some(srt) = &mut self.srt {
+            self.count += 1;
+            write!(
+                srt,
+                "{}\n{} --> {}\n{text}\n\n",
+                self.count,
+                timestamp(start, ','),
+                timestamp(end, ',')
+            )?;
+            srt.flush()?;
+        }
+        if let Some(vtt) = &mut self.vtt {
+            write!(
+                vtt,
+                "{} --> {}\n{text}\n\n",
+                timestamp(start, '.'),
+                timestamp(end, '.')
+            )?;
+            vtt.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A new file with only `header`, at `path` or numbered after it when it
+/// exists. The captions of earlier runs are timed from their own start,
+/// they are never overwritten nor appended to.
+fn create(path: &Path, header: &[u8]) -> EbookResult<BufWriter<File>> {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    for number in 0.. {
+        let path = match number {
+            0 => path.to_owned(),
+            number => path.with_file_name(format!("{stem}.{number}{extension}")),
+        };
+        let write = || {
+            let file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            let mut file = BufWriter::new(file);
+            file.write_all(header)?;
+            file.flush()?;
+            Ok::<_, io::Error>(file)
+        };
+
+        match write() {
+            Ok(file) => {
+                info!("Writing captions to {}", path.display());
+                return Ok(file);
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(EbookError::Captions(path, err.to_string())),
+        }
+    }
+    unreachable!("Every caption file name is taken")
+}
+
+/// `HH:MM:SS,mmm` for SRT, with a dot for WebVTT
+fn timestamp(time: Duration, separator: char) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::encoder::EncoderConfig;
+    use crate::testing;
+
+    /// Captions of a queue heard at 100 samples per second
+    fn captions() -> (Captions, Receiver<Event>) {
+        let (tx, rx) = mpsc::channel();
+        let captions = Captions {
+            queue: AudioQueue::new(&EncoderConfig::default().audio),
+            samples_per_second: 100,
+            pending: Arc::default(),
+            events: Some(tx),
+        };
+        (captions, rx)
+    }
+
+    fn start(text: &str, at: u64, duration: u64) -> Event {
+        Event::Start {
+            text: text.to_owned(),
+            at: Duration::from_millis(at),
+            duration: Duration::from_millis(duration),
+        }
+    }
+
+    fn end(at: u64) -> Event {
+        Event::End {
+            at: Duration::from_millis(at),
+        }
+    }
+
+    fn pull(queue: &AudioQueue, samples: usize) {
+        queue.pull(&mut vec![0.; samples], false);
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(Duration::ZERO, ','), "00:00:00,000");
+        assert_eq!(timestamp(Duration::from_millis(1500), ','), "00:00:01,500");
+        assert_eq!(
+            timestamp(Duration::from_millis(3_723_004), '.'),
+            "01:02:03.004"
+        );
+        assert_eq!(
+            timestamp(Duration::from_secs(100 * 3600), '.'),
+            "100:00:00.000"
+        );
+    }
+
+    #[test]
+    fn cues_follow_the_queue() {
+        let (captions, events) = captions();
+        let queue = captions.queue.clone();
+        captions.cue("Hola\n  mundo.", 0, 200);
+        captions.cue("Adiós.", 200, 300);
+        // Nothing to be heard
+        captions.cue("Nada.", 300, 300);
+        queue.push(&[0.1; 300]);
+
+        captions.advance(Duration::ZERO);
+        pull(&queue, 100);
+        captions.advance(Duration::from_millis(1000));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [start("Hola mundo.", 0, 2000)]
+        );
+
+        // The next one starts as soon as the previous one ends
+        pull(&queue, 100);
+        captions.advance(Duration::from_millis(2000));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            [end(2000), start("Adiós.", 2000, 1000)]
+        );
+
+        // Cut when the queue is cleared
+        pull(&queue, 50);
+        queue.clear();
+        captions.advance(Duration::from_millis(2500));
+        assert_eq!(events.try_iter().collect::<Vec<_>>(), [end(2500)]);
+        assert!(captions.pending.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn cleared_cues_are_never_shown() {
+        let (captions, events) = captions();
+        let queue = captions.queue.clone();
+        captions.cue("Uno.", 0, 100);
+        captions.cue("Dos.", 100, 200);
+        queue.push(&[0.1; 200]);
+
+        queue.clear();
+        captions.advance(Duration::ZERO);
+        assert!(events.try_recv().is_err());
+        assert!(captions.pending.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn sidecar_files() {
+        let dir = testing::temp_dir();
+        let scene = SharedScene::default();
+        let writer = CaptionWriter {
+            scene: Some(scene.clone()),
+            appsrc: None,
+            srt: Some(create(&dir.join("captions.srt"), b"").unwrap()),
+            vtt: Some(create(&dir.join("captions.vtt"), b"WEBVTT\n\n").unwrap()),
+            count: 0,
+            current: None,
+        };
+
+        let (tx, rx) = mpsc::channel();
+        for event in [
+            start("Hola mundo.", 1500, 2000),
+            end(3000),
+            // Cut right away, it is not written nor counted
+            start("Nada.", 3000, 1000),
+            end(3000),
+            start("Adiós.", 3_723_004, 1000),
+            end(3_724_000),
+        ] {
+            tx.send(event).unwrap();
+        }
+        drop(tx);
+        writer.run(rx);
+
+        assert_eq!(
+            fs::read_to_string(dir.join("captions.srt")).unwrap(),
+            "1\n00:00:01,500 --> 00:00:03,000\nHola mundo.\n\n\
+            2\n01:02:03,004 --> 01:02:04,000\nAdiós.\n\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("captions.vtt")).unwrap(),
+            "WEBVTT\n\n\
+            00:00:01.500 --> 00:00:03.000\nHola mundo.\n\n\
+            01:02:03.004 --> 01:02:04.000\nAdiós.\n\n"
+        );
+        assert_eq!(scene.lock().caption, None);
+    }
+
+    #[test]
+    fn every_run_gets_its_own_file() {
+        let dir = testing::temp_dir();
+        fs::write(dir.join("captions.srt"), "1\nfirst run\n").unwrap();
+        fs::write(dir.join("captions.1.srt"), "1\nsecond run\n").unwrap();
+
+        drop(create(&dir.join("captions.srt"), b"").unwrap());
+        drop(create(&dir.join("captions"), b"").unwrap());
+
+        assert_eq!(
+            fs::read_to_string(dir.join("captions.srt")).unwrap(),
+            "1\nfirst run\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("captions.1.srt")).unwrap(),
+            "1\nsecond run\n"
+        );
+        assert_eq!(fs::read_to_string(dir.join("captions.2.srt")).unwrap(), "");
+        assert!(dir.join("captions").is_file());
+    }
+}
@@ -0,0 +1,456 @@
+mod commands;
+mod irc;
+mod overlay;
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{debug, info, trace, warn};
+
+use commands::CommandHandler;
+use irc::{ChatMessage, IrcMessage};
+pub use overlay::ChatOverlay;
+
+use crate::control::ReaderControl;
+use crate::dashboard::Feed;
+use crate::dictionary::Definitions;
+use crate::poll::Polls;
+use crate::shutdown::Shutdown;
+use crate::CHAT_LOG;
+
+pub const DEFAULT_CHAT_ADDR: &str = "irc.chat.twitch.tv:6667";
+pub const DEFAULT_OVERLAY_LINES: usize = 8;
+
+/// Twitch drops longer messages
+const MAX_REPLY_LEN: usize = 500;
+/// Twitch pings every ~5 minutes, silence longer than this is a dead link
+const IDLE_TIMEOUT: Duration = Duration::from_secs(6 * 60);
+/// How often the shutdown flag is checked while waiting for lines
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+/// NOTICEs sent before closing the connection on a bad token
+const AUTH_FAILED: &[&str] = &["Login authentication failed", "Improperly formatted auth"];
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct ChatConfig {
+    /// Channel login, without `#`
+    pub channel: String,
+    /// Account of the bot, the channel itself if not set
+    pub nick: Option<String>,
+    /// `oauth:...` token with chat scopes. Without it the bot joins
+    /// anonymously and cannot reply.
+    pub token: Option<String>,
+    /// `host:port` of the IRC server, a local stand-in works too
+    pub addr: String,
+    /// Messages shown on screen, 0 hides the chat panel
+    pub overlay_lines: usize,
+    /// Messages with any of these words are not shown on screen
+    pub banned_words: Vec<String>,
+}
+
+impl ChatConfig {
+    fn login(&self) -> (String, Option<String>) {
+        match &self.token {
+            Some(token) if token.starts_with("oauth:") => (self.nick(), Some(token.clone())),
+            Some(token) => (self.nick(), Some(format!("oauth:{token}"))),
+            // Twitch accepts any `justinfan` nick without password, read only
+            None => (format!("justinfan{}", std::process::id()), None),
+        }
+    }
+
+    fn nick(&self) -> String {
+        self.nick.as_deref().unwrap_or(&self.channel).to_lowercase()
+    }
+}
+
+impl fmt::Display for ChatConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = if self.token.is_some() {
+            "bot"
+        } else {
+            "anonymous"
+        };
+        write!(f, "#{} as {} on {}", self.channel, mode, self.addr)
+    }
+}
+
+/// What the bot does with every chat message
+struct ChatBot {
+    commands: CommandHandler,
+    overlay: Option<ChatOverlay>,
+    /// Chat pane of the dashboard
+    feed: Option<Feed>,
+    polls: Option<Polls>,
+}
+
+impl ChatBot {
+    /// Returns the reply for the chat, if any
+    fn on_message(&mut self, message: &ChatMessage) -> Option<String> {
+        if let Some(overlay) = &self.overlay {
+            overlay.push(message);
+        }
+        if let Some(feed) = &self.feed {
+            feed.push(format!("{}: {}", message.display_name, message.text));
+        }
+
+        self.commands.handle(message)
+    }
+
+    /// What the bot says on its own, like the start of a book poll
+    fn announcements(&self) -> Vec<String> {
+        self.polls
+            .as_ref()
+            .map(Polls::take_announcements)
+            .unwrap_or_default()
+    }
+}
+
+/// Join the channel and run commands until shutdown, reconnecting when the
+/// connection drops
+pub fn spawn(
+    config: ChatConfig,
+    control: ReaderControl,
+    overlay: Option<ChatOverlay>,
+    polls: Option<Polls>,
+    definitions: Option<Definitions>,
+    feed: Option<Feed>,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut bot = ChatBot {
+            commands: CommandHandler::new(control, polls.clone(), definitions),
+            overlay,
+            feed,
+            polls,
+        };
+        let mut backoff = MIN_BACKOFF;
+
+        while !shutdown.is_requested() {
+            let started = Instant::now();
+            match run_connection(&config, &mut bot, &shutdown) {
+                Ok(()) => break,
+                Err(err) => warn!(target: CHAT_LOG, "Disconnected: {err}"),
+            }
+
+            if started.elapsed() > MAX_BACKOFF {
+                backoff = MIN_BACKOFF;
+            }
+
+            info!(target: CHAT_LOG, "Reconnecting in {}s", backoff.as_secs());
+            let deadline = Instant::now() + backoff;
+            while Instant::now() < deadline && !shutdown.is_requested() {
+                thread::sleep(READ_TIMEOUT.min(deadline - Instant::now()));
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    })
+}
+
+/// Returns `Ok` only on shutdown
+fn run_connection(config: &ChatConfig, bot: &mut ChatBot, shutdown: &Shutdown) -> io::Result<()> {
+    let mut connection = ChatConnection::connect(config)?;
+    let mut last_seen = Instant::now();
+    let mut line = String::new();
+
+    while !shutdown.is_requested() {
+        for announcement in bot.announcements() {
+            connection.say(&announcement)?;
+        }
+
+        match connection.reader.read_line(&mut line) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) => {}
+            Err(err) if is_timeout(&err) => {
+                if last_seen.elapsed() > IDLE_TIMEOUT {
+                    return Err(io::ErrorKind::TimedOut.into());
+                }
+                // Partial lines stay in `line`
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+
+        last_seen = Instant::now();
+        let message = IrcMessage::parse(&line);
+        line.clear();
+
+        if let Some(message) = message {
+            connection.handle(message, bot)?;
+        }
+    }
+
+    connection.send(&format!("PART #{}", config.channel))
+}
+
+struct ChatConnection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+    channel: String,
+    can_reply: bool,
+}
+
+impl ChatConnection {
+    fn connect(config: &ChatConfig) -> io::Result<Self> {
+        debug!(target: CHAT_LOG, "Connecting to {}", config.addr);
+        let writer = TcpStream::connect(&config.addr)?;
+        writer.set_read_timeout(Some(READ_TIMEOUT))?;
+
+        let (nick, token) = config.login();
+        let mut connection = Self {
+            reader: BufReader::new(writer.try_clone()?),
+            writer,
+            channel: config.channel.to_lowercase(),
+            can_reply: token.is_some(),
+        };
+
+        connection.send("CAP REQ :twitch.tv/tags twitch.tv/commands")?;
+        if let Some(token) = token {
+            connection.send_secret(&format!("PASS {token}"))?;
+        }
+        connection.send(&format!("NICK {nick}"))?;
+        connection.send(&format!("JOIN #{}", connection.channel))?;
+
+        Ok(connection)
+    }
+
+    fn handle(&mut self, message: IrcMessage, bot: &mut ChatBot) -> io::Result<()> {
+        match message.command.as_str() {
+            "PING" => {
+                let server = message
+                    .params
+                    .first()
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                self.send(&format!("PONG :{server}"))
+            }
+            "JOIN" if message.params.first() == Some(&format!("#{}", self.channel)) => {
+                info!(target: CHAT_LOG, "Joined #{}", self.channel);
+                Ok(())
+            }
+            "RECONNECT" => Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "server asked to reconnect",
+            )),
+            "NOTICE" => {
+                let text = message
+                    .params
+                    .last()
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                if AUTH_FAILED.iter().any(|notice| text.contains(notice)) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        text.to_owned(),
+                    ));
+                }
+                info!(target: CHAT_LOG, "{text}");
+                Ok(())
+            }
+            "PRIVMSG" => {
+                let Some(chat) = ChatMessage::from_irc(&message) else {
+                    return Ok(());
+                };
+                trace!(target: CHAT_LOG, "{}: {}", chat.display_name, chat.text);
+
+                match bot.on_message(&chat) {
+                    Some(reply) => self.say(&reply),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn say(&mut self, text: &str) -> io::Result<()> {
+        if !self.can_reply {
+            info!(target: CHAT_LOG, "(anonymous, not sent) {text}");
+            return Ok(());
+        }
+
+        let text = text
+            .chars()
+            .map(|c| if c == '\r' || c == '\n' { ' ' } else { c })
+            .collect::<String>();
+        let text = truncate(&text, MAX_REPLY_LEN);
+        self.send(&format!("PRIVMSG #{} :{text}", self.channel))
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        trace!(target: CHAT_LOG, "> {line}");
+        self.send_secret(line)
+    }
+
+    /// Like [`Self::send`] without logging the line
+    fn send_secret(&mut self, line: &str) -> io::Result<()> {
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
+        self.writer.flush()
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+fn truncate(text: &str, max_len: usize) -> &str {
+    if text.len() <= max_len {
+        return text;
+    }
+
+    let mut end = max_len;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::render::SharedScene;
+    use crate::testing;
+
+    /// Twitch side of a connection to the stand-in server
+    struct Server {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Server {
+        fn accept(listener: &TcpListener) -> Self {
+            let (stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+
+            Self {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                writer: stream,
+            }
+        }
+
+        fn expect(&mut self, expected: &str) {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            assert_eq!(line.trim_end(), expected);
+        }
+
+        fn send(&mut self, line: &str) {
+            write!(self.writer, "{line}\r\n").unwrap();
+        }
+
+        fn login(&mut self) {
+            self.expect("CAP REQ :twitch.tv/tags twitch.tv/commands");
+            self.expect("PASS oauth:secret");
+            self.expect("NICK lector_bot");
+            self.expect("JOIN #canal");
+            self.send(":lector_bot!lector_bot@lector_bot.tmi.twitch.tv JOIN #canal");
+        }
+    }
+
+    #[test]
+    fn stand_in_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ChatConfig {
+            channel: "Canal".to_owned(),
+            nick: Some("Lector_Bot".to_owned()),
+            token: Some("secret".to_owned()),
+            addr: listener.local_addr().unwrap().to_string(),
+            overlay_lines: 0,
+            banned_words: vec![],
+        };
+        let control = testing::control();
+        let shutdown = Shutdown::default();
+        let chat = spawn(
+            config,
+            control.clone(),
+            None,
+            None,
+            None,
+            None,
+            shutdown.clone(),
+        );
+
+        let mut server = Server::accept(&listener);
+        server.login();
+
+        server.send("PING :tmi.twitch.tv");
+        server.expect("PONG :tmi.twitch.tv");
+
+        // The viewer gets no reply, the moderator pauses the reading
+        server.send(
+            "@badges=subscriber/3;mod=0;display-name=Ana \
+            :ana!ana@ana.tmi.twitch.tv PRIVMSG #canal :!pause",
+        );
+        server.send(
+            "@badges=moderator/1;mod=1;display-name=Luis \
+            :luis!luis@luis.tmi.twitch.tv PRIVMSG #canal :!pause",
+        );
+        server.expect("PRIVMSG #canal :Reading paused");
+        assert!(control.session().is_paused());
+
+        server.send("@display-name=Ana :ana!ana@ana.tmi.twitch.tv PRIVMSG #canal :!progress");
+        server.expect("PRIVMSG #canal :@Ana 0% of book");
+
+        // Twitch asks to reconnect before a restart
+        server.send(":tmi.twitch.tv RECONNECT");
+        let mut server = Server::accept(&listener);
+        server.login();
+
+        shutdown.request();
+        server.expect("PART #canal");
+        chat.join().unwrap();
+    }
+
+    #[test]
+    fn announce_book_polls() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = ChatConfig {
+            channel: "canal".to_owned(),
+            nick: Some("lector_bot".to_owned()),
+            token: Some("secret".to_owned()),
+            addr: listener.local_addr().unwrap().to_string(),
+            overlay_lines: 0,
+            banned_words: vec![],
+        };
+        let control = testing::control();
+        let chunks = control.session().book().chunks.len();
+        control.seek(chunks);
+        let polls = Polls::new(
+            control.clone(),
+            SharedScene::default(),
+            &[testing::temp_dir().join("otro.txt")],
+            Duration::from_secs(60),
+        );
+        let shutdown = Shutdown::default();
+        let chat = spawn(
+            config,
+            control,
+            None,
+            Some(polls.clone()),
+            None,
+            None,
+            shutdown.clone(),
+        );
+
+        let mut server = Server::accept(&listener);
+        server.login();
+        // Nobody asked for it, the book is about to end
+        let ticks = polls.spawn(shutdown.clone());
+        server.expect("PRIVMSG #canal :Next book (60s): !vote 1 otro");
+
+        shutdown.request();
+        server.expect("PART #canal");
+        chat.join().unwrap();
+        ticks.join().unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::audio::Sound;
+use crate::chat::irc::ChatMessage;
+use crate::control::ReaderControl;
+use crate::dictionary::Definitions;
+use crate::poll::{PollKind, Polls};
+use crate::CHAT_LOG;
+
+const PREFIX: char = '!';
+
+/// Bot commands, `!name [args]`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Book,
+    Chapter,
+    Progress,
+    /// `!skip` jumps a sentence, `!skip chapter` a whole chapter
+    Skip {
+        chapter: bool,
+    },
+    Pause,
+    Resume,
+    /// `!vote 2`, options start at 1
+    Vote(usize),
+    /// `!poll book` or `!poll chapter`
+    Poll(PollKind),
+    /// `!define word`
+    Define(String),
+    /// `!speed 1.25`
+    Speed(f32),
+    /// `!pitch 0.9`
+    Pitch(f32),
+    /// `!volume -3`, in dB
+    Volume(f32),
+}
+
+impl Command {
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.strip_prefix(PREFIX)?.split_whitespace();
+        let name = words.next()?.to_lowercase();
+        let arg = words.next().map(str::to_lowercase);
+
+        Some(match name.as_str() {
+            "book" => Self::Book,
+            "chapter" => Self::Chapter,
+            "progress" => Self::Progress,
+            "skip" => Self::Skip {
+                chapter: matches!(arg.as_deref(), Some("chapter" | "capitulo" | "capítulo")),
+            },
+            "pause" => Self::Pause,
+            "resume" => Self::Resume,
+            "vote" => Self::Vote(arg?.parse().ok()?),
+            "poll" => Self::Poll(match arg?.as_str() {
+                "book" | "libro" => PollKind::Book,
+                "chapter" | "capitulo" | "capítulo" => PollKind::Chapter,
+                _ => return None,
+            }),
+            "define" | "definir" => Self::Define(arg?),
+            "speed" | "velocidad" => Self::Speed(number(arg?)?),
+            "pitch" | "tono" => Self::Pitch(number(arg?)?),
+            "volume" | "volumen" => Self::Volume(number(arg?)?),
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Book => "book",
+            Self::Chapter => "chapter",
+            Self::Progress => "progress",
+            Self::Skip { .. } => "skip",
+            Self::Pause => "pause",
+            Self::Resume => "resume",
+            Self::Vote(_) => "vote",
+            Self::Poll(_) => "poll",
+            Self::Define(_) => "define",
+            Self::Speed(_) => "speed",
+            Self::Pitch(_) => "pitch",
+            Self::Volume(_) => "volume",
+        }
+    }
+
+    /// Commands that change the reading are only for the broadcaster and
+    /// moderators
+    pub fn is_mod_only(&self) -> bool {
+        matches!(
+            self,
+            Self::Skip { .. }
+                | Self::Pause
+                | Self::Resume
+                | Self::Poll(_)
+                | Self::Speed(_)
+                | Self::Pitch(_)
+                | Self::Volume(_)
+        )
+    }
+
+    /// Time before the same viewer can use the command again. Moderators are
+    /// not limited.
+    pub fn cooldown(&self) -> Duration {
+        match self {
+            Self::Book | Self::Chapter | Self::Progress | Self::Define(_) => {
+                Duration::from_secs(30)
+            }
+            Self::Vote(_) => Duration::from_secs(2),
+            Self::Skip { .. }
+            | Self::Pause
+            | Self::Resume
+            | Self::Poll(_)
+            | Self::Speed(_)
+            | Self::Pitch(_)
+            | Self::Volume(_) => Duration::from_secs(5),
+        }
+    }
+}
+
+/// `"NaN"` and `"inf"` parse as floats too
+fn number(arg: String) -> Option<f32> {
+    arg.parse().ok().filter(|n: &f32| n.is_finite())
+}
+
+/// Runs chat commands against the reader, enforcing permissions and
+/// cooldowns
+#[derive(Debug)]
+pub struct CommandHandler {
+    control: ReaderControl,
+    polls: Option<Polls>,
+    definitions: Option<Definitions>,
+    /// (login, command) -> usable again at
+    cooldowns: HashMap<(String, &'static str), Instant>,
+}
+
+impl CommandHandler {
+    pub fn new(
+        control: ReaderControl,
+        polls: Option<Polls>,
+        definitions: Option<Definitions>,
+    ) -> Self {
+        Self {
+            control,
+            polls,
+            definitions,
+            cooldowns: HashMap::new(),
+        }
+    }
+
+    /// Run the command in `message`, if any. Returns the reply for the chat,
+    /// `None` when the command did nothing.
+    pub fn handle(&mut self, message: &ChatMessage) -> Option<String> {
+        let command = Command::parse(&message.text)?;
+
+        if command.is_mod_only() && !message.is_mod {
+            debug!(target: CHAT_LOG, "{} cannot use !{}", message.login, command.name());
+            return None;
+        }
+
+        if !message.is_mod && !self.take_cooldown(&message.login, &command) {
+            debug!(target: CHAT_LOG, "!{} on cooldown for {}", command.name(), message.login);
+            return None;
+        }
+
+        debug!(target: CHAT_LOG, "{} used !{}", message.login, command.name());
+        // Votes are too frequent to chime, the others only chime once done
+        let chime = !matches!(command, Command::Vote(_));
+        let reply = self.run(command, message);
+        if chime && reply.is_some() {
+            self.control.play_sound(Sound::Command);
+        }
+        reply
+    }
+
+    /// `false` if the user is still on cooldown, otherwise start a new one
+    fn take_cooldown(&mut self, login: &str, command: &Command) -> bool {
+        let now = Instant::now();
+        self.cooldowns.retain(|_, until| *until > now);
+
+        let key = (login.to_owned(), command.name());
+        if self.cooldowns.contains_key(&key) {
+            return false;
+        }
+
+        self.cooldowns.insert(key, now + command.cooldown());
+        true
+    }
+
+    fn run(&self, command: Command, message: &ChatMessage) -> Option<String> {
+        let user = &message.display_name;
+
+        match command {
+            Command::Book => {
+                let session = self.control.session();
+                let book = session.book();
+                Some(format!(
+                    "@{user} Reading {} ({} chapters)",
+                    book.title,
+                    book.chapters.len().saturating_sub(1)
+                ))
+            }
+            Command::Chapter => {
+                let session = self.control.session();
+                let chapter = session.book().chapters.get(session.current_chapter())?;
+                Some(format!("@{user} Chapter {chapter}"))
+            }
+            Command::Progress => {
+                let session = self.control.session();
+                Some(format!(
+                    "@{user} {:.0}% of {}",
+                    session.progress() * 100.0,
+                    session.book().title
+                ))
+            }
+            Command::Skip { chapter: false } => {
+                self.control.skip();
+                Some("Skipping the sentence".to_owned())
+            }
+            Command::Skip { chapter: true } => {
+                self.control.skip_chapter();
+                let session = self.control.session();
+                let chapter = session.book().chapters.get(session.current_chapter())?;
+                Some(format!("Skipping to chapter {chapter}"))
+            }
+            Command::Pause => {
+                self.control.pause();
+                Some("Reading paused".to_owned())
+            }
+            Command::Resume => {
+                self.control.resume();
+                Some("Reading resumed".to_owned())
+            }
+            Command::Vote(option) => {
+                self.polls.as_ref()?.vote(&message.login, option);
+                None
+            }
+            Command::Poll(kind) => self.polls.as_ref()?.start(kind),
+            Command::Define(word) => {
+                let definition = self.definitions.as_ref()?.define(&word);
+                Some(match definition {
+                    Some(definition) => format!("@{user} {word}: {definition}"),
+                    None => format!("@{user} {word} is not in the dictionary"),
+                })
+            }
+            Command::Speed(speed) => {
+                self.control.set_speed(speed);
+                Some(format!("Speed x{:.2}", self.control.voice().speed))
+            }
+            Command::Pitch(pitch) => {
+                self.control.set_pitch(pitch);
+                Some(format!("Pitch x{:.2}", self.control.voice().pitch))
+            }
+            Command::Volume(volume) => {
+                self.control.set_volume(volume);
+                Some(format!("Volume {:+.1} dB", self.control.voice().volume))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn message(login: &str, is_mod: bool, text: &str) -> ChatMessage {
+        ChatMessage {
+            login: login.to_owned(),
+            display_name: login.to_owned(),
+            color: None,
+            text: text.to_owned(),
+            is_mod,
+            is_broadcaster: false,
+            emotes: vec![],
+        }
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Command::parse("!book"), Some(Command::Book));
+        assert_eq!(
+            Command::parse("!SKIP"),
+            Some(Command::Skip { chapter: false })
+        );
+        assert_eq!(
+            Command::parse("!skip capítulo"),
+            Some(Command::Skip { chapter: true })
+        );
+        assert_eq!(Command::parse("!vote 2"), Some(Command::Vote(2)));
+        assert_eq!(
+            Command::parse("!poll libro"),
+            Some(Command::Poll(PollKind::Book))
+        );
+        assert_eq!(
+            Command::parse("!definir Hidalgo"),
+            Some(Command::Define("hidalgo".to_owned()))
+        );
+        assert_eq!(Command::parse("!speed 1.25"), Some(Command::Speed(1.25)));
+        assert_eq!(Command::parse("!volumen -3"), Some(Command::Volume(-3.0)));
+    }
+
+    #[test]
+    fn parse_rejects_other_messages() {
+        assert_eq!(Command::parse("book"), None);
+        assert_eq!(Command::parse("!"), None);
+        assert_eq!(Command::parse("!unknown"), None);
+        assert_eq!(Command::parse("!vote"), None);
+        assert_eq!(Command::parse("!vote two"), None);
+        assert_eq!(Command::parse("!poll song"), None);
+        assert_eq!(Command::parse("!define"), None);
+        assert_eq!(Command::parse("!speed NaN"), None);
+        assert_eq!(Command::parse("!pitch inf"), None);
+        assert_eq!(Command::parse("!volume -infinity"), None);
+    }
+
+    #[test]
+    fn mod_only_commands_are_rejected() {
+        let control = testing::control();
+        let mut handler = CommandHandler::new(control.clone(), None, None);
+
+        assert_eq!(handler.handle(&message("viewer", false, "!pause")), None);
+        assert!(!control.session().is_paused());
+
+        assert_eq!(
+            handler
+                .handle(&message("moderator", true, "!pause"))
+                .as_deref(),
+            Some("Reading paused")
+        );
+        assert!(control.session().is_paused());
+    }
+
+    #[test]
+    fn replies_only_when_something_ran() {
+        let mut handler = CommandHandler::new(testing::control(), None, None);
+
+        assert!(handler.handle(&message("mod", true, "!skip")).is_some());
+        assert_eq!(handler.handle(&message("ana", false, "!vote 1")), None);
+        assert_eq!(handler.handle(&message("mod", true, "!poll libro")), None);
+    }
+
+    #[test]
+    fn cooldowns_are_per_viewer_and_command() {
+        let mut handler = CommandHandler::new(testing::control(), None, None);
+
+        assert!(handler
+            .handle(&message("ana", false, "!progress"))
+            .is_some());
+        assert_eq!(handler.handle(&message("ana", false, "!progress")), None);
+        assert!(handler.handle(&message("ana", false, "!book")).is_some());
+        assert!(handler
+            .handle(&message("luis", false, "!progress"))
+            .is_some());
+
+        // Moderators are not limited
+        assert!(handler.handle(&message("mod", true, "!progress")).is_some());
+        assert!(handler.handle(&message("mod", true, "!progress")).is_some());
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// One line of the IRC protocol with the IRCv3 tags Twitch sends:
+///
+/// `@color=#1E90FF;mod=1 :nick!nick@nick.tmi.twitch.tv PRIVMSG #channel :hello`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IrcMessage {
+    pub tags: HashMap<String, String>,
+    pub prefix: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+impl IrcMessage {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+        let mut message = Self::default();
+
+        if let Some(tags) = rest.strip_prefix('@') {
+            let (tags, tail) = tags.split_once(' ')?;
+            message.tags = tags
+                .split(';')
+                .map(|tag| match tag.split_once('=') {
+                    Some((key, value)) => (key.to_owned(), unescape_tag(value)),
+                    None => (tag.to_owned(), String::new()),
+                })
+                .collect();
+            rest = tail.trim_start();
+        }
+
+        if let Some(prefix) = rest.strip_prefix(':') {
+            let (prefix, tail) = prefix.split_once(' ')?;
+            message.prefix = Some(prefix.to_owned());
+            rest = tail.trim_start();
+        }
+
+        let (params, trailing) = match rest.split_once(" :") {
+            Some((params, trailing)) => (params, Some(trailing)),
+            None => (rest, None),
+        };
+        let mut params = params.split_whitespace();
+
+        message.command = params.next()?.to_owned();
+        message.params = params.map(str::to_owned).collect();
+        message.params.extend(trailing.map(str::to_owned));
+
+        Some(message)
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .get(key)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    /// `nick` from `nick!user@host`
+    pub fn nick(&self) -> Option<&str> {
+        let prefix = self.prefix.as_deref()?;
+        Some(prefix.split_once('!').map_or(prefix, |(nick, _)| nick))
+    }
+}
+
+/// A chat message from a viewer
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    /// Lowercase login, stable between messages
+    pub login: String,
+    pub display_name: String,
+    /// `#RRGGBB`, `None` when the user never picked one
+    pub color: Option<String>,
+    pub text: String,
+    pub is_mod: bool,
+    pub is_broadcaster: bool,
+    /// Emote id and the char range it covers in `text`
+    pub emotes: Vec<(String, Range<usize>)>,
+}
+
+impl ChatMessage {
+    /// `None` if `message` is not a PRIVMSG
+    pub fn from_irc(message: &IrcMessage) -> Option<Self> {
+        if message.command != "PRIVMSG" {
+            return None;
+        }
+
+        let login = message.nick()?.to_owned();
+        let text = message.params.get(1)?.clone();
+        let badges = message.tag("badges").unwrap_or_default();
+        let has_badge = |name: &str| {
+            badges
+                .split(',')
+                .any(|badge| badge.split('/').next() == Some(name))
+        };
+
+        let is_broadcaster = has_badge("broadcaster");
+        let is_mod = message.tag("mod") == Some("1") || has_badge("moderator");
+
+        Some(Self {
+            display_name: message
+                .tag("display-name")
+                .map(str::to_owned)
+                .unwrap_or_else(|| login.clone()),
+            login,
+            color: message.tag("color").map(str::to_owned),
+            text,
+            is_mod: is_mod || is_broadcaster,
+            is_broadcaster,
+            emotes: message.tag("emotes").map(parse_emotes).unwrap_or_default(),
+        })
+    }
+}
+
+/// `25:0-4,12-16/1902:6-10`
+fn parse_emotes(tag: &str) -> Vec<(String, Range<usize>)> {
+    let mut emotes = tag
+        .split('/')
+        .filter_map(|emote| emote.split_once(':'))
+        .flat_map(|(id, ranges)| {
+            ranges.split(',').filter_map(move |range| {
+                let (start, end) = range.split_once('-')?;
+                let (start, end) = (start.parse::<usize>().ok()?, end.parse::<usize>().ok()?);
+                Some((id.to_owned(), start..end + 1))
+            })
+        })
+        .collect::<Vec<_>>();
+    emotes.sort_by_key(|(_, range)| range.start);
+    emotes
+}
+
+/// Tag values escape `;`, spaces and line breaks
+fn unescape_tag(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some(':') => unescaped.push(';'),
+            Some('s') => unescaped.push(' '),
+            Some('r') => unescaped.push('\r'),
+            Some('n') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn privmsg(tags: &str, text: &str) -> ChatMessage {
+        let line =
+            format!("@{tags} :lector!lector@lector.tmi.twitch.tv PRIVMSG #canal :{text}\r\n");
+        ChatMessage::from_irc(&IrcMessage::parse(&line).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parse_tags_prefix_and_params() {
+        let message = IrcMessage::parse(
+            "@badge-info=;color=#1E90FF;display-name=Lector :lector!lector@lector.tmi.twitch.tv \
+            PRIVMSG #canal :!skip chapter\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(message.tag("color"), Some("#1E90FF"));
+        assert_eq!(message.tag("badge-info"), None);
+        assert_eq!(message.nick(), Some("lector"));
+        assert_eq!(message.command, "PRIVMSG");
+        assert_eq!(message.params, ["#canal", "!skip chapter"]);
+    }
+
+    #[test]
+    fn parse_without_tags_or_prefix() {
+        let message = IrcMessage::parse("PING :tmi.twitch.tv\r\n").unwrap();
+
+        assert!(message.tags.is_empty());
+        assert_eq!(message.prefix, None);
+        assert_eq!(message.command, "PING");
+        assert_eq!(message.params, ["tmi.twitch.tv"]);
+
+        assert_eq!(IrcMessage::parse(""), None);
+        assert_eq!(IrcMessage::parse("@only=tags"), None);
+    }
+
+    #[test]
+    fn unescape_tag_values() {
+        let message = IrcMessage::parse(
+            r"@system-msg=Hola\smundo\:\sadiós\\\nfin;flag :tmi.twitch.tv USERNOTICE #canal",
+        )
+        .unwrap();
+
+        assert_eq!(message.tag("system-msg"), Some("Hola mundo; adiós\\\nfin"));
+        assert_eq!(message.tags.get("flag").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn badges_make_moderators() {
+        let viewer = privmsg("badges=subscriber/12;mod=0", "hola");
+        assert!(!viewer.is_mod);
+        assert!(!viewer.is_broadcaster);
+
+        let moderator = privmsg("badges=moderator/1,subscriber/12;mod=1", "hola");
+        assert!(moderator.is_mod);
+        assert!(!moderator.is_broadcaster);
+
+        // Twitch sends `mod=0` to the broadcaster
+        let broadcaster = privmsg("badges=broadcaster/1;mod=0", "hola");
+        assert!(broadcaster.is_mod);
+        assert!(broadcaster.is_broadcaster);
+    }
+
+    #[test]
+    fn chat_message_fields() {
+        let message = privmsg(
+            "display-name=Lector;color=;emotes=25:6-10/1902:0-4",
+            "Keepo Kappa",
+        );
+
+        assert_eq!(message.login, "lector");
+        assert_eq!(message.display_name, "Lector");
+        assert_eq!(message.color, None);
+        assert_eq!(message.text, "Keepo Kappa");
+        assert_eq!(
+            message.emotes,
+            [("1902".to_owned(), 0..5), ("25".to_owned(), 6..11)]
+        );
+
+        let anonymous = privmsg("color=#FF0000", "hola");
+        assert_eq!(anonymous.display_name, "lector");
+        assert_eq!(anonymous.color.as_deref(), Some("#FF0000"));
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::chat::irc::ChatMessage;
+use crate::render::SharedScene;
+use crate::renderizer::hot_lib::{ChatLine, ChatPart};
+use crate::shutdown::Shutdown;
+
+/// Time a message stays in the panel, fading during the last [`FADE`]
+const VISIBLE: Duration = Duration::from_secs(45);
+const FADE: Duration = Duration::from_secs(5);
+/// Opacity changes in steps, so static frames only re-render a few times
+/// while a message fades out
+const FADE_STEPS: f32 = 10.;
+const TICK: Duration = Duration::from_millis(100);
+
+/// Twitch's colors for users that never picked one
+const DEFAULT_COLORS: &[(u8, u8, u8)] = &[
+    (0xFF, 0x00, 0x00),
+    (0x00, 0x00, 0xFF),
+    (0x00, 0x80, 0x00),
+    (0xB2, 0x22, 0x22),
+    (0xFF, 0x7F, 0x50),
+    (0x9A, 0xCD, 0x32),
+    (0xFF, 0x45, 0x00),
+    (0x2E, 0x8B, 0x57),
+    (0xDA, 0xA5, 0x20),
+    (0xD2, 0x69, 0x1E),
+    (0x5F, 0x9E, 0xA0),
+    (0x1E, 0x90, 0xFF),
+    (0xFF, 0x69, 0xB4),
+    (0x8A, 0x2B, 0xE2),
+    (0x00, 0xFF, 0x7F),
+];
+
+/// Last chat messages shown in the side panel of the scene. Commands and
+/// messages with banned words are left out.
+#[derive(Debug, Clone)]
+pub struct ChatOverlay {
+    messages: Arc<Mutex<VecDeque<(Instant, ChatLine)>>>,
+    scene: SharedScene,
+    max_lines: usize,
+    banned_words: Arc<[String]>,
+}
+
+impl ChatOverlay {
+    pub fn new(scene: SharedScene, max_lines: usize, banned_words: &[String]) -> Self {
+        scene.lock().show_chat = true;
+
+        Self {
+            messages: Arc::default(),
+            scene,
+            max_lines,
+            banned_words: banned_words
+                .iter()
+                .map(|word| word.to_lowercase())
+                .collect(),
+        }
+    }
+
+    pub fn push(&self, message: &ChatMessage) {
+        if message.text.starts_with('!') || self.is_banned(&message.text) {
+            return;
+        }
+
+        let line = ChatLine {
+            user: message.display_name.clone(),
+            color: user_color(message),
+            parts: parts(message),
+            opacity: 1.,
+        };
+
+        {
+            let mut messages = self.messages.lock().unwrap();
+            messages.push_back((Instant::now(), line));
+            while messages.len() > self.max_lines {
+                messages.pop_front();
+            }
+        }
+
+        self.refresh();
+    }
+
+    /// Fade out old messages until shutdown
+    pub fn spawn(&self, shutdown: Shutdown) -> JoinHandle<()> {
+        let overlay = self.clone();
+        thread::spawn(move || {
+            while !shutdown.is_requested() {
+                overlay.refresh();
+                thread::sleep(TICK);
+            }
+        })
+    }
+
+    fn refresh(&self) {
+        let now = Instant::now();
+        let lines = {
+            let mut messages = self.messages.lock().unwrap();
+            messages.retain(|(at, _)| now.duration_since(*at) < VISIBLE);
+            messages
+                .iter()
+                .map(|(at, line)| ChatLine {
+                    opacity: opacity(now.duration_since(*at)),
+                    ..line.clone()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut scene = self.scene.lock();
+        if scene.chat != lines {
+            scene.chat = lines;
+        }
+    }
+
+    fn is_banned(&self, text: &str) -> bool {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .any(|word| {
+                let word = word.to_lowercase();
+                self.banned_words.contains(&word)
+            })
+    }
+}
+
+fn opacity(age: Duration) -> f32 {
+    let left = VISIBLE.saturating_sub(age);
+    if left >= FADE {
+        return 1.;
+    }
+
+    let opacity = left.as_secs_f32() / FADE.as_secs_f32();
+    (opacity * FADE_STEPS).ceil() / FADE_STEPS
+}
+
+/// Split the text around the emotes
+fn parts(message: &ChatMessage) -> Vec<ChatPart> {
+    let chars = message.text.chars().collect::<Vec<_>>();
+    let mut parts = vec![];
+    let mut start = 0;
+
+    for (_, range) in &message.emotes {
+        if range.start < start || range.start >= range.end || range.end > chars.len() {
+            continue;
+        }
+
+        if range.start > start {
+            parts.push(ChatPart::Text(chars[start..range.start].iter().collect()));
+        }
+        parts.push(ChatPart::Emote(chars[range.clone()].iter().collect()));
+        start = range.end;
+    }
+
+    if start < chars.len() {
+        parts.push(ChatPart::Text(chars[start..].iter().collect()));
+    }
+
+    parts
+}
+
+fn user_color(message: &ChatMessage) -> (f32, f32, f32) {
+    let (r, g, b) = message
+        .color
+        .as_deref()
+        .and_then(parse_hex_color)
+        .unwrap_or_else(|| {
+            let hash = message.login.bytes().fold(0usize, |hash, b| {
+                hash.wrapping_mul(31).wrapping_add(b as usize)
+            });
+            DEFAULT_COLORS[hash % DEFAULT_COLORS.len()]
+        });
+
+    readable(r as f32 / 255., g as f32 / 255., b as f32 / 255.)
+}
+
+/// `#1E90FF`
+fn parse_hex_color(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Darken light colors, the background is light too
+fn readable(r: f32, g: f32, b: f32) -> (f32, f32, f32) {
+    const MAX_LUMINANCE: f32 = 0.45;
+
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    if luminance <= MAX_LUMINANCE {
+        return (r, g, b);
+    }
+
+    let scale = MAX_LUMINANCE / luminance;
+    (r * scale, g * scale, b * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use super::*;
+
+    fn message(text: &str, emotes: &[(&str, Range<usize>)]) -> ChatMessage {
+        ChatMessage {
+            login: "viewer".to_owned(),
+            display_name: "Viewer".to_owned(),
+            color: None,
+            text: text.to_owned(),
+            is_mod: false,
+            is_broadcaster: false,
+            emotes: emotes
+                .iter()
+                .map(|(id, range)| (id.to_string(), range.clone()))
+                .collect(),
+        }
+    }
+
+    fn text(text: &str) -> ChatPart {
+        ChatPart::Text(text.to_owned())
+    }
+
+    fn emote(name: &str) -> ChatPart {
+        ChatPart::Emote(name.to_owned())
+    }
+
+    #[test]
+    fn split_around_emotes() {
+        assert_eq!(parts(&message("hola", &[])), [text("hola")]);
+        assert!(parts(&message("", &[])).is_empty());
+        assert_eq!(
+            parts(&message(
+                "Kappa hola Kappa",
+                &[("25", 0..5), ("25", 11..16)]
+            )),
+            [emote("Kappa"), text(" hola "), emote("Kappa")]
+        );
+        assert_eq!(
+            parts(&message("¡qué LUL tan bueño!", &[("425618", 5..8)])),
+            [text("¡qué "), emote("LUL"), text(" tan bueño!")]
+        );
+    }
+
+    #[test]
+    fn invalid_emotes_are_text() {
+        // Past the end of the text
+        assert_eq!(
+            parts(&message("hola Kappa", &[("25", 5..11)])),
+            [text("hola Kappa")]
+        );
+        assert_eq!(parts(&message("hola", &[("25", 2..5)])), [text("hola")]);
+        // Overlapping the previous one
+        assert_eq!(
+            parts(&message("KappaPride", &[("25", 0..5), ("55338", 0..10)])),
+            [emote("Kappa"), text("Pride")]
+        );
+        assert_eq!(
+            parts(&message("KappaPride", &[("25", 0..5), ("55338", 4..10)])),
+            [emote("Kappa"), text("Pride")]
+        );
+        // `3-1`
+        assert_eq!(
+            parts(&message("hola", &[("25", Range { start: 3, end: 2 })])),
+            [text("hola")]
+        );
+    }
+
+    #[test]
+    fn fade_out() {
+        assert_eq!(opacity(Duration::ZERO), 1.);
+        assert_eq!(opacity(VISIBLE - FADE), 1.);
+        assert_eq!(opacity(VISIBLE - FADE / 2), 0.5);
+        // Rounded up to the next step
+        assert_eq!(opacity(VISIBLE - FADE / 2 + Duration::from_millis(1)), 0.5);
+        assert_eq!(opacity(VISIBLE - FADE / 2 - Duration::from_millis(1)), 0.6);
+        assert_eq!(opacity(VISIBLE - Duration::from_millis(1)), 0.1);
+        assert_eq!(opacity(VISIBLE), 0.);
+        assert_eq!(opacity(VISIBLE * 2), 0.);
+    }
+
+    #[test]
+    fn banned_words() {
+        let overlay = ChatOverlay::new(SharedScene::default(), 10, &["Spoiler".to_owned()]);
+
+        assert!(overlay.is_banned("spoiler"));
+        assert!(overlay.is_banned("¡SPOILER! muere al final"));
+        assert!(overlay.is_banned("no,spoiler.por favor"));
+        assert!(!overlay.is_banned("spoilers"));
+        assert!(!overlay.is_banned("antispoiler"));
+        assert!(!overlay.is_banned(""));
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(parse_hex_color("#1E90FF"), Some((0x1E, 0x90, 0xFF)));
+        assert_eq!(parse_hex_color("#00ff7f"), Some((0x00, 0xFF, 0x7F)));
+        assert_eq!(parse_hex_color("1E90FF"), None);
+        assert_eq!(parse_hex_color("#1E90F"), None);
+        assert_eq!(parse_hex_color("#1E90FF0"), None);
+        assert_eq!(parse_hex_color("#1E90FG"), None);
+        assert_eq!(parse_hex_color("#+1+2+3"), None);
+        assert_eq!(parse_hex_color("#1ñE90"), None);
+        assert_eq!(parse_hex_color(""), None);
+    }
+
+    #[test]
+    fn light_colors_are_darkened() {
+        let luminance = |(r, g, b): (f32, f32, f32)| 0.2126 * r + 0.7152 * g + 0.0722 * b;
+
+        assert_eq!(readable(0., 0., 0.), (0., 0., 0.));
+        assert_eq!(readable(1., 0., 0.), (1., 0., 0.));
+        assert_eq!(readable(0., 0., 1.), (0., 0., 1.));
+
+        for color in [(1., 1., 1.), (0., 1., 0.), (0., 1., 0.5)] {
+            let (r, g, b) = readable(color.0, color.1, color.2);
+            assert!((luminance((r, g, b)) - 0.45).abs() < 1e-6, "{color:?}");
+            // Same hue
+            assert!((r * color.1 - g * color.0).abs() < 1e-6, "{color:?}");
+            assert!((g * color.2 - b * color.1).abs() < 1e-6, "{color:?}");
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+
+use crate::error::{EbookError, EbookResult};
+
+pub const USAGE: &str = "\
+Usage: ebook_reader [OPTIONS]
+
+Options:
+  --config <PATH>         TOML config file, ebook.toml when it exists
+  --check-config          Validate and print the effective config, then exit
+  --<section.key> <VALUE> Override a key of the config file, like --tts.language en
+  -h, --help              Print this help";
+
+#[derive(Debug, Default)]
+pub struct Cli {
+    pub config: Option<PathBuf>,
+    pub check_config: bool,
+    pub help: bool,
+    /// `section.key` and its value, they win over the environment and the
+    /// config file
+    pub overrides: Vec<(String, String)>,
+}
+
+impl Cli {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> EbookResult<Self> {
+        let mut cli = Self::default();
+        let mut args = args.into_iter().peekable();
+
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                match arg.as_str() {
+                    "-h" => {
+                        cli.help = true;
+                        continue;
+                    }
+                    _ => return Err(EbookError::InvalidArgument(arg)),
+                }
+            };
+
+            // `--key=value` or `--key value`, a lone flag means `true`
+            let (name, value) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (name, args.next_if(|next| !next.starts_with("--"))),
+            };
+
+            match name {
+                "help" => cli.help = true,
+                "check-config" => cli.check_config = true,
+                "config" => match value {
+                    Some(path) => cli.config = Some(path.into()),
+                    None => return Err(EbookError::InvalidArgument(arg)),
+                },
+                key if key.contains('.') => cli
+                    .overrides
+                    .push((key.to_owned(), value.unwrap_or_else(|| "true".to_owned()))),
+                _ => return Err(EbookError::InvalidArgument(arg)),
+            }
+        }
+
+        Ok(cli)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> EbookResult<Cli> {
+        Cli::parse(args.split_whitespace().map(str::to_owned))
+    }
+
+    fn overrides(args: &str) -> Vec<(String, String)> {
+        parse(args).unwrap().overrides
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_owned(), value.to_owned())
+    }
+
+    #[test]
+    fn both_value_forms() {
+        assert_eq!(overrides("--tts.language en"), [pair("tts.language", "en")]);
+        assert_eq!(overrides("--tts.language=en"), [pair("tts.language", "en")]);
+        // Only the first `=` splits
+        assert_eq!(
+            overrides("--twitch.rewards=abc=voice"),
+            [pair("twitch.rewards", "abc=voice")]
+        );
+        assert_eq!(overrides("--tts.volume -3"), [pair("tts.volume", "-3")]);
+        assert_eq!(overrides("--book.path="), [pair("book.path", "")]);
+
+        assert_eq!(
+            parse("--config a.toml").unwrap().config,
+            Some("a.toml".into())
+        );
+        assert_eq!(
+            parse("--config=a.toml").unwrap().config,
+            Some("a.toml".into())
+        );
+    }
+
+    #[test]
+    fn lone_flags() {
+        assert_eq!(
+            overrides("--captions.burn_in --tts.speed 1.5 --log.dashboard"),
+            [
+                pair("captions.burn_in", "true"),
+                pair("tts.speed", "1.5"),
+                pair("log.dashboard", "true"),
+            ]
+        );
+
+        let cli = parse("--check-config --tts.detect").unwrap();
+        assert!(cli.check_config && !cli.help);
+        assert_eq!(cli.overrides, [pair("tts.detect", "true")]);
+        assert!(parse("-h").unwrap().help);
+        assert!(parse("--help").unwrap().help);
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        for args in [
+            "book.txt",
+            "-x",
+            "--verbose",
+            "--config",
+            "--config --check-config",
+            "--tts.language en fr",
+        ] {
+            let err = parse(args).unwrap_err();
+            assert!(matches!(err, EbookError::InvalidArgument(_)), "{args}");
+        }
+    }
+}
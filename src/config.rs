@@ -1,18 +1,221 @@
+use std::collections::HashMap;
 use std::env::{self, VarError};
 use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use crate::api::ControlApiConfig;
+use crate::audio::{
+    Mastering, MusicConfig, MAX_DUCK, MAX_LOUDNESS, MAX_MUSIC_VOLUME, MAX_PAUSE_SECONDS,
+    MIN_LOUDNESS, MIN_MUSIC_VOLUME,
+};
+use crate::captions::CaptionsConfig;
+use crate::chat::{ChatConfig, DEFAULT_CHAT_ADDR, DEFAULT_OVERLAY_LINES};
+use crate::cli::Cli;
+use crate::dialogue::{Cast, Role};
+use crate::dictionary::Dictionary;
+use crate::encoder::{EncoderConfig, EncoderPreset};
 use crate::error::{EbookError, EbookResult};
+use crate::eventsub::{EventSubConfig, DEFAULT_EVENTSUB_URL};
+use crate::helix::{
+    HelixConfig, DEFAULT_API_URL, DEFAULT_AUTH_URL, DEFAULT_CATEGORY, DEFAULT_TITLE,
+};
+use crate::logger;
+use crate::preview::PreviewMode;
+use crate::tts::{
+    Locale, MAX_PITCH, MAX_SPEED, MAX_VOLUME, MIN_PITCH, MIN_SPEED, MIN_VOLUME, VOICES,
+};
 
 const STREAM_KEY_NAME: &str = "TWITCH_STREAM_KEY";
+const RTMP_URL_NAME: &str = "RTMP_URL";
 const PREVIEW_NAME: &str = "PREVIEW";
+const PREVIEW_ADDR_NAME: &str = "PREVIEW_ADDR";
 const LOG_FILE_NAME: &str = "LOG_FILE";
+const ENCODER_PRESET_NAME: &str = "ENCODER_PRESET";
+const STATIC_FRAMES_NAME: &str = "STATIC_FRAMES";
+const BOOK_NAME: &str = "BOOK";
+const LANGUAGE_NAME: &str = "LANGUAGE";
+const DETECT_LANGUAGE_NAME: &str = "DETECT_LANGUAGE";
+const VOICE_NAME: &str = "VOICE";
+const SPEED_NAME: &str = "SPEED";
+const PITCH_NAME: &str = "PITCH";
+const VOLUME_NAME: &str = "VOLUME";
+const DIALOGUE_VOICE_NAME: &str = "DIALOGUE_VOICE";
+const DIALOGUE_PITCH_NAME: &str = "DIALOGUE_PITCH";
+const CHARACTERS_NAME: &str = "CHARACTERS";
+const LOUDNESS_NAME: &str = "LOUDNESS";
+const SENTENCE_PAUSE_NAME: &str = "SENTENCE_PAUSE";
+const PARAGRAPH_PAUSE_NAME: &str = "PARAGRAPH_PAUSE";
+const CHAPTER_PAUSE_NAME: &str = "CHAPTER_PAUSE";
+const MUSIC_NAME: &str = "MUSIC";
+const MUSIC_VOLUME_NAME: &str = "MUSIC_VOLUME";
+const MUSIC_DUCK_NAME: &str = "MUSIC_DUCK";
+const FONT_NAME: &str = "FONT";
+const SOUNDS_NAME: &str = "SOUNDS";
+const CAPTIONS_BURN_IN_NAME: &str = "CAPTIONS_BURN_IN";
+const CAPTIONS_EMBED_NAME: &str = "CAPTIONS_EMBED";
+const CAPTIONS_SRT_NAME: &str = "CAPTIONS_SRT";
+const CAPTIONS_VTT_NAME: &str = "CAPTIONS_VTT";
+const CHANNEL_NAME: &str = "TWITCH_CHANNEL";
+const BOT_NICK_NAME: &str = "TWITCH_BOT_NICK";
+const OAUTH_TOKEN_NAME: &str = "TWITCH_OAUTH_TOKEN";
+const CHAT_ADDR_NAME: &str = "TWITCH_CHAT_ADDR";
+const CHAT_OVERLAY_LINES_NAME: &str = "CHAT_OVERLAY_LINES";
+const CHAT_BANNED_WORDS_NAME: &str = "CHAT_BANNED_WORDS";
+const PLAYLIST_NAME: &str = "PLAYLIST";
+const POLL_SECONDS_NAME: &str = "POLL_SECONDS";
+const API_TOKEN_NAME: &str = "TWITCH_API_TOKEN";
+const REFRESH_TOKEN_NAME: &str = "TWITCH_REFRESH_TOKEN";
+const CLIENT_ID_NAME: &str = "TWITCH_CLIENT_ID";
+const CLIENT_SECRET_NAME: &str = "TWITCH_CLIENT_SECRET";
+const CATEGORY_NAME: &str = "TWITCH_CATEGORY";
+const TITLE_NAME: &str = "TWITCH_TITLE";
+const API_URL_NAME: &str = "TWITCH_API_URL";
+const AUTH_URL_NAME: &str = "TWITCH_AUTH_URL";
+const REWARDS_NAME: &str = "TWITCH_REWARDS";
+const EVENTSUB_URL_NAME: &str = "TWITCH_EVENTSUB_URL";
+const DICTIONARY_NAME: &str = "DICTIONARY";
+const DEFINE_ALOUD_NAME: &str = "DEFINE_ALOUD";
+const CONTROL_ADDR_NAME: &str = "CONTROL_ADDR";
+const CONTROL_TOKEN_NAME: &str = "CONTROL_TOKEN";
+const DASHBOARD_NAME: &str = "DASHBOARD";
+
+/// Key of the config file for every variable, the environment still wins
+/// over the file and the command line over both
+const CONFIG_KEYS: &[(&str, &str)] = &[
+    ("stream.key", STREAM_KEY_NAME),
+    ("stream.url", RTMP_URL_NAME),
+    ("preview.mode", PREVIEW_NAME),
+    ("preview.addr", PREVIEW_ADDR_NAME),
+    ("log.file", LOG_FILE_NAME),
+    ("log.dashboard", DASHBOARD_NAME),
+    ("encoder.preset", ENCODER_PRESET_NAME),
+    ("encoder.static_frames", STATIC_FRAMES_NAME),
+    ("book.path", BOOK_NAME),
+    ("book.playlist", PLAYLIST_NAME),
+    ("tts.language", LANGUAGE_NAME),
+    ("tts.detect", DETECT_LANGUAGE_NAME),
+    ("tts.voice", VOICE_NAME),
+    ("tts.speed", SPEED_NAME),
+    ("tts.pitch", PITCH_NAME),
+    ("tts.volume", VOLUME_NAME),
+    ("dialogue.voice", DIALOGUE_VOICE_NAME),
+    ("dialogue.pitch", DIALOGUE_PITCH_NAME),
+    ("dialogue.characters", CHARACTERS_NAME),
+    ("audio.loudness", LOUDNESS_NAME),
+    ("audio.sentence_pause", SENTENCE_PAUSE_NAME),
+    ("audio.paragraph_pause", PARAGRAPH_PAUSE_NAME),
+    ("audio.chapter_pause", CHAPTER_PAUSE_NAME),
+    ("music.playlist", MUSIC_NAME),
+    ("music.volume", MUSIC_VOLUME_NAME),
+    ("music.duck", MUSIC_DUCK_NAME),
+    ("theme.font", FONT_NAME),
+    ("theme.sounds", SOUNDS_NAME),
+    ("captions.burn_in", CAPTIONS_BURN_IN_NAME),
+    ("captions.embed", CAPTIONS_EMBED_NAME),
+    ("captions.srt", CAPTIONS_SRT_NAME),
+    ("captions.vtt", CAPTIONS_VTT_NAME),
+    ("chat.channel", CHANNEL_NAME),
+    ("chat.nick", BOT_NICK_NAME),
+    ("chat.token", OAUTH_TOKEN_NAME),
+    ("chat.addr", CHAT_ADDR_NAME),
+    ("chat.overlay_lines", CHAT_OVERLAY_LINES_NAME),
+    ("chat.banned_words", CHAT_BANNED_WORDS_NAME),
+    ("polls.seconds", POLL_SECONDS_NAME),
+    ("twitch.token", API_TOKEN_NAME),
+    ("twitch.refresh_token", REFRESH_TOKEN_NAME),
+    ("twitch.client_id", CLIENT_ID_NAME),
+    ("twitch.client_secret", CLIENT_SECRET_NAME),
+    ("twitch.category", CATEGORY_NAME),
+    ("twitch.title", TITLE_NAME),
+    ("twitch.api_url", API_URL_NAME),
+    ("twitch.auth_url", AUTH_URL_NAME),
+    ("twitch.rewards", REWARDS_NAME),
+    ("twitch.eventsub_url", EVENTSUB_URL_NAME),
+    ("dictionary.path", DICTIONARY_NAME),
+    ("dictionary.aloud", DEFINE_ALOUD_NAME),
+    ("control.addr", CONTROL_ADDR_NAME),
+    ("control.token", CONTROL_TOKEN_NAME),
+];
+
+/// Never taken from the command line, `ps` shows it to everyone. They are
+/// masked in the logs as soon as they are loaded.
+const SECRET_NAMES: &[&str] = &[
+    STREAM_KEY_NAME,
+    OAUTH_TOKEN_NAME,
+    API_TOKEN_NAME,
+    REFRESH_TOKEN_NAME,
+    CLIENT_SECRET_NAME,
+    CONTROL_TOKEN_NAME,
+];
+/// Set by systemd for `LoadCredential=`
+const CREDENTIALS_DIRECTORY_NAME: &str = "CREDENTIALS_DIRECTORY";
+
+const DEFAULT_CONFIG: &str = "ebook.toml";
+const DEFAULT_RTMP_URL: &str = "rtmp://live.twitch.tv/app";
+const DEFAULT_BOOK: &str = "book.txt";
+const DEFAULT_LANGUAGE: &str = "es";
+const DEFAULT_FONT: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+const DEFAULT_POLL_SECONDS: u64 = 90;
+const DEFAULT_LOUDNESS: f32 = -16.0;
+const DEFAULT_SENTENCE_PAUSE: f32 = 0.3;
+const DEFAULT_PARAGRAPH_PAUSE: f32 = 0.8;
+const DEFAULT_CHAPTER_PAUSE: f32 = 2.0;
+const DEFAULT_MUSIC_VOLUME: f32 = -20.0;
+const DEFAULT_MUSIC_DUCK: f32 = 12.0;
 
 #[derive(Debug)]
 pub struct EbookConfig {
     pub stream_key: String,
-    pub preview: bool,
+    /// Ingest server, the stream key is appended to it
+    pub rtmp_url: String,
+    pub preview: PreviewMode,
     pub log_file: Option<PathBuf>,
+    pub encoder: EncoderConfig,
+    pub book: PathBuf,
+    /// BCP-47 tag, its region picks the voice when [`EbookConfig::voice`]
+    /// is not set
+    pub language: Locale,
+    /// Detect the language of every sentence, [`EbookConfig::language`] is
+    /// the fallback
+    pub detect_language: bool,
+    /// Voice, speed, pitch and volume (in dB) the narrator starts with
+    pub voice: String,
+    pub speed: f32,
+    pub pitch: f32,
+    pub volume: f32,
+    /// Voice of the dialogue, the narrator's when it is not set
+    pub dialogue: Role,
+    /// TOML file with the voice of every named character
+    pub characters: Option<PathBuf>,
+    /// Loudness of the speech and silence between chunks
+    pub mastering: Mastering,
+    /// Background music, ducked under the speech
+    pub music: Option<MusicConfig>,
+    pub font: Option<PathBuf>,
+    /// Directory with the sounds of chapters, book endings, polls and
+    /// chat commands
+    pub sounds: Option<PathBuf>,
+    /// Captions of what is heard, all off by default
+    pub captions: CaptionsConfig,
+    pub chat: Option<ChatConfig>,
+    /// Books to read after [`EbookConfig::book`], and the options of the
+    /// next book polls
+    pub playlist: Vec<PathBuf>,
+    pub poll_seconds: u64,
+    pub helix: Option<HelixConfig>,
+    /// Needs [`EbookConfig::helix`]
+    pub eventsub: Option<EventSubConfig>,
+    /// JSON or StarDict `.ifo` for `!define`, `{language}` is replaced by
+    /// the language code
+    pub dictionary: Option<PathBuf>,
+    /// Read definitions between sentences too
+    pub define_aloud: bool,
+    /// Only bound when configured
+    pub control_api: Option<ControlApiConfig>,
+    /// Terminal dashboard instead of plain logs
+    pub dashboard: bool,
 }
 
 impl fmt::Display for EbookConfig {
@@ -28,6 +231,7 @@ impl fmt::Display for EbookConfig {
             "  {YELL}Stream key : {RED_}{}{RST_}\n",
             self.stream_key.chars().map(|_| '*').collect::<String>()
         )?;
+        write!(f, "  {YELL}Output     : {GREE}{}{RST_}\n", self.rtmp_url)?;
         write!(f, "  {YELL}Preview    : {GREE}{}{RST_}\n", self.preview)?;
         if let Some(log_file) = &self.log_file {
             write!(
@@ -38,33 +242,851 @@ impl fmt::Display for EbookConfig {
         } else {
             write!(f, "  {YELL}Log File   : {RED_}No{RST_}\n")?;
         }
+        write!(
+            f,
+            "  {YELL}Encoder    : {GREE}{}{RST_}\n",
+            self.encoder.preset
+        )?;
+        write!(
+            f,
+            "  {YELL}Static     : {GREE}{}{RST_}\n",
+            self.encoder.static_frames
+        )?;
+        write!(
+            f,
+            "  {YELL}Book       : {GREE}{}{RST_}\n",
+            self.book.display()
+        )?;
+        let detect = if self.detect_language {
+            " (detect)"
+        } else {
+            ""
+        };
+        write!(
+            f,
+            "  {YELL}Language   : {GREE}{} ({:?}){detect}{RST_}\n",
+            self.language, self.language.language
+        )?;
+        write!(
+            f,
+            "  {YELL}Voice      : {GREE}{} x{:.2} ^{:.2} {:+.1} dB{RST_}\n",
+            self.voice, self.speed, self.pitch, self.volume
+        )?;
+        let characters = match &self.characters {
+            Some(path) => format!(" ({})", path.display()),
+            None => String::new(),
+        };
+        write!(
+            f,
+            "  {YELL}Dialogue   : {GREE}{} ^{:.2}{characters}{RST_}\n",
+            self.dialogue.tld.as_deref().unwrap_or(&self.voice),
+            self.dialogue.pitch.unwrap_or(1.)
+        )?;
+        let loudness = match self.mastering.loudness {
+            Some(loudness) => format!("{loudness:.1} LUFS"),
+            None => "engine".to_owned(),
+        };
+        write!(
+            f,
+            "  {YELL}Mastering  : {GREE}{loudness}, pauses {:.2}s {:.2}s {:.2}s{RST_}\n",
+            self.mastering.sentence_pause.as_secs_f32(),
+            self.mastering.paragraph_pause.as_secs_f32(),
+            self.mastering.chapter_pause.as_secs_f32()
+        )?;
+        if let Some(music) = &self.music {
+            write!(f, "  {YELL}Music      : {GREE}{music}{RST_}\n")?;
+        } else {
+            write!(f, "  {YELL}Music      : {RED_}No{RST_}\n")?;
+        }
+        if let Some(font) = &self.font {
+            write!(f, "  {YELL}Font       : {GREE}{}{RST_}\n", font.display())?;
+        } else {
+            write!(f, "  {YELL}Font       : {RED_}No{RST_}\n")?;
+        }
+        if let Some(sounds) = &self.sounds {
+            write!(f, "  {YELL}Sounds     : {GREE}{}{RST_}\n", sounds.display())?;
+        } else {
+            write!(f, "  {YELL}Sounds     : {RED_}No{RST_}\n")?;
+        }
+        if self.captions.is_enabled() {
+            write!(f, "  {YELL}Captions   : {GREE}{}{RST_}\n", self.captions)?;
+        } else {
+            write!(f, "  {YELL}Captions   : {RED_}No{RST_}\n")?;
+        }
+        if let Some(chat) = &self.chat {
+            write!(f, "  {YELL}Chat       : {GREE}{chat}{RST_}\n")?;
+        } else {
+            write!(f, "  {YELL}Chat       : {RED_}No{RST_}\n")?;
+        }
+        write!(
+            f,
+            "  {YELL}Playlist   : {GREE}{} books{RST_}\n",
+            self.playlist.len()
+        )?;
+        write!(
+            f,
+            "  {YELL}Poll       : {GREE}{}s{RST_}\n",
+            self.poll_seconds
+        )?;
+        if let Some(helix) = &self.helix {
+            write!(f, "  {YELL}Twitch API : {GREE}{helix}{RST_}\n")?;
+        } else {
+            write!(f, "  {YELL}Twitch API : {RED_}No{RST_}\n")?;
+        }
+        if let Some(eventsub) = &self.eventsub {
+            write!(f, "  {YELL}Rewards    : {GREE}{eventsub}{RST_}\n")?;
+        } else {
+            write!(f, "  {YELL}Rewards    : {RED_}No{RST_}\n")?;
+        }
+        if let Some(dictionary) = &self.dictionary {
+            let aloud = if self.define_aloud { " (aloud)" } else { "" };
+            write!(
+                f,
+                "  {YELL}Dictionary : {GREE}{}{aloud}{RST_}\n",
+                dictionary.display()
+            )?;
+        } else {
+            write!(f, "  {YELL}Dictionary : {RED_}No{RST_}\n")?;
+        }
+        if let Some(control_api) = &self.control_api {
+            write!(f, "  {YELL}Control API: {GREE}{control_api}{RST_}\n")?;
+        } else {
+            write!(f, "  {YELL}Control API: {RED_}No{RST_}\n")?;
+        }
+        write!(f, "  {YELL}Dashboard  : {GREE}{}{RST_}\n", self.dashboard)?;
 
         Ok(())
     }
 }
 
 impl EbookConfig {
-    pub fn from_envs() -> EbookResult<Self> {
-        Ok(Self {
-            stream_key: load_env(STREAM_KEY_NAME)?.ok_or(EbookError::NoTwitchStreamKey)?,
-            preview: load_bool(PREVIEW_NAME)?.unwrap_or(false),
-            log_file: load_env(LOG_FILE_NAME)?.map(|p| p.into()),
-        })
+    pub fn load(source: &ConfigSource) -> EbookResult<Self> {
+        let mut encoder = match source.load(ENCODER_PRESET_NAME)? {
+            Some(preset) => EncoderConfig::from_preset(preset.parse::<EncoderPreset>()?),
+            None => EncoderConfig::default(),
+        };
+        encoder.static_frames = source.load_bool(STATIC_FRAMES_NAME)?.unwrap_or(false);
+        encoder.validate()?;
+
+        let eventsub = match source.load(REWARDS_NAME)? {
+            Some(rewards) => Some(EventSubConfig {
+                rewards: EventSubConfig::parse_rewards(&rewards)?,
+                url: source
+                    .load(EVENTSUB_URL_NAME)?
+                    .unwrap_or_else(|| DEFAULT_EVENTSUB_URL.to_owned()),
+            }),
+            None => None,
+        };
+
+        let language = source
+            .load(LANGUAGE_NAME)?
+            .as_deref()
+            .unwrap_or(DEFAULT_LANGUAGE)
+            .parse::<Locale>()
+            .map_err(EbookError::InvalidLanguage)?;
+
+        let dictionary: Option<PathBuf> = source.load(DICTIONARY_NAME)?.map(|path| {
+            path.replace("{language}", language.language.as_code())
+                .into()
+        });
+
+        let speed = source.load_number(SPEED_NAME)?.unwrap_or(1.);
+        if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+            return Err(EbookError::InvalidNumber(SPEED_NAME, speed.to_string()));
+        }
+        let pitch = source.load_number(PITCH_NAME)?.unwrap_or(1.);
+        if !(MIN_PITCH..=MAX_PITCH).contains(&pitch) {
+            return Err(EbookError::InvalidNumber(PITCH_NAME, pitch.to_string()));
+        }
+        let volume = source.load_number(VOLUME_NAME)?.unwrap_or(0.);
+        if !(MIN_VOLUME..=MAX_VOLUME).contains(&volume) {
+            return Err(EbookError::InvalidNumber(VOLUME_NAME, volume.to_string()));
+        }
+
+        let dialogue_pitch: Option<f32> = source.load_number(DIALOGUE_PITCH_NAME)?;
+        if let Some(pitch) = dialogue_pitch {
+            if !(MIN_PITCH..=MAX_PITCH).contains(&pitch) {
+                return Err(EbookError::InvalidNumber(
+                    DIALOGUE_PITCH_NAME,
+                    pitch.to_string(),
+                ));
+            }
+        }
+
+        let loudness = match source.load(LOUDNESS_NAME)?.as_deref().map(str::trim) {
+            Some("") => None,
+            Some(loudness) => Some(
+                loudness
+                    .parse()
+                    .ok()
+                    .filter(|loudness| (MIN_LOUDNESS..=MAX_LOUDNESS).contains(loudness))
+                    .ok_or_else(|| EbookError::InvalidNumber(LOUDNESS_NAME, loudness.to_owned()))?,
+            ),
+            None => Some(DEFAULT_LOUDNESS),
+        };
+        let mastering = Mastering {
+            loudness,
+            sentence_pause: load_pause(source, SENTENCE_PAUSE_NAME, DEFAULT_SENTENCE_PAUSE)?,
+            paragraph_pause: load_pause(source, PARAGRAPH_PAUSE_NAME, DEFAULT_PARAGRAPH_PAUSE)?,
+            chapter_pause: load_pause(source, CHAPTER_PAUSE_NAME, DEFAULT_CHAPTER_PAUSE)?,
+        };
+
+        let music = match source.load(MUSIC_NAME)? {
+            Some(path) => {
+                let volume = source
+                    .load_number(MUSIC_VOLUME_NAME)?
+                    .unwrap_or(DEFAULT_MUSIC_VOLUME);
+                if !(MIN_MUSIC_VOLUME..=MAX_MUSIC_VOLUME).contains(&volume) {
+                    return Err(EbookError::InvalidNumber(
+                        MUSIC_VOLUME_NAME,
+                        volume.to_string(),
+                    ));
+                }
+                let duck = source
+                    .load_number(MUSIC_DUCK_NAME)?
+                    .unwrap_or(DEFAULT_MUSIC_DUCK);
+                if !(0.0..=MAX_DUCK).contains(&duck) {
+                    return Err(EbookError::InvalidNumber(MUSIC_DUCK_NAME, duck.to_string()));
+                }
+                Some(MusicConfig {
+                    tracks: load_playlist(Path::new(&path))?,
+                    volume,
+                    duck,
+                })
+            }
+            None => None,
+        };
+
+        let config = Self {
+            stream_key: source
+                .load(STREAM_KEY_NAME)?
+                .ok_or(EbookError::NoTwitchStreamKey)?,
+            rtmp_url: source
+                .load(RTMP_URL_NAME)?
+                .unwrap_or_else(|| DEFAULT_RTMP_URL.to_owned())
+                .trim_end_matches('/')
+                .to_owned(),
+            preview: match source.load(PREVIEW_NAME)? {
+                Some(mode) => {
+                    PreviewMode::parse(&mode, source.load(PREVIEW_ADDR_NAME)?.as_deref())?
+                }
+                None => PreviewMode::Off,
+            },
+            log_file: source.load(LOG_FILE_NAME)?.map(|p| p.into()),
+            encoder,
+            book: source
+                .load(BOOK_NAME)?
+                .unwrap_or_else(|| DEFAULT_BOOK.to_owned())
+                .into(),
+            language,
+            detect_language: source.load_bool(DETECT_LANGUAGE_NAME)?.unwrap_or(false),
+            voice: source
+                .load(VOICE_NAME)?
+                .unwrap_or_else(|| language.tld().unwrap_or(VOICES[0]).to_owned()),
+            speed,
+            pitch,
+            volume,
+            dialogue: Role {
+                tld: source.load(DIALOGUE_VOICE_NAME)?,
+                pitch: dialogue_pitch,
+            },
+            characters: source.load(CHARACTERS_NAME)?.map(|path| path.into()),
+            mastering,
+            music,
+            font: match source.load(FONT_NAME)?.as_deref() {
+                Some("") => None,
+                Some(font) => Some(font.into()),
+                None => Some(DEFAULT_FONT.into()),
+            },
+            sounds: source.load(SOUNDS_NAME)?.map(|path| path.into()),
+            captions: CaptionsConfig {
+                burn_in: source.load_bool(CAPTIONS_BURN_IN_NAME)?.unwrap_or(false),
+                embed: source.load_bool(CAPTIONS_EMBED_NAME)?.unwrap_or(false),
+                srt: source.load(CAPTIONS_SRT_NAME)?.map(|path| path.into()),
+                vtt: source.load(CAPTIONS_VTT_NAME)?.map(|path| path.into()),
+            },
+            chat: match source.load(CHANNEL_NAME)? {
+                Some(channel) => Some(ChatConfig {
+                    channel: channel.trim_start_matches('#').to_lowercase(),
+                    nick: source.load(BOT_NICK_NAME)?,
+                    token: source.load(OAUTH_TOKEN_NAME)?,
+                    addr: source
+                        .load(CHAT_ADDR_NAME)?
+                        .unwrap_or_else(|| DEFAULT_CHAT_ADDR.to_owned()),
+                    overlay_lines: source
+                        .load_number(CHAT_OVERLAY_LINES_NAME)?
+                        .unwrap_or(DEFAULT_OVERLAY_LINES),
+                    banned_words: source
+                        .load(CHAT_BANNED_WORDS_NAME)?
+                        .map(|words| {
+                            words
+                                .split(',')
+                                .map(str::trim)
+                                .filter(|word| !word.is_empty())
+                                .map(str::to_owned)
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                }),
+                None => None,
+            },
+            playlist: match source.load(PLAYLIST_NAME)? {
+                Some(path) => load_playlist(Path::new(&path))?,
+                None => vec![],
+            },
+            poll_seconds: source
+                .load_number(POLL_SECONDS_NAME)?
+                .unwrap_or(DEFAULT_POLL_SECONDS),
+            helix: match source.load(API_TOKEN_NAME)? {
+                Some(token) => Some(HelixConfig {
+                    token: token.trim_start_matches("oauth:").to_owned(),
+                    refresh_token: source.load(REFRESH_TOKEN_NAME)?,
+                    client_id: source.load(CLIENT_ID_NAME)?,
+                    client_secret: source.load(CLIENT_SECRET_NAME)?,
+                    category: source
+                        .load(CATEGORY_NAME)?
+                        .unwrap_or_else(|| DEFAULT_CATEGORY.to_owned()),
+                    title: source
+                        .load(TITLE_NAME)?
+                        .unwrap_or_else(|| DEFAULT_TITLE.to_owned()),
+                    api_url: source
+                        .load(API_URL_NAME)?
+                        .unwrap_or_else(|| DEFAULT_API_URL.to_owned()),
+                    auth_url: source
+                        .load(AUTH_URL_NAME)?
+                        .unwrap_or_else(|| DEFAULT_AUTH_URL.to_owned()),
+                }),
+                None => None,
+            },
+            eventsub,
+            dictionary,
+            define_aloud: source.load_bool(DEFINE_ALOUD_NAME)?.unwrap_or(false),
+            control_api: match source.load(CONTROL_ADDR_NAME)? {
+                Some(addr) => Some(ControlApiConfig {
+                    addr: addr
+                        .trim()
+                        .parse()
+                        .map_err(|_| EbookError::InvalidControlAddress(addr.clone()))?,
+                    token: source
+                        .load(CONTROL_TOKEN_NAME)?
+                        .filter(|token| !token.trim().is_empty())
+                        .ok_or(EbookError::NoControlToken)?,
+                }),
+                None => None,
+            },
+            dashboard: source.load_bool(DASHBOARD_NAME)?.unwrap_or(false),
+        };
+
+        if config.eventsub.is_some() && config.helix.is_none() {
+            return Err(EbookError::NoTwitchApiToken(REWARDS_NAME));
+        }
+
+        Ok(config)
+    }
+
+    /// Everything [`EbookConfig::load`] does not open: the book, the
+    /// playlist, the music, the font, the sounds, the dictionary and the
+    /// characters
+    pub fn check(&self) -> EbookResult<()> {
+        let files = [(BOOK_NAME, &self.book)]
+            .into_iter()
+            .chain(self.playlist.iter().map(|book| (PLAYLIST_NAME, book)))
+            .chain(
+                self.music
+                    .iter()
+                    .flat_map(|music| &music.tracks)
+                    .map(|track| (MUSIC_NAME, track)),
+            )
+            .chain(self.font.iter().map(|font| (FONT_NAME, font)));
+        for (key, path) in files {
+            if !path.is_file() {
+                return Err(EbookError::MissingFile(key, path.clone()));
+            }
+        }
+
+        if let Some(sounds) = self.sounds.as_ref().filter(|sounds| !sounds.is_dir()) {
+            return Err(EbookError::MissingFile(SOUNDS_NAME, sounds.clone()));
+        }
+
+        if let Some(dictionary) = &self.dictionary {
+            Dictionary::open(dictionary)?;
+        }
+        Cast::open(self.dialogue.clone(), self.characters.as_deref())?;
+
+        Ok(())
+    }
+}
+
+/// One file per line, relative to the playlist. Empty lines and the ones
+/// starting with `#` are skipped.
+fn load_playlist(path: &Path) -> EbookResult<Vec<PathBuf>> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| EbookError::ReadPlaylist(path.to_owned(), err.to_string()))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line))
+        .collect())
+}
+
+/// Where each setting is read from, the first one that has it wins:
+/// command line, environment and config file
+#[derive(Debug, Default)]
+pub struct ConfigSource {
+    overrides: HashMap<&'static str, String>,
+    /// By environment variable name
+    file: HashMap<String, String>,
+}
+
+impl ConfigSource {
+    /// The config file of `--config`, or [`DEFAULT_CONFIG`] when it exists
+    pub fn new(cli: &Cli) -> EbookResult<Self> {
+        let path = match &cli.config {
+            Some(path) => Some(path.clone()),
+            None => Some(PathBuf::from(DEFAULT_CONFIG)).filter(|path| path.is_file()),
+        };
+
+        let mut file = HashMap::new();
+        if let Some(path) = path {
+            let table = fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|content| {
+                    content
+                        .parse::<toml::Table>()
+                        .map_err(|err| toml_error(&content, &err))
+                })
+                .map_err(|err| EbookError::ReadConfig(path, err))?;
+            flatten(&table, "", &mut file)?;
+        }
+
+        let mut overrides = HashMap::new();
+        for (key, value) in &cli.overrides {
+            let name = env_name(key).ok_or_else(|| EbookError::UnknownConfigKey(key.clone()))?;
+            if SECRET_NAMES.contains(&name) {
+                return Err(EbookError::SecretOnCommandLine(key.clone()));
+            }
+            overrides.insert(name, value.clone());
+        }
+
+        Ok(Self { overrides, file })
+    }
+
+    fn load(&self, key: &'static str) -> EbookResult<Option<String>> {
+        if !SECRET_NAMES.contains(&key) {
+            return self.load_plain(key);
+        }
+
+        let secret = self.load_secret(key)?;
+        if let Some(secret) = &secret {
+            logger::register_secret(secret);
+        }
+        Ok(secret)
+    }
+
+    /// The file in `{KEY}_FILE`, the systemd credential `KEY`, the
+    /// environment or the config file, where `key_file` works too
+    fn load_secret(&self, key: &'static str) -> EbookResult<Option<String>> {
+        if let Some(path) = env::var_os(format!("{key}_FILE")) {
+            return read_secret(key, Path::new(&path)).map(Some);
+        }
+
+        if let Some(dir) = env::var_os(CREDENTIALS_DIRECTORY_NAME) {
+            let path = Path::new(&dir).join(key);
+            if path.is_file() {
+                return read_secret(key, &path).map(Some);
+            }
+        }
+
+        if let Some(secret) = self.load_plain(key)? {
+            return Ok(Some(secret));
+        }
+
+        match self.file.get(&format!("{key}_FILE")) {
+            Some(path) => read_secret(key, Path::new(path)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn load_plain(&self, key: &'static str) -> EbookResult<Option<String>> {
+        if let Some(v) = self.overrides.get(key) {
+            return Ok(Some(v.clone()));
+        }
+
+        match env::var(key) {
+            Ok(v) => Ok(Some(v)),
+            Err(VarError::NotPresent) => Ok(self.file.get(key).cloned()),
+            Err(VarError::NotUnicode(_)) => Err(EbookError::InvalidEnvEncoding(key)),
+        }
+    }
+
+    fn load_bool(&self, key: &'static str) -> EbookResult<Option<bool>> {
+        let Some(v) = self.load(key)? else {
+            return Ok(None);
+        };
+
+        Ok(Some(&v != "0" && &v != "false"))
+    }
+
+    fn load_number<T: std::str::FromStr>(&self, key: &'static str) -> EbookResult<Option<T>> {
+        let Some(v) = self.load(key)? else {
+            return Ok(None);
+        };
+
+        v.trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| EbookError::InvalidNumber(key, v))
     }
 }
 
-fn load_bool(key: &'static str) -> EbookResult<Option<bool>> {
-    let Some(v) = load_env(key)? else {
-        return Ok(None);
+/// Seconds of silence, `default` when not set
+fn load_pause(source: &ConfigSource, key: &'static str, default: f32) -> EbookResult<Duration> {
+    let seconds = source.load_number(key)?.unwrap_or(default);
+    if !(0.0..=MAX_PAUSE_SECONDS).contains(&seconds) {
+        return Err(EbookError::InvalidNumber(key, seconds.to_string()));
+    }
+    Ok(Duration::from_secs_f32(seconds))
+}
+
+/// Where and why `content` is not valid TOML. The `Display` of `err` quotes
+/// the offending line, which may hold a secret.
+fn toml_error(content: &str, err: &toml::de::Error) -> String {
+    let Some(before) = err.span().and_then(|span| content.get(..span.start)) else {
+        return err.message().to_owned();
     };
 
-    Ok(Some(&v != "0" && &v != "false"))
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    format!(
+        "{} at line {}, column {}",
+        err.message(),
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1
+    )
+}
+
+fn read_secret(key: &'static str, path: &Path) -> EbookResult<String> {
+    fs::read_to_string(path)
+        .map(|secret| secret.trim().to_owned())
+        .map_err(|err| EbookError::ReadSecret(key, path.to_owned(), err.to_string()))
+}
+
+fn env_name(key: &str) -> Option<&'static str> {
+    CONFIG_KEYS
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, env)| *env)
 }
 
-fn load_env(key: &'static str) -> EbookResult<Option<String>> {
-    match env::var(key) {
-        Ok(v) => Ok(Some(v)),
-        Err(VarError::NotPresent) => Ok(None),
-        Err(VarError::NotUnicode(_)) => Err(EbookError::InvalidEnvEncoding(key)),
+/// Tables are sections, unless the key takes a table like `twitch.rewards`.
+/// Secrets can be given as a path with the `_file` suffix, like `stream.key_file`.
+fn flatten(
+    table: &toml::Table,
+    prefix: &str,
+    file: &mut HashMap<String, String>,
+) -> EbookResult<()> {
+    for (name, value) in table {
+        let key = match prefix {
+            "" => name.clone(),
+            prefix => format!("{prefix}.{name}"),
+        };
+
+        let secret_file = key
+            .strip_suffix("_file")
+            .and_then(env_name)
+            .filter(|env| SECRET_NAMES.contains(env))
+            .map(|env| format!("{env}_FILE"));
+
+        match (env_name(&key).map(str::to_owned).or(secret_file), value) {
+            (Some(env), value) => {
+                file.insert(env, to_env_value(value));
+            }
+            (None, toml::Value::Table(table)) => flatten(table, &key, file)?,
+            (None, _) => return Err(EbookError::UnknownConfigKey(key)),
+        }
+    }
+
+    Ok(())
+}
+
+/// The same format the environment variable takes: arrays are comma
+/// separated and tables `key=value` pairs
+fn to_env_value(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(v) => v.clone(),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(to_env_value)
+            .collect::<Vec<_>>()
+            .join(","),
+        toml::Value::Table(table) => table
+            .iter()
+            .map(|(key, v)| format!("{key}={}", to_env_value(v)))
+            .collect::<Vec<_>>()
+            .join(","),
+        v => v.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Only the stream key, which is required. The language is fixed,
+    /// gettext's `LANGUAGE` is often in the environment.
+    fn source(overrides: &[(&'static str, &str)]) -> ConfigSource {
+        ConfigSource {
+            overrides: [(LANGUAGE_NAME, "es")]
+                .iter()
+                .chain(overrides)
+                .map(|(key, value)| (*key, value.to_string()))
+                .collect(),
+            file: HashMap::from([(STREAM_KEY_NAME.to_owned(), "live_test".to_owned())]),
+        }
+    }
+
+    fn cli(config: Option<PathBuf>, overrides: &[(&str, &str)]) -> Cli {
+        Cli {
+            config,
+            overrides: overrides
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            ..Cli::default()
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        // A key of its own, nothing else reads it
+        const KEY: &str = "EBOOK_TEST_PLAIN";
+        let mut source = ConfigSource::default();
+        assert_eq!(source.load_plain(KEY).unwrap(), None);
+
+        source.file.insert(KEY.to_owned(), "file".to_owned());
+        assert_eq!(source.load_plain(KEY).unwrap().as_deref(), Some("file"));
+
+        env::set_var(KEY, "env");
+        let env = source.load_plain(KEY);
+        source.overrides.insert(KEY, "override".to_owned());
+        let overridden = source.load_plain(KEY);
+        env::remove_var(KEY);
+
+        assert_eq!(env.unwrap().as_deref(), Some("env"));
+        assert_eq!(overridden.unwrap().as_deref(), Some("override"));
+    }
+
+    #[test]
+    fn file_and_overrides() {
+        let path = testing::temp_dir().join("config.toml");
+        fs::write(
+            &path,
+            "[tts]\nlanguage = \"en\"\nspeed = 1.25\n\n\
+             [chat]\nbanned_words = [\"a\", \"b\"]\n\n\
+             [twitch.rewards]\nabc = \"voice\"\n",
+        )
+        .unwrap();
+
+        let source =
+            ConfigSource::new(&cli(Some(path.clone()), &[("tts.language", "fr")])).unwrap();
+        assert_eq!(source.file[LANGUAGE_NAME], "en");
+        assert_eq!(source.file[SPEED_NAME], "1.25");
+        assert_eq!(source.file[CHAT_BANNED_WORDS_NAME], "a,b");
+        assert_eq!(source.file[REWARDS_NAME], "abc=voice");
+        assert_eq!(
+            source.load_plain(LANGUAGE_NAME).unwrap().as_deref(),
+            Some("fr")
+        );
+
+        fs::write(&path, "[tts]\nlanguaje = \"en\"\n").unwrap();
+        let err = ConfigSource::new(&cli(Some(path), &[])).unwrap_err();
+        assert!(matches!(err, EbookError::UnknownConfigKey(key) if key == "tts.languaje"));
+    }
+
+    #[test]
+    fn no_secrets_on_the_command_line() {
+        for key in [
+            "stream.key",
+            "chat.token",
+            "twitch.client_secret",
+            "control.token",
+        ] {
+            let err = ConfigSource::new(&cli(None, &[(key, "secret")])).unwrap_err();
+            assert!(
+                matches!(&err, EbookError::SecretOnCommandLine(k) if k == key),
+                "{err}"
+            );
+        }
+
+        // Not even as a file
+        let err = ConfigSource::new(&cli(None, &[("stream.key_file", "key.txt")])).unwrap_err();
+        assert!(matches!(err, EbookError::UnknownConfigKey(_)));
+        let err = ConfigSource::new(&cli(None, &[("tts.nope", "1")])).unwrap_err();
+        assert!(matches!(err, EbookError::UnknownConfigKey(_)));
+    }
+
+    #[test]
+    fn out_of_range() {
+        for (key, value) in [
+            (SPEED_NAME, "0.4"),
+            (SPEED_NAME, "2.1"),
+            (SPEED_NAME, "fast"),
+            (PITCH_NAME, "3"),
+            (VOLUME_NAME, "-31"),
+            (VOLUME_NAME, "13"),
+            (DIALOGUE_PITCH_NAME, "0.1"),
+            (LOUDNESS_NAME, "-4"),
+            (LOUDNESS_NAME, "-41"),
+            (LOUDNESS_NAME, "loud"),
+            (SENTENCE_PAUSE_NAME, "-1"),
+            (PARAGRAPH_PAUSE_NAME, "NaN"),
+            (CHAPTER_PAUSE_NAME, "11"),
+        ] {
+            let err = EbookConfig::load(&source(&[(key, value)])).unwrap_err();
+            assert!(
+                matches!(err, EbookError::InvalidNumber(k, _) if k == key),
+                "{key}: {err}"
+            );
+        }
+
+        // Checked before the playlist is read
+        for (key, value) in [
+            (MUSIC_VOLUME_NAME, "1"),
+            (MUSIC_VOLUME_NAME, "-61"),
+            (MUSIC_DUCK_NAME, "-1"),
+            (MUSIC_DUCK_NAME, "41"),
+        ] {
+            let err =
+                EbookConfig::load(&source(&[(MUSIC_NAME, "music.m3u"), (key, value)])).unwrap_err();
+            assert!(
+                matches!(err, EbookError::InvalidNumber(k, _) if k == key),
+                "{key}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn range_limits() {
+        let config = EbookConfig::load(&source(&[
+            (SPEED_NAME, "0.5"),
+            (PITCH_NAME, "2"),
+            (VOLUME_NAME, " -30 "),
+            (LOUDNESS_NAME, ""),
+            (SENTENCE_PAUSE_NAME, "0"),
+            (CHAPTER_PAUSE_NAME, "10"),
+        ]))
+        .unwrap();
+
+        assert_eq!((config.speed, config.pitch, config.volume), (0.5, 2., -30.));
+        assert_eq!(config.mastering.loudness, None);
+        assert_eq!(config.mastering.sentence_pause, Duration::ZERO);
+        assert_eq!(config.mastering.chapter_pause, Duration::from_secs(10));
+
+        let config = EbookConfig::load(&source(&[(LOUDNESS_NAME, "-40")])).unwrap();
+        assert_eq!(config.mastering.loudness, Some(-40.));
+    }
+
+    #[test]
+    fn check_files() {
+        let book = testing::book(testing::BOOK);
+        let dir = book.parent().unwrap().to_owned();
+        let mut config = EbookConfig::load(&source(&[
+            (BOOK_NAME, book.to_str().unwrap()),
+            (FONT_NAME, ""),
+        ]))
+        .unwrap();
+        config.check().unwrap();
+
+        config.sounds = Some(dir.clone());
+        config.playlist = vec![book.clone()];
+        config.check().unwrap();
+
+        config.playlist.push(dir.join("missing.txt"));
+        let err = config.check().unwrap_err();
+        assert!(
+            matches!(err, EbookError::MissingFile(PLAYLIST_NAME, _)),
+            "{err}"
+        );
+        config.playlist.pop();
+
+        config.sounds = Some(book.clone());
+        let err = config.check().unwrap_err();
+        assert!(
+            matches!(err, EbookError::MissingFile(SOUNDS_NAME, _)),
+            "{err}"
+        );
+        config.sounds = None;
+
+        config.font = Some(dir.clone());
+        let err = config.check().unwrap_err();
+        assert!(
+            matches!(err, EbookError::MissingFile(FONT_NAME, _)),
+            "{err}"
+        );
+        config.font = None;
+
+        config.book = dir.join("missing.txt");
+        let err = config.check().unwrap_err();
+        assert!(
+            matches!(err, EbookError::MissingFile(BOOK_NAME, _)),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn parse_errors_hide_the_line() {
+        let path = testing::temp_dir().join("config.toml");
+        fs::write(&path, "[stream]\nkey = \"live_123456789\n").unwrap();
+        let cli = Cli {
+            config: Some(path),
+            ..Cli::default()
+        };
+
+        let err = ConfigSource::new(&cli).unwrap_err().to_string();
+        assert!(err.contains("at line 2, column"), "{err}");
+        assert!(!err.contains("live_"), "{err}");
+    }
+
+    #[test]
+    fn secret_precedence() {
+        // Keys of their own, nothing else reads them
+        const KEY: &str = "EBOOK_TEST_SECRET";
+        let dir = testing::temp_dir();
+        let write = |name: &str, secret: &str| {
+            let path = dir.join(name);
+            fs::write(&path, format!("{secret}\n")).unwrap();
+            path
+        };
+
+        let mut source = ConfigSource::default();
+        assert_eq!(source.load_secret(KEY).unwrap(), None);
+
+        source.file.insert(
+            format!("{KEY}_FILE"),
+            write("file", "from-file").display().to_string(),
+        );
+        assert_eq!(
+            source.load_secret(KEY).unwrap().as_deref(),
+            Some("from-file")
+        );
+
+        source.file.insert(KEY.to_owned(), "plain".to_owned());
+        assert_eq!(source.load_secret(KEY).unwrap().as_deref(), Some("plain"));
+
+        fs::create_dir(dir.join("credentials")).unwrap();
+        write("credentials/EBOOK_TEST_SECRET", "credential");
+        env::set_var(CREDENTIALS_DIRECTORY_NAME, dir.join("credentials"));
+        let credential = source.load_secret(KEY);
+
+        env::set_var(format!("{KEY}_FILE"), write("env", "from-env-file"));
+        let env_file = source.load_secret(KEY);
+        env::remove_var(format!("{KEY}_FILE"));
+        env::remove_var(CREDENTIALS_DIRECTORY_NAME);
+
+        assert_eq!(credential.unwrap().as_deref(), Some("credential"));
+        assert_eq!(env_file.unwrap().as_deref(), Some("from-env-file"));
     }
 }
@@ -0,0 +1,251 @@
+use std::path::PathBuf;
+use std::sync::MutexGuard;
+
+use log::info;
+
+use crate::audio::{AudioQueue, Sound, Sounds};
+use crate::error::{EbookError, EbookResult};
+use crate::render::SharedScene;
+use crate::session::{ReadingSession, SharedSession};
+use crate::tts::{
+    Locale, SharedVoice, Voice, MAX_PITCH, MAX_SPEED, MAX_VOLUME, MIN_PITCH, MIN_SPEED, MIN_VOLUME,
+    VOICES,
+};
+
+/// Actions on the running reader, shared by everything that can steer it
+/// (chat commands, operators). Keeps the session, the audio queue and the
+/// scene consistent with each other.
+#[derive(Debug, Clone)]
+pub struct ReaderControl {
+    session: SharedSession,
+    scene: SharedScene,
+    queue: AudioQueue,
+    voice: SharedVoice,
+    sounds: Sounds,
+}
+
+impl ReaderControl {
+    pub fn new(
+        session: SharedSession,
+        scene: SharedScene,
+        queue: AudioQueue,
+        voice: SharedVoice,
+        sounds: Sounds,
+    ) -> Self {
+        Self {
+            session,
+            scene,
+            queue,
+            voice,
+            sounds,
+        }
+    }
+
+    pub fn session(&self) -> MutexGuard<'_, ReadingSession> {
+        self.session.lock().unwrap()
+    }
+
+    /// Stop right away, the sentence being read is read again on resume
+    pub fn pause(&self) {
+        let mut session = self.session();
+        if session.is_paused() {
+            return;
+        }
+
+        let position = session.current_position();
+        session.set_paused(true);
+        self.cut_to(&mut session, position);
+        self.queue.set_idle();
+        info!("Reading paused");
+    }
+
+    pub fn resume(&self) {
+        let mut session = self.session();
+        if session.is_paused() {
+            session.set_paused(false);
+            info!("Reading resumed");
+        }
+    }
+
+    /// Over the narration, right away
+    pub fn play_sound(&self, sound: Sound) {
+        self.sounds.play(sound);
+    }
+
+    /// Jump to the next sentence
+    pub fn skip(&self) {
+        let mut session = self.session();
+        let position = session.current_position() + 1;
+        self.cut_to(&mut session, position);
+    }
+
+    /// Jump to the start of the next chapter
+    pub fn skip_chapter(&self) {
+        let mut session = self.session();
+        let next = session.current_chapter() + 1;
+        let position = match session.book().chapters.get(next) {
+            Some(chapter) => chapter.first_chunk,
+            None => session.book().chunks.len(),
+        };
+        self.cut_to(&mut session, position);
+    }
+
+    /// Jump to the start of `chapter`, an index in
+    /// [`crate::book::Book::chapters`]
+    pub fn seek_chapter(&self, chapter: usize) {
+        let mut session = self.session();
+        let Some(position) = session
+            .book()
+            .chapters
+            .get(chapter)
+            .map(|chapter| chapter.first_chunk)
+        else {
+            return;
+        };
+        self.cut_to(&mut session, position);
+    }
+
+    /// Read again the paragraph being read, or the previous one if the
+    /// current one just started
+    pub fn recap(&self) {
+        let mut session = self.session();
+        let chunks = &session.book().chunks;
+        let Some(current) = chunks.len().checked_sub(1) else {
+            return;
+        };
+        let current = session.current_position().min(current);
+
+        let paragraph_start = |mut start: usize| {
+            let paragraph = chunks[start].paragraph;
+            while start > 0 && chunks[start - 1].paragraph == paragraph {
+                start -= 1;
+            }
+            start
+        };
+
+        let mut start = paragraph_start(current);
+        if start == current && start > 0 {
+            start = paragraph_start(start - 1);
+        }
+
+        info!("Recap from chunk {start}");
+        self.cut_to(&mut session, start);
+    }
+
+    pub fn voice(&self) -> Voice {
+        self.voice.lock().unwrap().clone()
+    }
+
+    /// Use the gTTS server `tld` from the next sentence on, one of
+    /// [`VOICES`]
+    pub fn set_voice(&self, tld: &str) -> EbookResult<()> {
+        if !VOICES.contains(&tld) {
+            return Err(EbookError::InvalidVoice(tld.to_owned()));
+        }
+
+        self.voice.lock().unwrap().tld = tld.to_owned();
+        info!("Voice {tld}");
+        Ok(())
+    }
+
+    pub fn next_voice(&self) {
+        let mut voice = self.voice.lock().unwrap();
+        voice.next();
+        info!("Voice {}", voice.tld);
+    }
+
+    /// Tempo from the next sentence on, clamped to a sane range. NaN and
+    /// infinities are ignored.
+    pub fn set_speed(&self, speed: f32) {
+        if !speed.is_finite() {
+            return;
+        }
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.voice.lock().unwrap().speed = speed;
+        info!("Speed {speed}");
+    }
+
+    /// Pitch from the next sentence on, the tempo stays
+    pub fn set_pitch(&self, pitch: f32) {
+        if !pitch.is_finite() {
+            return;
+        }
+        let pitch = pitch.clamp(MIN_PITCH, MAX_PITCH);
+        self.voice.lock().unwrap().pitch = pitch;
+        info!("Pitch {pitch}");
+    }
+
+    /// Gain in dB from the next sentence on
+    pub fn set_volume(&self, volume: f32) {
+        if !volume.is_finite() {
+            return;
+        }
+        let volume = volume.clamp(MIN_VOLUME, MAX_VOLUME);
+        self.voice.lock().unwrap().volume = volume;
+        info!("Volume {volume} dB");
+    }
+
+    /// Also stops detecting the language of the book
+    pub fn set_language(&self, locale: Locale) {
+        info!("Language {locale}");
+        let mut voice = self.voice.lock().unwrap();
+        voice.language = locale.language;
+        if let Some(tld) = locale.tld() {
+            voice.tld = tld.to_owned();
+        }
+        voice.detect = false;
+    }
+
+    /// Read `path` after the books already enqueued
+    pub fn enqueue(&self, path: PathBuf) {
+        self.session().enqueue(path);
+    }
+
+    /// Read `path` when the current book ends, before any other enqueued book
+    pub fn play_next(&self, path: PathBuf) {
+        self.session().play_next(path);
+    }
+
+    /// Read `pieces` between the current sentence and the next one, instead
+    /// of the aside still pending
+    pub fn set_aside(&self, pieces: Vec<String>) {
+        self.session().set_aside(pieces);
+    }
+
+    pub fn seek(&self, position: usize) {
+        let mut session = self.session();
+        self.cut_to(&mut session, position);
+    }
+
+    fn cut_to(&self, session: &mut ReadingSession, position: usize) {
+        session.seek(position);
+        self.queue.clear();
+        session.update_scene(&mut self.scene.lock());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn voice_settings() {
+        let control = testing::control();
+
+        control.set_speed(f32::NAN);
+        control.set_pitch(f32::INFINITY);
+        control.set_volume(f32::NEG_INFINITY);
+        let voice = control.voice();
+        assert_eq!((voice.speed, voice.pitch, voice.volume), (1.0, 1.0, 0.0));
+
+        control.set_speed(10.0);
+        control.set_pitch(0.1);
+        let voice = control.voice();
+        assert_eq!((voice.speed, voice.pitch), (MAX_SPEED, MIN_PITCH));
+
+        assert!(control.set_voice("com.mx").is_ok());
+        assert!(control.set_voice("fr").is_err());
+        assert_eq!(control.voice().tld, "com.mx");
+    }
+}
@@ -0,0 +1,476 @@
+use std::collections::VecDeque;
+use std::io::{self, Stdout, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::ExecutableCommand;
+use log::warn;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Gauge, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+
+use crate::api::{OutputHealth, SharedHealth};
+use crate::audio::AudioQueue;
+use crate::control::ReaderControl;
+use crate::encoder::AudioSettings;
+use crate::preview::PreviewHandle;
+use crate::shutdown::Shutdown;
+use crate::stats::{OutputStats, StatsSnapshot};
+use crate::tts::Voice;
+
+/// Lines kept by every [`Feed`]
+const MAX_FEED_LINES: usize = 1000;
+/// Lines printed to the terminal when the dashboard closes
+const LOG_TAIL: usize = 20;
+const UPCOMING_CHUNKS: usize = 5;
+/// Sentences jumped by the arrow keys
+const SEEK_STEP: usize = 10;
+const SPEED_STEP: f32 = 0.1;
+const PITCH_STEP: f32 = 0.05;
+/// In dB
+const VOLUME_STEP: f32 = 1.0;
+const TICK: Duration = Duration::from_millis(250);
+/// Window of the fps and bitrate rates
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Last lines of something, shared between whoever writes them and the
+/// dashboard
+#[derive(Debug, Clone, Default)]
+pub struct Feed {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    /// Set while the dashboard shows the feed
+    open: Arc<AtomicBool>,
+}
+
+impl Feed {
+    pub fn push(&self, line: String) {
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == MAX_FEED_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::SeqCst)
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Full screen view of the reader for the operator, with keys to steer it.
+/// Logs go to its scrollback pane while it is open.
+pub struct Dashboard {
+    pub control: ReaderControl,
+    pub queue: AudioQueue,
+    pub audio: AudioSettings,
+    pub health: SharedHealth,
+    pub stats: OutputStats,
+    pub preview: Option<PreviewHandle>,
+    pub logs: Feed,
+    pub chat: Feed,
+}
+
+/// Closes the dashboard and gives the terminal back when dropped, also on
+/// errors
+pub struct DashboardHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    logs: Feed,
+}
+
+impl Drop for DashboardHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
+
+        // Whatever the scrollback had is gone with the alternate screen
+        let lines = self.logs.lines();
+        let mut stderr = io::stderr().lock();
+        for line in &lines[lines.len().saturating_sub(LOG_TAIL)..] {
+            _ = writeln!(stderr, "{line}");
+        }
+    }
+}
+
+struct View {
+    header: String,
+    progress: f32,
+    status: String,
+    current: String,
+    upcoming: Vec<String>,
+    voice: Voice,
+    buffered: f32,
+    underruns: u64,
+    health: OutputHealth,
+    fps: f32,
+    kbps: f32,
+    late_frames: u64,
+    preview: Option<bool>,
+    uptime: Duration,
+}
+
+impl Dashboard {
+    pub fn spawn(self, shutdown: Shutdown) -> io::Result<DashboardHandle> {
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        terminal::enable_raw_mode()?;
+        io::stdout().execute(EnterAlternateScreen)?;
+        terminal.clear()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let logs = self.logs.clone();
+        logs.open.store(true, Ordering::SeqCst);
+
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let result = self.run(&mut terminal, &stop, &shutdown);
+                self.logs.open.store(false, Ordering::SeqCst);
+                restore_terminal();
+
+                if let Err(err) = result {
+                    warn!("Dashboard closed: {err}");
+                }
+            })
+        };
+
+        Ok(DashboardHandle {
+            stop,
+            thread: Some(thread),
+            logs,
+        })
+    }
+
+    fn run(
+        &self,
+        terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+        stop: &AtomicBool,
+        shutdown: &Shutdown,
+    ) -> io::Result<()> {
+        let started = Instant::now();
+        // Start of the rate window and the counters then
+        let mut window = (Instant::now(), self.stats.snapshot());
+        let (mut fps, mut kbps) = (0., 0.);
+        // Log lines hidden under the bottom of the pane
+        let mut scroll = 0;
+
+        while !stop.load(Ordering::SeqCst) {
+            if window.0.elapsed() >= RATE_WINDOW {
+                let snapshot = self.stats.snapshot();
+                (fps, kbps) = snapshot.rates_since(&window.1, window.0.elapsed());
+                window = (Instant::now(), snapshot);
+            }
+
+            let view = self.view(started, &window.1, fps, kbps);
+            let logs = self.logs.lines();
+            let chat = self.chat.lines();
+            terminal.draw(|frame| draw(frame, &view, &logs, &chat, scroll))?;
+
+            if !event::poll(TICK)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    // Raw mode eats SIGINT, a second one still forces the exit
+                    if shutdown.is_requested() {
+                        restore_terminal();
+                        std::process::exit(1);
+                    }
+                    shutdown.request();
+                }
+                KeyCode::Char('q') => shutdown.request(),
+                KeyCode::Char(' ') | KeyCode::Char('p') => {
+                    if self.control.session().is_paused() {
+                        self.control.resume();
+                    } else {
+                        self.control.pause();
+                    }
+                }
+                KeyCode::Char('s') => self.control.skip(),
+                KeyCode::Char('c') => self.control.skip_chapter(),
+                KeyCode::Char('r') => self.control.recap(),
+                KeyCode::Left => {
+                    let position = self.control.session().current_position();
+                    self.control.seek(position.saturating_sub(SEEK_STEP));
+                }
+                KeyCode::Right => {
+                    let position = self.control.session().current_position();
+                    self.control.seek(position + SEEK_STEP);
+                }
+                KeyCode::Char('v') => self.control.next_voice(),
+                KeyCode::Char('+') => self
+                    .control
+                    .set_speed(self.control.voice().speed + SPEED_STEP),
+                KeyCode::Char('-') => self
+                    .control
+                    .set_speed(self.control.voice().speed - SPEED_STEP),
+                KeyCode::Char(']') => self
+                    .control
+                    .set_pitch(self.control.voice().pitch + PITCH_STEP),
+                KeyCode::Char('[') => self
+                    .control
+                    .set_pitch(self.control.voice().pitch - PITCH_STEP),
+                KeyCode::Char('>') => self
+                    .control
+                    .set_volume(self.control.voice().volume + VOLUME_STEP),
+                KeyCode::Char('<') => self
+                    .control
+                    .set_volume(self.control.voice().volume - VOLUME_STEP),
+                KeyCode::Char('w') => {
+                    if let Some(preview) = &self.preview {
+                        preview.toggle();
+                    }
+                }
+                KeyCode::Up => scroll = (scroll + 1).min(logs.len()),
+                KeyCode::Down => scroll = scroll.saturating_sub(1),
+                KeyCode::PageUp => scroll = (scroll + 10).min(logs.len()),
+                KeyCode::PageDown => scroll = scroll.saturating_sub(10),
+                KeyCode::End => scroll = 0,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn view(&self, started: Instant, stats: &StatsSnapshot, fps: f32, kbps: f32) -> View {
+        let samples_per_second = (self.audio.sample_rate * self.audio.channels).max(1) as f32;
+        let session = self.control.session();
+        let book = session.book();
+        let position = session.current_position();
+
+        let header = match book.chapters.get(session.current_chapter()) {
+            Some(chapter) if chapter.number.is_some() => format!("{} — {chapter}", book.title),
+            _ => book.title.clone(),
+        };
+        let mut status = format!(
+            "{:.1}% · {position}/{} · {} books next",
+            session.progress() * 100.,
+            book.chunks.len(),
+            session.upcoming().len()
+        );
+        if session.is_paused() {
+            status.push_str(" · PAUSED");
+        }
+
+        View {
+            header,
+            progress: session.progress(),
+            status,
+            current: session
+                .playing()
+                .map(|chunk| chunk.text.clone())
+                .unwrap_or_default(),
+            upcoming: book
+                .chunks
+                .iter()
+                .skip(session.position())
+                .take(UPCOMING_CHUNKS)
+                .map(|chunk| chunk.text.clone())
+                .collect(),
+            voice: self.control.voice(),
+            buffered: self.queue.len() as f32 / samples_per_second,
+            underruns: self.queue.underruns(),
+            health: self.health.lock().unwrap().clone(),
+            fps,
+            kbps,
+            late_frames: stats.late_frames,
+            preview: self.preview.as_ref().map(PreviewHandle::is_enabled),
+            uptime: started.elapsed(),
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, view: &View, logs: &[String], chat: &[String], scroll: usize) {
+    let [header, middle, bottom, help] = split(
+        Direction::Vertical,
+        frame.size(),
+        [
+            Constraint::Length(3),
+            Constraint::Min(10),
+            Constraint::Percentage(35),
+            Constraint::Length(1),
+        ],
+    );
+    let [left, right] = split(
+        Direction::Horizontal,
+        middle,
+        [Constraint::Percentage(60), Constraint::Percentage(40)],
+    );
+    let [reading, upcoming] = split(
+        Direction::Vertical,
+        left,
+        [Constraint::Percentage(50), Constraint::Percentage(50)],
+    );
+    let [output, chat_area] = split(
+        Direction::Vertical,
+        right,
+        [Constraint::Length(9), Constraint::Min(3)],
+    );
+
+    frame.render_widget(
+        Gauge::default()
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(view.header.as_str()),
+            )
+            .gauge_style(Style::default().fg(Color::Red))
+            .ratio(view.progress.clamp(0., 1.) as f64)
+            .label(view.status.as_str()),
+        header,
+    );
+
+    frame.render_widget(
+        Paragraph::new(view.current.as_str())
+            .style(Style::default().add_modifier(Modifier::BOLD))
+            .wrap(Wrap { trim: true })
+            .block(pane("Reading")),
+        reading,
+    );
+
+    let upcoming_lines = view
+        .upcoming
+        .iter()
+        .map(|text| Line::from(format!("· {text}")))
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        Paragraph::new(upcoming_lines)
+            .wrap(Wrap { trim: true })
+            .block(pane("Up next")),
+        upcoming,
+    );
+
+    let health = &view.health;
+    let state_color = if health.state == "Playing" {
+        Color::Green
+    } else {
+        Color::Red
+    };
+    let mut output_lines = vec![
+        Line::from(vec![
+            Span::raw("State    "),
+            Span::styled(health.state.clone(), Style::default().fg(state_color)),
+        ]),
+        Line::from(format!(
+            "Video    {:.1} fps · {:.0} kbps",
+            view.fps, view.kbps
+        )),
+        Line::from(format!("Late     {} frames", view.late_frames)),
+        Line::from(format!(
+            "Audio    {:.1}s buffered · {} underruns",
+            view.buffered, view.underruns
+        )),
+        Line::from(format!(
+            "Voice    {} {} · x{:.2} · ^{:.2} · {:+.1} dB",
+            view.voice.language,
+            view.voice.tld,
+            view.voice.speed,
+            view.voice.pitch,
+            view.voice.volume
+        )),
+        Line::from(format!(
+            "Uptime   {} · warnings {}",
+            format_duration(view.uptime),
+            health.warnings
+        )),
+    ];
+    if let Some(preview) = view.preview {
+        output_lines.push(Line::from(format!(
+            "Preview  {}",
+            if preview { "on" } else { "off" }
+        )));
+    }
+    frame.render_widget(Paragraph::new(output_lines).block(pane("Output")), output);
+
+    frame.render_widget(
+        Paragraph::new(tail(chat, chat_area, 0).to_vec().join("\n"))
+            .wrap(Wrap { trim: false })
+            .block(pane("Chat")),
+        chat_area,
+    );
+
+    let log_lines = tail(logs, bottom, scroll)
+        .iter()
+        .map(|line| {
+            let color = match line.split_whitespace().next() {
+                Some("ERROR") => Color::Red,
+                Some("WARN") => Color::Yellow,
+                Some("DEBUG" | "TRACE") => Color::DarkGray,
+                _ => Color::Reset,
+            };
+            Line::styled(line.as_str(), Style::default().fg(color))
+        })
+        .collect::<Vec<_>>();
+    let title = if scroll > 0 {
+        format!("Logs (-{scroll})")
+    } else {
+        "Logs".to_owned()
+    };
+    frame.render_widget(Paragraph::new(log_lines).block(pane(&title)), bottom);
+
+    frame.render_widget(
+        Paragraph::new(
+            "space pause · s skip · c chapter · ←/→ seek · r recap · v voice · +/- speed · \
+            [/] pitch · </> volume · w preview · ↑/↓ logs · q quit",
+        )
+        .style(Style::default().fg(Color::DarkGray)),
+        help,
+    );
+}
+
+fn split<const N: usize>(
+    direction: Direction,
+    area: Rect,
+    constraints: [Constraint; N],
+) -> [Rect; N] {
+    let chunks = Layout::default()
+        .direction(direction)
+        .constraints(constraints)
+        .split(area);
+    std::array::from_fn(|idx| chunks[idx])
+}
+
+fn pane(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+/// The lines that fit in `area`, `scroll` lines up from the last one
+fn tail<'a>(lines: &'a [String], area: Rect, scroll: usize) -> &'a [String] {
+    let height = area.height.saturating_sub(2) as usize;
+    let end = lines.len().saturating_sub(scroll);
+    &lines[end.saturating_sub(height)..end]
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn restore_terminal() {
+    _ = terminal::disable_raw_mode();
+    _ = io::stdout().execute(LeaveAlternateScreen);
+}
@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use log::info;
+
+use crate::book::Chunk;
+use crate::error::{EbookError, EbookResult};
+use crate::tts::{Languages, MAX_PITCH, MIN_PITCH, VOICES};
+
+/// Em-dash, horizontal bar and the `--` of plain text editions
+const DASHES: &[&str] = &["—", "―", "--"];
+/// Languages whose dialogue starts the paragraph with a dash, in the
+/// others it is only a parenthesis of the narration
+const DASH_DIALOGUE: &[Languages] = &[
+    Languages::Spanish,
+    Languages::Catalan,
+    Languages::Portuguese,
+    Languages::French,
+    Languages::Italian,
+    Languages::Russian,
+    Languages::Ukrainian,
+    Languages::Bulgarian,
+    Languages::Polish,
+    Languages::Swedish,
+];
+/// Opening and closing quotes
+const QUOTES: &[(char, char)] = &[('«', '»'), ('“', '”'), ('"', '"')];
+const ENGLISH_QUOTES: &[(char, char)] = &[('“', '”'), ('"', '"')];
+/// Low opening quotes and guillemets pointing inwards, „so“ and »so«
+const GERMAN_QUOTES: &[(char, char)] = &[('„', '“'), ('»', '«'), ('"', '"')];
+const GERMAN_LANGUAGES: &[Languages] = &[
+    Languages::German,
+    Languages::Danish,
+    Languages::Czech,
+    Languages::Slovak,
+];
+
+/// A piece of a chunk read by a single voice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    /// Byte range in the chunk text, without dashes or quotes
+    pub range: Range<usize>,
+    pub speech: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Narration,
+    /// The paragraph started with a dash, the next one opens an aside
+    Dash,
+    /// "—dijo Iturri—", the next dash goes back to the dialogue
+    Aside,
+    /// Waiting for the closing quote
+    Quote(char),
+}
+
+/// Splits the sentences of a paragraph in narration and speech. Sentences
+/// must be given in order, an open quote or dash goes on in the next one.
+#[derive(Debug)]
+pub struct Parser {
+    quotes: &'static [(char, char)],
+    dash_dialogue: bool,
+    state: State,
+    first: bool,
+    has_speech: bool,
+    /// Capitalized words of the narration, the speaker is one of them.
+    /// Nothing tells a name from any other word starting a sentence, so
+    /// "Entonces" or "Y" are collected too. They only matter if they are
+    /// in the characters file.
+    names: Vec<String>,
+}
+
+impl Parser {
+    /// Conventions of `language`, most languages when it is unknown
+    pub fn new(language: Option<Languages>) -> Self {
+        let quotes = match language {
+            Some(Languages::English) => ENGLISH_QUOTES,
+            Some(language) if GERMAN_LANGUAGES.contains(&language) => GERMAN_QUOTES,
+            _ => QUOTES,
+        };
+        let dash_dialogue = match language {
+            Some(language) => DASH_DIALOGUE.contains(&language),
+            None => true,
+        };
+
+        Self {
+            quotes,
+            dash_dialogue,
+            state: State::Narration,
+            first: true,
+            has_speech: false,
+            names: vec![],
+        }
+    }
+
+    /// Narration and speech of `sentence`, empty when it is all narration
+    pub fn parse(&mut self, sentence: &str) -> Vec<Part> {
+        let mut parts = vec![];
+        let mut start = 0;
+
+        if std::mem::take(&mut self.first) && self.dash_dialogue {
+            if let Some(len) = dash_len(sentence) {
+                self.state = State::Dash;
+                start = len;
+            }
+        }
+
+        let mut idx = start;
+        while let Some(c) = sentence[idx..].chars().next() {
+            match self.transition(&sentence[idx..], c) {
+                Some((len, state)) => {
+                    self.push(&mut parts, sentence, start..idx);
+                    self.state = state;
+                    idx += len;
+                    start = idx;
+                }
+                None => idx += c.len_utf8(),
+            }
+        }
+        self.push(&mut parts, sentence, start..sentence.len());
+
+        if parts.iter().any(|part| part.speech) {
+            parts
+        } else {
+            vec![]
+        }
+    }
+
+    /// Who may be speaking in the paragraph, none when nobody speaks
+    pub fn speakers(self) -> Vec<String> {
+        if self.has_speech {
+            self.names
+        } else {
+            vec![]
+        }
+    }
+
+    /// Length of the mark at the start of `rest` and the state after it
+    fn transition(&self, rest: &str, c: char) -> Option<(usize, State)> {
+        match self.state {
+            State::Dash => Some((dash_len(rest)?, State::Aside)),
+            State::Aside => Some((dash_len(rest)?, State::Dash)),
+            State::Narration => self
+                .quotes
+                .iter()
+                .find(|(open, _)| *open == c)
+                .map(|(_, close)| (c.len_utf8(), State::Quote(*close))),
+            State::Quote(close) => (c == close).then_some((c.len_utf8(), State::Narration)),
+        }
+    }
+
+    /// Without the punctuation left by the marks, "—dijo—. Ven" is "Ven"
+    fn push(&mut self, parts: &mut Vec<Part>, sentence: &str, range: Range<usize>) {
+        let text = &sentence[range.clone()];
+        let trimmed = text
+            .trim_start_matches(|c: char| c.is_whitespace() || matches!(c, '.' | ',' | ';' | ':'));
+        let start = range.start + text.len() - trimmed.len();
+        let end = start + trimmed.trim_end().len();
+        if !trimmed.chars().any(char::is_alphanumeric) {
+            return;
+        }
+
+        let speech = matches!(self.state, State::Dash | State::Quote(_));
+        if speech {
+            self.has_speech = true;
+        } else {
+            let names = trimmed
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| word.starts_with(char::is_uppercase))
+                .map(str::to_owned);
+            self.names.extend(names);
+        }
+
+        parts.push(Part {
+            range: start..end,
+            speech,
+        });
+    }
+}
+
+fn dash_len(text: &str) -> Option<usize> {
+    DASHES
+        .iter()
+        .find(|dash| text.starts_with(*dash))
+        .map(|dash| dash.len())
+}
+
+/// How some dialogue sounds, what is not set is the narrator's voice
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Role {
+    /// gTTS server, it picks the accent
+    pub tld: Option<String>,
+    /// 1 is the pitch of the engine
+    pub pitch: Option<f32>,
+}
+
+/// Voices of the dialogue, by character when they are named in the
+/// narration around it, like "dijo Iturri"
+#[derive(Debug, Clone)]
+pub struct Cast {
+    /// Unknown characters, and what the characters do not set
+    dialogue: Role,
+    /// By name and alias
+    characters: HashMap<String, Role>,
+}
+
+impl Cast {
+    /// `characters` is a TOML table per character with its `voice` and
+    /// `pitch`, and other `names` it is called by
+    pub fn open(dialogue: Role, characters: Option<&Path>) -> EbookResult<Self> {
+        let mut cast = Self {
+            dialogue,
+            characters: HashMap::new(),
+        };
+        let Some(path) = characters else {
+            return Ok(cast);
+        };
+
+        let read_err = |err: String| EbookError::Characters(path.to_owned(), err);
+        let table = fs::read_to_string(path)
+            .map_err(|err| read_err(err.to_string()))?
+            .parse::<toml::Table>()
+            .map_err(|err| read_err(err.to_string()))?;
+
+        for (name, character) in &table {
+            let invalid = |field: &str| read_err(format!("invalid {field} of {name}"));
+            let character = character.as_table().ok_or_else(|| invalid("table"))?;
+
+            let tld = match character.get("voice") {
+                Some(voice) => {
+                    let voice = voice
+                        .as_str()
+                        .filter(|voice| VOICES.contains(voice))
+                        .ok_or_else(|| invalid("voice"))?;
+                    Some(voice.to_owned())
+                }
+                None => cast.dialogue.tld.clone(),
+            };
+            let pitch = match character.get("pitch") {
+                Some(pitch) => {
+                    let pitch = pitch
+                        .as_float()
+                        .or_else(|| pitch.as_integer().map(|pitch| pitch as f64))
+                        .map(|pitch| pitch as f32)
+                        .filter(|pitch| (MIN_PITCH..=MAX_PITCH).contains(pitch))
+                        .ok_or_else(|| invalid("pitch"))?;
+                    Some(pitch)
+                }
+                None => cast.dialogue.pitch,
+            };
+            let role = Role { tld, pitch };
+
+            let aliases = match character.get("names") {
+                Some(names) => names
+                    .as_array()
+                    .and_then(|names| names.iter().map(|name| name.as_str()).collect())
+                    .ok_or_else(|| invalid("names"))?,
+                None => vec![],
+            };
+            for alias in aliases.into_iter().chain([name.as_str()]) {
+                cast.characters.insert(alias.to_owned(), role.clone());
+            }
+        }
+        info!("{} characters loaded from {}", table.len(), path.display());
+
+        Ok(cast)
+    }
+
+    /// `chunk` split by who reads it, `None` is the narrator. The whole
+    /// chunk is narration when the dialogue would sound the same.
+    ///
+    /// All the speech of the paragraph goes to the first of its names that
+    /// is a character, so in a conversation between two of them both are
+    /// read with the same voice.
+    pub fn lines(&self, chunk: &Chunk) -> Vec<(Range<usize>, Option<Role>)> {
+        if self.dialogue == Role::default() && self.characters.is_empty() {
+            return vec![(0..chunk.text.len(), None)];
+        }
+
+        let role = chunk
+            .speakers
+            .iter()
+            .find_map(|name| self.characters.get(name))
+            .unwrap_or(&self.dialogue);
+        match &chunk.dialogue[..] {
+            [] => vec![(0..chunk.text.len(), None)],
+            parts => parts
+                .iter()
+                .map(|part| (part.range.clone(), part.speech.then(|| role.clone())))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// The speech and narration of every sentence, as text
+    fn parse(
+        language: Languages,
+        sentences: &[&str],
+    ) -> (Vec<Vec<(&'static str, String)>>, Vec<String>) {
+        let mut parser = Parser::new(Some(language));
+        let parts = sentences
+            .iter()
+            .map(|sentence| {
+                parser
+                    .parse(sentence)
+                    .into_iter()
+                    .map(|part| {
+                        let kind = if part.speech { "speech" } else { "narration" };
+                        (kind, sentence[part.range].to_owned())
+                    })
+                    .collect()
+            })
+            .collect();
+        (parts, parser.speakers())
+    }
+
+    fn parts(parts: &[(&'static str, &str)]) -> Vec<(&'static str, String)> {
+        parts
+            .iter()
+            .map(|(kind, text)| (*kind, text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn dash_with_aside() {
+        let (parsed, speakers) = parse(Languages::Spanish, &["—Ven —dijo Iturri—. Siéntate."]);
+
+        assert_eq!(
+            parsed,
+            [parts(&[
+                ("speech", "Ven"),
+                ("narration", "dijo Iturri"),
+                ("speech", "Siéntate."),
+            ])]
+        );
+        assert_eq!(speakers, ["Iturri"]);
+    }
+
+    #[test]
+    fn plain_text_dashes() {
+        let (parsed, _) = parse(
+            Languages::Spanish,
+            &["--¿Es de los nuestros?--preguntó Ochoa."],
+        );
+
+        assert_eq!(
+            parsed,
+            [parts(&[
+                ("speech", "¿Es de los nuestros?"),
+                ("narration", "preguntó Ochoa."),
+            ])]
+        );
+    }
+
+    #[test]
+    fn guillemets() {
+        let (parsed, speakers) = parse(
+            Languages::Spanish,
+            &["Entonces Ochoa dijo: «No vendré mañana». Y se fue."],
+        );
+
+        assert_eq!(
+            parsed,
+            [parts(&[
+                ("narration", "Entonces Ochoa dijo:"),
+                ("speech", "No vendré mañana"),
+                ("narration", "Y se fue."),
+            ])]
+        );
+        // Sentence-initial words are taken as names too
+        assert_eq!(speakers, ["Entonces", "Ochoa", "Y"]);
+    }
+
+    #[test]
+    fn german_quotes() {
+        let (parsed, _) = parse(
+            Languages::German,
+            &["Er sagte: „Ich komme morgen.“ Dann ging er."],
+        );
+
+        assert_eq!(
+            parsed,
+            [parts(&[
+                ("narration", "Er sagte:"),
+                ("speech", "Ich komme morgen."),
+                ("narration", "Dann ging er."),
+            ])]
+        );
+    }
+
+    #[test]
+    fn quote_over_several_sentences() {
+        let (parsed, speakers) = parse(
+            Languages::Spanish,
+            &[
+                "Lacy contestó: «No lo sé.",
+                "Quizá mañana.",
+                "Ya veremos». Y calló.",
+            ],
+        );
+
+        assert_eq!(
+            parsed,
+            [
+                parts(&[("narration", "Lacy contestó:"), ("speech", "No lo sé.")]),
+                parts(&[("speech", "Quizá mañana.")]),
+                parts(&[("speech", "Ya veremos"), ("narration", "Y calló.")]),
+            ]
+        );
+        assert_eq!(speakers, ["Lacy", "Y"]);
+    }
+
+    #[test]
+    fn narration_only() {
+        let (parsed, speakers) = parse(
+            Languages::English,
+            &["He wrote a few letters — not many — and went to bed."],
+        );
+
+        assert_eq!(parsed, [vec![]]);
+        assert!(speakers.is_empty());
+    }
+
+    #[test]
+    fn characters() {
+        let path = testing::temp_dir().join("characters.toml");
+        fs::write(
+            &path,
+            "[Iturri]\nvoice = \"com.mx\"\npitch = 0.85\nnames = [\"tío\"]\n\n[Lacy]\npitch = 1.2\n",
+        )
+        .unwrap();
+        let dialogue = Role {
+            tld: Some("es".to_owned()),
+            pitch: None,
+        };
+        let cast = Cast::open(dialogue.clone(), Some(&path)).unwrap();
+
+        let text = "—Ven —dijo Iturri—. Siéntate.";
+        let mut chunk = Chunk {
+            text: text.to_owned(),
+            chapter: 0,
+            paragraph: 0,
+            language: Some(Languages::Spanish),
+            dialogue: Parser::new(Some(Languages::Spanish)).parse(text),
+            speakers: vec!["Lacy".to_owned(), "Iturri".to_owned()],
+        };
+        let lacy = Role {
+            tld: Some("es".to_owned()),
+            pitch: Some(1.2),
+        };
+        // The first character named speaks all the paragraph
+        assert_eq!(
+            cast.lines(&chunk)
+                .into_iter()
+                .map(|(_, role)| role)
+                .collect::<Vec<_>>(),
+            [Some(lacy.clone()), None, Some(lacy)]
+        );
+
+        chunk.speakers = vec!["Entonces".to_owned(), "tío".to_owned()];
+        assert_eq!(
+            cast.lines(&chunk)[0].1,
+            cast.characters.get("Iturri").cloned()
+        );
+        chunk.speakers = vec!["Entonces".to_owned()];
+        assert_eq!(cast.lines(&chunk)[0].1, Some(dialogue));
+
+        fs::write(&path, "[Iturri]\nvoice = \"fr\"\n").unwrap();
+        assert!(Cast::open(Role::default(), Some(&path)).is_err());
+        fs::write(&path, "[Iturri]\npitch = 5\n").unwrap();
+        assert!(Cast::open(Role::default(), Some(&path)).is_err());
+    }
+}
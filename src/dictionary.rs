@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use log::info;
+use serde_json::Value;
+
+use crate::control::ReaderControl;
+use crate::error::{EbookError, EbookResult};
+use crate::render::SharedScene;
+use crate::renderizer::hot_lib::DefinitionCard;
+use crate::tts::{tokenize, GOOGLE_TTS_MAX_CHARS};
+
+/// Time the definition card stays on screen
+const CARD: Duration = Duration::from_secs(12);
+/// Longest definition read aloud, the card shows more
+const MAX_ALOUD_LEN: usize = 240;
+
+/// Word lookups in a local dictionary, either a JSON object
+/// (`{"word": "definition"}`) or an uncompressed StarDict (`.ifo`)
+#[derive(Debug)]
+pub enum Dictionary {
+    Json(HashMap<String, String>),
+    StarDict(StarDict),
+}
+
+impl Dictionary {
+    pub fn open(path: &Path) -> EbookResult<Self> {
+        let dictionary = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ifo") => Self::StarDict(StarDict::open(path)?),
+            _ => Self::Json(load_json(path)?),
+        };
+        info!("Dictionary {} loaded", path.display());
+
+        Ok(dictionary)
+    }
+
+    pub fn define(&self, word: &str) -> Option<String> {
+        let word = normalize(word);
+        if word.is_empty() {
+            return None;
+        }
+
+        match self {
+            Self::Json(words) => words.get(&word).cloned(),
+            Self::StarDict(dict) => dict.define(&word),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StarDict {
+    dict: PathBuf,
+    /// word -> offset and size in `dict`
+    index: HashMap<String, (u64, u64)>,
+    /// Type of the entries when they all share one, `m` is plain text
+    same_type: Option<u8>,
+}
+
+impl StarDict {
+    fn open(ifo: &Path) -> EbookResult<Self> {
+        let read_err = |path: &Path, err: &dyn std::fmt::Display| {
+            EbookError::Dictionary(path.to_owned(), err.to_string())
+        };
+
+        let info = fs::read_to_string(ifo).map_err(|err| read_err(ifo, &err))?;
+        let field = |name: &str| {
+            info.lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+        };
+        let offset_bits = field("idxoffsetbits").unwrap_or("32");
+        let same_type = field("sametypesequence").and_then(|types| types.bytes().next());
+
+        let idx = ifo.with_extension("idx");
+        let dict = ifo.with_extension("dict");
+        if !dict.exists() {
+            return Err(read_err(
+                &dict,
+                &"not found, compressed .dict.dz must be unpacked",
+            ));
+        }
+
+        let data = fs::read(&idx).map_err(|err| read_err(&idx, &err))?;
+        let offset_len = if offset_bits == "64" { 8 } else { 4 };
+        let mut index = HashMap::new();
+        let mut rest = data.as_slice();
+
+        while let Some(end) = rest.iter().position(|b| *b == 0) {
+            let word = String::from_utf8_lossy(&rest[..end]).to_lowercase();
+            rest = &rest[end + 1..];
+            if rest.len() < offset_len + 4 {
+                return Err(read_err(&idx, &"truncated index"));
+            }
+
+            let offset = be_number(&rest[..offset_len]);
+            let size = be_number(&rest[offset_len..offset_len + 4]);
+            rest = &rest[offset_len + 4..];
+
+            index.entry(word).or_insert((offset, size));
+        }
+
+        Ok(Self {
+            dict,
+            index,
+            same_type,
+        })
+    }
+
+    fn define(&self, word: &str) -> Option<String> {
+        let (offset, size) = *self.index.get(word)?;
+
+        let mut file = File::open(&self.dict).ok()?;
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        let mut data = vec![0; size as usize];
+        file.read_exact(&mut data).ok()?;
+
+        // Without a shared type every entry starts with its own
+        let text = match self.same_type {
+            Some(_) => &data[..],
+            None => data.get(1..)?,
+        };
+        let end = text.iter().position(|b| *b == 0).unwrap_or(text.len());
+
+        Some(String::from_utf8_lossy(&text[..end]).trim().to_owned())
+    }
+}
+
+/// Runs `!define`: shows the card and, if configured, reads the
+/// definition between sentences
+#[derive(Debug, Clone)]
+pub struct Definitions {
+    dictionary: Arc<Dictionary>,
+    scene: SharedScene,
+    control: ReaderControl,
+    aloud: bool,
+    /// Bumped on every card, so an old timer does not hide a newer card
+    generation: Arc<AtomicU64>,
+}
+
+impl Definitions {
+    pub fn new(
+        dictionary: Dictionary,
+        scene: SharedScene,
+        control: ReaderControl,
+        aloud: bool,
+    ) -> Self {
+        Self {
+            dictionary: Arc::new(dictionary),
+            scene,
+            control,
+            aloud,
+            generation: Arc::default(),
+        }
+    }
+
+    /// Returns the definition, `None` if the word is not in the dictionary
+    pub fn define(&self, word: &str) -> Option<String> {
+        let definition = self.dictionary.define(word)?;
+        let word = normalize(word);
+
+        self.show_card(&word, &definition);
+
+        if self.aloud {
+            let aloud = truncate_sentences(&definition, MAX_ALOUD_LEN);
+            // A burst of !define reads only the last definition
+            let pieces = tokenize(&format!("{word}: {aloud}"), GOOGLE_TTS_MAX_CHARS);
+            self.control.set_aside(pieces);
+        }
+
+        Some(definition)
+    }
+
+    fn show_card(&self, word: &str, definition: &str) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.scene.lock().definition = Some(DefinitionCard {
+            word: word.to_owned(),
+            definition: definition.to_owned(),
+        });
+
+        let scene = self.scene.clone();
+        let current = self.generation.clone();
+        thread::spawn(move || {
+            thread::sleep(CARD);
+            if current.load(Ordering::SeqCst) == generation {
+                scene.lock().definition = None;
+            }
+        });
+    }
+}
+
+/// `"¿Zascandil?"` -> `"zascandil"`
+fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+/// Whole sentences of `text` up to `max_len` bytes, at least the first one
+fn truncate_sentences(text: &str, max_len: usize) -> String {
+    let mut result = String::new();
+    for sentence in tokenize(text, max_len) {
+        if !result.is_empty() && result.len() + 1 + sentence.len() > max_len {
+            break;
+        }
+        if !result.is_empty() {
+            result.push(' ');
+        }
+        result.push_str(&sentence);
+    }
+    result
+}
+
+fn load_json(path: &Path) -> EbookResult<HashMap<String, String>> {
+    let read_err =
+        |err: &dyn std::fmt::Display| EbookError::Dictionary(path.to_owned(), err.to_string());
+
+    let content = fs::read_to_string(path).map_err(|err| read_err(&err))?;
+    let Value::Object(words) = serde_json::from_str(&content).map_err(|err| read_err(&err))? else {
+        return Err(read_err(&"expected an object of word -> definition"));
+    };
+
+    Ok(words
+        .into_iter()
+        .filter_map(|(word, definition)| {
+            let definition = match definition {
+                Value::String(definition) => definition,
+                // Several meanings
+                Value::Array(meanings) => meanings
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" | "),
+                _ => return None,
+            };
+            Some((word.to_lowercase(), definition))
+        })
+        .collect())
+}
+
+fn be_number(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |n, b| (n << 8) | *b as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// `.ifo`, `.idx` and `.dict` with `entries`, each one a word and the
+    /// bytes stored for it
+    fn star_dict(ifo: &str, offset_len: usize, entries: &[(&str, &[u8])]) -> PathBuf {
+        let dir = testing::temp_dir();
+        let (mut idx, mut dict) = (vec![], vec![]);
+        for (word, data) in entries {
+            idx.extend_from_slice(word.as_bytes());
+            idx.push(0);
+            idx.extend_from_slice(&(dict.len() as u64).to_be_bytes()[8 - offset_len..]);
+            idx.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            dict.extend_from_slice(data);
+        }
+
+        fs::write(dir.join("words.idx"), idx).unwrap();
+        fs::write(dir.join("words.dict"), dict).unwrap();
+        let path = dir.join("words.ifo");
+        fs::write(
+            &path,
+            format!("StarDict's dict ifo file\nversion=3.0.0\n{ifo}"),
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn star_dict_with_same_type() {
+        for (ifo, offset_len) in [
+            ("sametypesequence=m\n", 4),
+            ("idxoffsetbits=64\nsametypesequence=m\n", 8),
+        ] {
+            let path = star_dict(
+                ifo,
+                offset_len,
+                &[("Hidalgo", b"Persona noble. "), ("yelmo", b"Casco\0")],
+            );
+            let dictionary = Dictionary::open(&path).unwrap();
+
+            assert_eq!(
+                dictionary.define("¿hidalgo?").as_deref(),
+                Some("Persona noble."),
+                "{ifo}"
+            );
+            assert_eq!(dictionary.define("Yelmo").as_deref(), Some("Casco"));
+            assert_eq!(dictionary.define("adarga"), None);
+        }
+    }
+
+    #[test]
+    fn star_dict_with_type_per_entry() {
+        let path = star_dict("", 4, &[("adarga", b"mEscudo de cuero\0")]);
+        let dictionary = Dictionary::open(&path).unwrap();
+
+        assert_eq!(
+            dictionary.define("adarga").as_deref(),
+            Some("Escudo de cuero")
+        );
+    }
+
+    #[test]
+    fn star_dict_errors() {
+        let path = star_dict("", 4, &[("adarga", b"m")]);
+        fs::remove_file(path.with_extension("dict")).unwrap();
+        assert!(Dictionary::open(&path).is_err());
+
+        let path = star_dict("", 4, &[]);
+        fs::write(path.with_extension("idx"), b"adarga\0\0\0").unwrap();
+        assert!(Dictionary::open(&path).is_err());
+    }
+
+    #[test]
+    fn json_dictionary() {
+        let path = testing::temp_dir().join("words.json");
+        fs::write(
+            &path,
+            r#"{"Rocín": "Caballo de mala traza", "lanza": ["Arma", "Vara"], "x": 1}"#,
+        )
+        .unwrap();
+        let dictionary = Dictionary::open(&path).unwrap();
+
+        assert_eq!(
+            dictionary.define("rocín,").as_deref(),
+            Some("Caballo de mala traza")
+        );
+        assert_eq!(dictionary.define("lanza").as_deref(), Some("Arma | Vara"));
+        assert_eq!(dictionary.define("x"), None);
+        assert_eq!(dictionary.define("..."), None);
+
+        fs::write(&path, r#"["not", "an", "object"]"#).unwrap();
+        assert!(Dictionary::open(&path).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{EbookError, EbookResult};
+
+// Twitch ingest recommendations
+// https://help.twitch.tv/s/article/broadcasting-guidelines
+const TWITCH_MAX_VIDEO_BITRATE: u32 = 6000;
+const TWITCH_MAX_AUDIO_BITRATE: u32 = 160;
+const TWITCH_MAX_FRAMERATE: u32 = 60;
+const TWITCH_MAX_WIDTH: u32 = 1920;
+const TWITCH_MAX_HEIGHT: u32 = 1080;
+const TWITCH_KEYFRAME_SECONDS: u32 = 2;
+const TWITCH_AUDIO_RATES: &[u32] = &[44100, 48000];
+
+/// Named encoder presets, selected with the `ENCODER_PRESET` environment
+/// variable.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EncoderPreset {
+    /// 1280x720 @ 30fps, 2500kbps. For slow uplinks
+    #[default]
+    Low720p30,
+    /// 1280x720 @ 30fps, 4500kbps
+    Hd720p30,
+    /// 1920x1080 @ 30fps, 6000kbps
+    FullHd1080p30,
+    /// No video at all, only the narration
+    AudioOnly,
+}
+
+impl EncoderPreset {
+    pub const ALL: &'static [EncoderPreset] = &[
+        EncoderPreset::Low720p30,
+        EncoderPreset::Hd720p30,
+        EncoderPreset::FullHd1080p30,
+        EncoderPreset::AudioOnly,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Low720p30 => "720p30-low",
+            Self::Hd720p30 => "720p30",
+            Self::FullHd1080p30 => "1080p30",
+            Self::AudioOnly => "audio-only",
+        }
+    }
+}
+
+impl FromStr for EncoderPreset {
+    type Err = EbookError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|preset| preset.name() == s)
+            .ok_or_else(|| EbookError::UnknownEncoderPreset(s.to_owned()))
+    }
+}
+
+impl fmt::Display for EncoderPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VideoSettings {
+    pub width: u32,
+    pub height: u32,
+    pub framerate: u32,
+    /// x264 `speed-preset`
+    ///
+    /// example: "veryfast"
+    pub x264_preset: &'static str,
+    /// Bitrate in kbps
+    pub bitrate: u32,
+    /// Seconds between keyframes
+    pub keyframe_interval: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioSettings {
+    pub sample_rate: u32,
+    pub channels: u32,
+    /// AAC bitrate in kbps
+    pub bitrate: u32,
+}
+
+impl AudioSettings {
+    /// Interleaved f32 caps, the format used everywhere in the audio path
+    pub fn caps(&self) -> String {
+        format!(
+            "audio/x-raw,format=F32LE,layout=interleaved,rate={},channels={}",
+            self.sample_rate, self.channels
+        )
+    }
+}
+
+/// Every knob of the outgoing stream. This is the only place where
+/// resolution, framerate and bitrates are decided, the renderer, the
+/// pipeline caps and the muxer all read from here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderConfig {
+    pub preset: EncoderPreset,
+    /// `None` when streaming audio only
+    pub video: Option<VideoSettings>,
+    pub audio: AudioSettings,
+    /// Only render a new frame when the visible content changes, the last
+    /// frame is repeated in between
+    pub static_frames: bool,
+}
+
+impl EncoderConfig {
+    pub fn from_preset(preset: EncoderPreset) -> Self {
+        let video = |width, height, bitrate| VideoSettings {
+            width,
+            height,
+            framerate: 30,
+            x264_preset: "veryfast",
+            bitrate,
+            keyframe_interval: TWITCH_KEYFRAME_SECONDS,
+        };
+
+        let audio = AudioSettings {
+            sample_rate: 44100,
+            channels: 2,
+            bitrate: 128,
+        };
+
+        let video = match preset {
+            EncoderPreset::Low720p30 => Some(video(1280, 720, 2500)),
+            EncoderPreset::Hd720p30 => Some(video(1280, 720, 4500)),
+            EncoderPreset::FullHd1080p30 => Some(video(1920, 1080, 6000)),
+            EncoderPreset::AudioOnly => None,
+        };
+
+        Self {
+            preset,
+            video,
+            audio,
+            static_frames: false,
+        }
+    }
+
+    /// Check the settings against Twitch's ingest recommendations
+    pub fn validate(&self) -> EbookResult<()> {
+        let out_of_spec = |reason: String| Err(EbookError::EncoderOutOfSpec(reason));
+
+        if let Some(video) = &self.video {
+            if video.width > TWITCH_MAX_WIDTH || video.height > TWITCH_MAX_HEIGHT {
+                return out_of_spec(format!(
+                    "Resolution {}x{} is bigger than {TWITCH_MAX_WIDTH}x{TWITCH_MAX_HEIGHT}",
+                    video.width, video.height
+                ));
+            }
+
+            if video.width % 2 != 0 || video.height % 2 != 0 {
+                return out_of_spec(format!(
+                    "Resolution {}x{} should be even for yuv420p",
+                    video.width, video.height
+                ));
+            }
+
+            if video.framerate == 0 || video.framerate > TWITCH_MAX_FRAMERATE {
+                return out_of_spec(format!(
+                    "Framerate {} should be between 1 and {TWITCH_MAX_FRAMERATE}",
+                    video.framerate
+                ));
+            }
+
+            if video.bitrate > TWITCH_MAX_VIDEO_BITRATE {
+                return out_of_spec(format!(
+                    "Video bitrate {}kbps is bigger than {TWITCH_MAX_VIDEO_BITRATE}kbps",
+                    video.bitrate
+                ));
+            }
+
+            if video.keyframe_interval != TWITCH_KEYFRAME_SECONDS {
+                return out_of_spec(format!(
+                    "Keyframe interval should be {TWITCH_KEYFRAME_SECONDS} seconds, not {}",
+                    video.keyframe_interval
+                ));
+            }
+        }
+
+        if self.audio.bitrate > TWITCH_MAX_AUDIO_BITRATE {
+            return out_of_spec(format!(
+                "Audio bitrate {}kbps is bigger than {TWITCH_MAX_AUDIO_BITRATE}kbps",
+                self.audio.bitrate
+            ));
+        }
+
+        if !TWITCH_AUDIO_RATES.contains(&self.audio.sample_rate) {
+            return out_of_spec(format!(
+                "Audio sample rate {}Hz should be one of {TWITCH_AUDIO_RATES:?}",
+                self.audio.sample_rate
+            ));
+        }
+
+        if self.audio.channels == 0 || self.audio.channels > 2 {
+            return out_of_spec(format!(
+                "Audio should be mono or stereo, not {} channels",
+                self.audio.channels
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Raw video caps produced by the renderer
+    pub fn video_caps(&self) -> Option<String> {
+        self.video.map(|video| {
+            format!(
+                "video/x-raw,format=BGRA,width={},height={},framerate={}/1",
+                video.width, video.height, video.framerate
+            )
+        })
+    }
+
+    /// H.264 encoder element, CBR with the keyframe interval in frames
+    pub fn video_encoder(&self) -> Option<String> {
+        let tune = if self.static_frames {
+            "zerolatency+stillimage"
+        } else {
+            "zerolatency"
+        };
+
+        self.video.map(|video| {
+            format!(
+                "x264enc speed-preset={} tune={tune} pass=cbr bitrate={} key-int-max={}",
+                video.x264_preset,
+                video.bitrate,
+                video.framerate * video.keyframe_interval
+            )
+        })
+    }
+
+    /// Raw audio caps fed to the AAC encoder
+    pub fn audio_caps(&self) -> String {
+        self.audio.caps()
+    }
+
+    pub fn audio_encoder(&self) -> String {
+        format!("avenc_aac bitrate={}", self.audio.bitrate * 1000)
+    }
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        Self::from_preset(EncoderPreset::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn out_of_spec(config: EncoderConfig) -> String {
+        match config.validate() {
+            Err(EbookError::EncoderOutOfSpec(reason)) => reason,
+            other => panic!("{config:?}: {other:?}"),
+        }
+    }
+
+    fn with_video(change: impl Fn(&mut VideoSettings)) -> EncoderConfig {
+        let mut config = EncoderConfig::from_preset(EncoderPreset::FullHd1080p30);
+        change(config.video.as_mut().unwrap());
+        config
+    }
+
+    #[test]
+    fn presets() {
+        for preset in EncoderPreset::ALL {
+            assert_eq!(preset.name().parse::<EncoderPreset>().unwrap(), *preset);
+            assert_eq!(preset.to_string(), preset.name());
+            EncoderConfig::from_preset(*preset).validate().unwrap();
+        }
+        assert_eq!(EncoderConfig::default().preset, EncoderPreset::Low720p30);
+        assert_eq!(
+            EncoderConfig::from_preset(EncoderPreset::AudioOnly).video,
+            None
+        );
+
+        for name in ["", "720P30", "1080p60", " 720p30"] {
+            let err = name.parse::<EncoderPreset>().unwrap_err();
+            assert!(matches!(&err, EbookError::UnknownEncoderPreset(n) if n == name));
+            assert!(err
+                .to_string()
+                .contains("720p30-low, 720p30, 1080p30, audio-only"));
+        }
+    }
+
+    #[test]
+    fn keyframes_every_two_seconds() {
+        let config = EncoderConfig::default();
+        assert!(config
+            .video_encoder()
+            .unwrap()
+            .ends_with("bitrate=2500 key-int-max=60"));
+
+        let reason = out_of_spec(with_video(|video| video.keyframe_interval = 4));
+        assert!(reason.contains("should be 2 seconds, not 4"), "{reason}");
+        out_of_spec(with_video(|video| video.keyframe_interval = 0));
+    }
+
+    #[test]
+    fn twitch_limits() {
+        with_video(|video| video.framerate = 60).validate().unwrap();
+        with_video(|video| video.bitrate = 6000).validate().unwrap();
+
+        let changes: &[fn(&mut VideoSettings)] = &[
+            |video| video.width = 2560,
+            |video| video.height = 1440,
+            |video| video.width = 1279,
+            |video| video.height = 719,
+            |video| video.framerate = 0,
+            |video| video.framerate = 61,
+            |video| video.bitrate = 6001,
+        ];
+        for change in changes {
+            out_of_spec(with_video(change));
+        }
+
+        let audio = |change: fn(&mut AudioSettings)| {
+            let mut config = EncoderConfig::from_preset(EncoderPreset::AudioOnly);
+            change(&mut config.audio);
+            config
+        };
+        audio(|audio| audio.sample_rate = 48000).validate().unwrap();
+        audio(|audio| audio.channels = 1).validate().unwrap();
+        audio(|audio| audio.bitrate = 160).validate().unwrap();
+
+        assert!(out_of_spec(audio(|audio| audio.bitrate = 320)).contains("160kbps"));
+        assert!(out_of_spec(audio(|audio| audio.sample_rate = 22050)).contains("22050Hz"));
+        assert!(out_of_spec(audio(|audio| audio.channels = 0)).contains("0 channels"));
+        assert!(out_of_spec(audio(|audio| audio.channels = 6)).contains("6 channels"));
+    }
+}
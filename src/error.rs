@@ -1,15 +1,64 @@
 use std::error::Error;
 use std::fmt;
+use std::path::PathBuf;
+
+use crate::cli::USAGE;
+use crate::encoder::EncoderPreset;
+use crate::preview::DEFAULT_PREVIEW_ADDR;
+use crate::tts::VOICES;
 
 #[derive(Debug, Clone)]
 pub enum EbookError {
     // Config
     InvalidEnvEncoding(&'static str),
     NoTwitchStreamKey,
+    InvalidLanguage(String),
+    InvalidNumber(&'static str, String),
+    InvalidReward(String),
+    InvalidVoice(String),
+    NoTwitchApiToken(&'static str),
+    InvalidControlAddress(String),
+    NoControlToken,
+    InvalidArgument(String),
+    ReadConfig(PathBuf, String),
+    UnknownConfigKey(String),
+    MissingFile(&'static str, PathBuf),
+    SecretOnCommandLine(String),
+    ReadSecret(&'static str, PathBuf, String),
+
+    // Encoder
+    UnknownEncoderPreset(String),
+    EncoderOutOfSpec(String),
+
+    // Preview
+    InvalidPreviewMode(String),
+    InvalidPreviewAddress(String),
+    PublicPreviewAddress(String),
+    PreviewServer(String),
+
+    // Control API
+    ControlServer(String),
+
+    // Reading
+    ReadBook(PathBuf, String),
+    Bookmark(PathBuf, String),
+    ReadPlaylist(PathBuf, String),
+    Dictionary(PathBuf, String),
+    Characters(PathBuf, String),
+    Captions(PathBuf, String),
+    Tts(String),
+    AudioDecode(String),
+
+    // Twitch API
+    Helix(String),
+
+    // Shutdown
+    Signal(String),
 
     // Glib
     Glib(glib::Error),
     GlibBool(glib::BoolError),
+    Pipeline(String),
 
     // Logger
     LoggerAlreadyInitialized,
@@ -21,10 +70,53 @@ impl fmt::Display for EbookError {
             // Config
             Self::InvalidEnvEncoding(key) => write!(f, "Cannot get environment variable {key}.\nIt was found but is not encoded correctly"),
             Self::NoTwitchStreamKey => f.write_str("No Twitch stream key in environment variables.\nTry TWITCH_STREAM_KEY={YOUR_STREAM_KEY}"),
+            Self::InvalidLanguage(err) => f.write_str(err),
+            Self::InvalidNumber(key, value) => write!(f, "Invalid number {value:?} in {key}"),
+            Self::InvalidReward(reward) => write!(f, "Invalid reward {reward:?}.\nTry TWITCH_REWARDS={{REWARD_ID}}=voice,{{REWARD_ID}}=speed:1.25,{{REWARD_ID}}=recap"),
+            Self::InvalidVoice(voice) => write!(f, "Unknown voice {voice:?}.\nTry one of {}", VOICES.join(", ")),
+            Self::NoTwitchApiToken(key) => write!(f, "{key} needs the Twitch API.\nTry TWITCH_API_TOKEN={{YOUR_USER_TOKEN}}"),
+            Self::InvalidControlAddress(addr) => write!(f, "Invalid control API address {addr:?}.\nTry CONTROL_ADDR=127.0.0.1:8091"),
+            Self::NoControlToken => f.write_str("The control API needs a token.\nTry CONTROL_TOKEN={A_LONG_RANDOM_STRING}"),
+            Self::InvalidArgument(arg) => write!(f, "Unknown argument {arg:?}.\n{USAGE}"),
+            Self::ReadConfig(path, err) => write!(f, "Cannot read config file {}: {err}", path.display()),
+            Self::UnknownConfigKey(key) => write!(f, "Unknown config key {key:?}.\nTry --tts.language=en or [tts] language = \"en\""),
+            Self::MissingFile(key, path) => write!(f, "{} in {key} does not exist", path.display()),
+            Self::SecretOnCommandLine(key) => write!(f, "{key} is a secret and cannot be given in the command line.\nTry the environment, a systemd credential or {key}_file in the config file"),
+            Self::ReadSecret(key, path, err) => write!(f, "Cannot read {key} from {}: {err}", path.display()),
+
+            // Encoder
+            Self::UnknownEncoderPreset(name) => write!(f, "Unknown encoder preset {name:?}.\nAvailable presets: {}", EncoderPreset::ALL.iter().map(|p| p.name()).collect::<Vec<_>>().join(", ")),
+            Self::EncoderOutOfSpec(reason) => write!(f, "Encoder settings are out of Twitch recommendations: {reason}"),
+
+            // Preview
+            Self::InvalidPreviewMode(mode) => write!(f, "Unknown preview mode {mode:?}.\nTry PREVIEW=window or PREVIEW=http"),
+            Self::InvalidPreviewAddress(addr) => write!(f, "Invalid preview address {addr:?}.\nTry PREVIEW_ADDR={DEFAULT_PREVIEW_ADDR}"),
+            Self::PublicPreviewAddress(addr) => write!(f, "Preview address {addr:?} is not a loopback address, anybody could watch the preview.\nTry PREVIEW_ADDR={DEFAULT_PREVIEW_ADDR} and an SSH tunnel"),
+            Self::PreviewServer(err) => write!(f, "Cannot start preview server: {err}"),
+
+            // Control API
+            Self::ControlServer(err) => write!(f, "Cannot start control API: {err}"),
+
+            // Reading
+            Self::ReadBook(path, err) => write!(f, "Cannot read book {}: {err}", path.display()),
+            Self::Bookmark(path, err) => write!(f, "Cannot save bookmark {}: {err}", path.display()),
+            Self::ReadPlaylist(path, err) => write!(f, "Cannot read playlist {}: {err}", path.display()),
+            Self::Dictionary(path, err) => write!(f, "Cannot read dictionary {}: {err}", path.display()),
+            Self::Characters(path, err) => write!(f, "Cannot read characters {}: {err}", path.display()),
+            Self::Captions(path, err) => write!(f, "Cannot write captions {}: {err}", path.display()),
+            Self::Tts(err) => write!(f, "TTS Error: {err}"),
+            Self::AudioDecode(err) => write!(f, "Cannot decode audio: {err}"),
+
+            // Twitch API
+            Self::Helix(err) => write!(f, "Twitch API Error: {err}"),
+
+            // Shutdown
+            Self::Signal(err) => write!(f, "Cannot install signal handlers: {err}"),
 
             // Glib
             Self::Glib(err) => write!(f, "Glib Error: {err}"),
             Self::GlibBool(err) => write!(f, "GlibBool Error: {err}"),
+            Self::Pipeline(err) => write!(f, "Pipeline Error: {err}"),
 
             // Logger
             Self::LoggerAlreadyInitialized => f.write_str("Logger was already initialized"),
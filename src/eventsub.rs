@@ -0,0 +1,480 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::net::TcpStream;
+use std::str::FromStr;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{debug, info, trace, warn};
+use serde_json::Value;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::control::ReaderControl;
+use crate::error::{EbookError, EbookResult};
+use crate::helix::{HelixClient, HelixConfig};
+use crate::shutdown::Shutdown;
+use crate::tts::VOICES;
+
+pub const DEFAULT_EVENTSUB_URL: &str = "wss://eventsub.wss.twitch.tv/ws";
+
+const REDEMPTION: &str = "channel.channel_points_custom_reward_redemption.add";
+const REDEMPTION_VERSION: &str = "1";
+/// Twitch may deliver a message twice
+const SEEN_IDS: usize = 64;
+/// Extra wait over the keepalive Twitch announces before reconnecting
+const KEEPALIVE_GRACE: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// What a channel point reward does
+#[derive(Debug, Clone, PartialEq)]
+pub enum RewardAction {
+    /// `voice` takes the viewer input as the voice, or the next one if it
+    /// is not a known voice. `voice:com.mx` always sets that one.
+    Voice(Option<String>),
+    /// `speed:1.25`
+    Speed(f32),
+    /// `recap`, read the last paragraph again
+    Recap,
+}
+
+impl FromStr for RewardAction {
+    type Err = EbookError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.trim().split_once(':') {
+            Some((name, arg)) => (name, Some(arg.trim())),
+            None => (s.trim(), None),
+        };
+
+        match (name, arg) {
+            ("voice", Some(voice)) if !VOICES.contains(&voice) => {
+                Err(EbookError::InvalidVoice(voice.to_owned()))
+            }
+            ("voice", arg) => Ok(Self::Voice(arg.map(str::to_owned))),
+            ("speed", Some(speed)) => speed
+                .parse()
+                .ok()
+                .filter(|speed: &f32| speed.is_finite())
+                .map(Self::Speed)
+                .ok_or_else(|| EbookError::InvalidReward(s.to_owned())),
+            ("recap", None) => Ok(Self::Recap),
+            _ => Err(EbookError::InvalidReward(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for RewardAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Voice(None) => f.write_str("voice"),
+            Self::Voice(Some(voice)) => write!(f, "voice:{voice}"),
+            Self::Speed(speed) => write!(f, "speed:{speed}"),
+            Self::Recap => f.write_str("recap"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EventSubConfig {
+    /// Reward id -> action
+    pub rewards: Vec<(String, RewardAction)>,
+    /// WebSocket URL, a local stand-in works too
+    pub url: String,
+}
+
+impl EventSubConfig {
+    /// `id=action,id=action`
+    pub fn parse_rewards(value: &str) -> EbookResult<Vec<(String, RewardAction)>> {
+        value
+            .split(',')
+            .filter(|reward| !reward.trim().is_empty())
+            .map(|reward| {
+                let (id, action) = reward
+                    .split_once('=')
+                    .ok_or_else(|| EbookError::InvalidReward(reward.to_owned()))?;
+                Ok((id.trim().to_owned(), action.parse()?))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for EventSubConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} rewards on {}", self.rewards.len(), self.url)
+    }
+}
+
+/// Listen to channel point redemptions until shutdown, following Twitch's
+/// reconnect requests and reconnecting when the connection drops
+pub fn spawn(
+    config: EventSubConfig,
+    helix: HelixConfig,
+    control: ReaderControl,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut client = None;
+        let mut listener = Listener {
+            config,
+            control,
+            seen: VecDeque::with_capacity(SEEN_IDS),
+        };
+        let mut url = listener.config.url.clone();
+        let mut subscribed = false;
+        let mut backoff = MIN_BACKOFF;
+
+        while !shutdown.is_requested() {
+            let started = Instant::now();
+            // Validating the token fails too when Twitch is down
+            let connected = match &mut client {
+                Some(client) => Ok(client),
+                None => HelixClient::connect(helix.clone()).map(|new| client.insert(new)),
+            };
+            let session =
+                connected.and_then(|client| listener.run(&url, client, subscribed, &shutdown));
+            match session {
+                Ok(Session::Closed) => break,
+                Ok(Session::Reconnect(reconnect_url)) => {
+                    // Subscriptions move to the new connection
+                    debug!("EventSub reconnecting to {reconnect_url}");
+                    url = reconnect_url;
+                    subscribed = true;
+                    continue;
+                }
+                Err(err) => warn!("EventSub unavailable: {err}"),
+            }
+
+            url = listener.config.url.clone();
+            subscribed = false;
+
+            if started.elapsed() > MAX_BACKOFF {
+                backoff = MIN_BACKOFF;
+            }
+            let deadline = Instant::now() + backoff;
+            while Instant::now() < deadline && !shutdown.is_requested() {
+                thread::sleep(READ_TIMEOUT.min(deadline - Instant::now()));
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    })
+}
+
+enum Session {
+    /// Shutdown requested
+    Closed,
+    /// Twitch asked to move to this URL
+    Reconnect(String),
+}
+
+struct Listener {
+    config: EventSubConfig,
+    control: ReaderControl,
+    /// Last message ids, to drop duplicates
+    seen: VecDeque<String>,
+}
+
+impl Listener {
+    fn run(
+        &mut self,
+        url: &str,
+        client: &mut HelixClient,
+        subscribed: bool,
+        shutdown: &Shutdown,
+    ) -> EbookResult<Session> {
+        let event_err = |err: &dyn fmt::Display| EbookError::Helix(err.to_string());
+
+        let (mut socket, _) = tungstenite::connect(url).map_err(|err| event_err(&err))?;
+        set_read_timeout(&socket, READ_TIMEOUT).map_err(|err| event_err(&err))?;
+
+        let mut keepalive = Duration::MAX;
+        let mut last_seen = Instant::now();
+
+        while !shutdown.is_requested() {
+            let text = match socket.read() {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(frame)) => {
+                    return Err(EbookError::Helix(format!("EventSub closed: {frame:?}")))
+                }
+                Ok(_) => continue,
+                Err(tungstenite::Error::Io(err))
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    if last_seen.elapsed() > keepalive {
+                        return Err(EbookError::Helix("EventSub keepalive timeout".to_owned()));
+                    }
+                    continue;
+                }
+                Err(err) => return Err(event_err(&err)),
+            };
+
+            last_seen = Instant::now();
+            let message = serde_json::from_str::<Value>(&text).map_err(|err| event_err(&err))?;
+            let metadata = &message["metadata"];
+            let payload = &message["payload"];
+
+            if let Some(id) = metadata["message_id"].as_str() {
+                if self.seen.iter().any(|seen| seen == id) {
+                    continue;
+                }
+                if self.seen.len() == SEEN_IDS {
+                    self.seen.pop_front();
+                }
+                self.seen.push_back(id.to_owned());
+            }
+
+            match metadata["message_type"].as_str().unwrap_or_default() {
+                "session_welcome" => {
+                    let session = &payload["session"];
+                    if let Some(seconds) = session["keepalive_timeout_seconds"].as_u64() {
+                        keepalive = Duration::from_secs(seconds) + KEEPALIVE_GRACE;
+                    }
+
+                    if !subscribed {
+                        let id = session["id"].as_str().unwrap_or_default();
+                        client.subscribe(REDEMPTION, REDEMPTION_VERSION, id)?;
+                        info!("Listening to channel point redemptions");
+                    }
+                }
+                "session_keepalive" => {}
+                "session_reconnect" => {
+                    let url = payload["session"]["reconnect_url"].as_str();
+                    return match url {
+                        Some(url) => Ok(Session::Reconnect(url.to_owned())),
+                        None => Err(EbookError::Helix("Reconnect without URL".to_owned())),
+                    };
+                }
+                "notification" => self.on_notification(payload),
+                "revocation" => {
+                    let status = payload["subscription"]["status"].as_str();
+                    warn!("EventSub subscription revoked: {status:?}");
+                }
+                kind => trace!("Ignoring EventSub {kind}"),
+            }
+        }
+
+        _ = socket.close(None);
+        _ = socket.flush();
+        Ok(Session::Closed)
+    }
+
+    fn on_notification(&self, payload: &Value) {
+        if payload["subscription"]["type"].as_str() != Some(REDEMPTION) {
+            return;
+        }
+
+        let event = &payload["event"];
+        let reward = event["reward"]["id"].as_str().unwrap_or_default();
+        let user = event["user_name"].as_str().unwrap_or_default();
+        let input = event["user_input"].as_str().unwrap_or_default().trim();
+
+        let Some((_, action)) = self.config.rewards.iter().find(|(id, _)| id == reward) else {
+            trace!("Ignoring reward {reward}");
+            return;
+        };
+
+        info!("{user} redeemed {action}");
+        match action {
+            RewardAction::Voice(voice) => {
+                let voice = voice.as_deref().unwrap_or(input);
+                if self.control.set_voice(voice).is_err() {
+                    self.control.next_voice();
+                }
+            }
+            RewardAction::Speed(speed) => self.control.set_speed(*speed),
+            RewardAction::Recap => self.control.recap(),
+        }
+    }
+}
+
+fn set_read_timeout(socket: &Socket, timeout: Duration) -> io::Result<()> {
+    match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(timeout)),
+        MaybeTlsStream::Rustls(stream) => stream.sock.set_read_timeout(Some(timeout)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::helix::tests::{mock_server, VALIDATE};
+    use crate::testing::{self, wait_until};
+
+    type ServerSocket = WebSocket<TcpStream>;
+
+    fn accept(listener: &TcpListener) -> ServerSocket {
+        let (stream, _) = listener.accept().unwrap();
+        tungstenite::accept(stream)
+            .map_err(|err| err.to_string())
+            .unwrap()
+    }
+
+    fn send(socket: &mut ServerSocket, id: &str, kind: &str, payload: Value) {
+        let message = json!({
+            "metadata": { "message_id": id, "message_type": kind },
+            "payload": payload,
+        });
+        socket.send(Message::Text(message.to_string())).unwrap();
+    }
+
+    fn welcome(socket: &mut ServerSocket, id: &str, session: &str, keepalive: u64) {
+        let payload = json!({
+            "session": { "id": session, "keepalive_timeout_seconds": keepalive },
+        });
+        send(socket, id, "session_welcome", payload);
+    }
+
+    fn redeem(socket: &mut ServerSocket, id: &str, reward: &str) {
+        let payload = json!({
+            "subscription": { "type": REDEMPTION },
+            "event": { "reward": { "id": reward }, "user_name": "Ana", "user_input": "" },
+        });
+        send(socket, id, "notification", payload);
+    }
+
+    #[test]
+    fn stand_in_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (helix, requests) = mock_server(vec![VALIDATE, (202, "{}"), (202, "{}")]);
+        let config = EventSubConfig {
+            rewards: EventSubConfig::parse_rewards(
+                "voice-id=voice,fast-id=speed:1.5,slow-id=speed:0.75",
+            )
+            .unwrap(),
+            url: format!("ws://{addr}/ws"),
+        };
+        let control = testing::control();
+        let shutdown = Shutdown::default();
+        let eventsub = spawn(config, helix, control.clone(), shutdown.clone());
+
+        let mut socket = accept(&listener);
+        requests.recv().unwrap();
+        welcome(&mut socket, "welcome-1", "session-1", 10);
+        let subscribe = requests.recv().unwrap();
+        assert_eq!(subscribe.path, "/helix/eventsub/subscriptions");
+        let body = serde_json::from_str::<Value>(&subscribe.body).unwrap();
+        assert_eq!(body["type"], REDEMPTION);
+        assert_eq!(body["transport"]["session_id"], "session-1");
+
+        // Delivered twice, the voice only moves once
+        redeem(&mut socket, "redemption-1", "voice-id");
+        redeem(&mut socket, "redemption-1", "voice-id");
+        redeem(&mut socket, "redemption-2", "fast-id");
+        assert!(wait_until(|| control.voice().speed == 1.5));
+        assert_eq!(control.voice().tld, "com.mx");
+
+        // The subscription moves to the new connection
+        let reconnect_url = format!("ws://{addr}/reconnect");
+        send(
+            &mut socket,
+            "reconnect-1",
+            "session_reconnect",
+            json!({ "session": { "id": "session-1", "reconnect_url": reconnect_url } }),
+        );
+        let mut socket = accept(&listener);
+        welcome(&mut socket, "welcome-2", "session-1", 1);
+        redeem(&mut socket, "redemption-3", "slow-id");
+        assert!(wait_until(|| control.voice().speed == 0.75));
+        assert!(requests.try_recv().is_err());
+
+        // Silence past the keepalive, a new connection subscribes again
+        let mut socket = accept(&listener);
+        welcome(&mut socket, "welcome-3", "session-3", 10);
+        let subscribe = requests.recv().unwrap();
+        let body = serde_json::from_str::<Value>(&subscribe.body).unwrap();
+        assert_eq!(body["transport"]["session_id"], "session-3");
+
+        shutdown.request();
+        eventsub.join().unwrap();
+    }
+
+    #[test]
+    fn retry_token_validation() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (helix, requests) = mock_server(vec![
+            (500, r#"{"message":"Internal Server Error"}"#),
+            VALIDATE,
+            (202, "{}"),
+        ]);
+        let config = EventSubConfig {
+            rewards: EventSubConfig::parse_rewards("voice-id=voice").unwrap(),
+            url: format!("ws://{addr}/ws"),
+        };
+        let shutdown = Shutdown::default();
+        let eventsub = spawn(config, helix, testing::control(), shutdown.clone());
+
+        // Twitch was down, it connects after the wait anyway
+        assert_eq!(requests.recv().unwrap().path, "/oauth2/validate");
+        let mut socket = accept(&listener);
+        assert_eq!(requests.recv().unwrap().path, "/oauth2/validate");
+        welcome(&mut socket, "welcome-1", "session-1", 10);
+        let subscribe = requests.recv().unwrap();
+        assert_eq!(subscribe.path, "/helix/eventsub/subscriptions");
+
+        shutdown.request();
+        eventsub.join().unwrap();
+    }
+
+    #[test]
+    fn parse_reward_actions() {
+        assert_eq!(
+            "voice".parse::<RewardAction>().ok(),
+            Some(RewardAction::Voice(None))
+        );
+        assert_eq!(
+            " voice: com.mx ".parse::<RewardAction>().ok(),
+            Some(RewardAction::Voice(Some("com.mx".to_owned())))
+        );
+        assert_eq!(
+            "speed:1.25".parse::<RewardAction>().ok(),
+            Some(RewardAction::Speed(1.25))
+        );
+        assert_eq!(
+            "recap".parse::<RewardAction>().ok(),
+            Some(RewardAction::Recap)
+        );
+
+        for invalid in [
+            "",
+            "voice:xx",
+            "speed",
+            "speed:fast",
+            "speed:NaN",
+            "recap:1",
+            "dance",
+        ] {
+            assert!(invalid.parse::<RewardAction>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn parse_rewards() {
+        let rewards = EventSubConfig::parse_rewards(" a1 = voice:es , b2=recap,").unwrap();
+        assert_eq!(
+            rewards,
+            [
+                ("a1".to_owned(), RewardAction::Voice(Some("es".to_owned()))),
+                ("b2".to_owned(), RewardAction::Recap),
+            ]
+        );
+
+        assert!(EventSubConfig::parse_rewards("").unwrap().is_empty());
+        assert!(EventSubConfig::parse_rewards("a1").is_err());
+        assert!(EventSubConfig::parse_rewards("a1=jump").is_err());
+    }
+}
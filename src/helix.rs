@@ -0,0 +1,437 @@
+mod auth;
+
+use std::fmt;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{debug, info, warn};
+use minreq::Method;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+
+use crate::error::{EbookError, EbookResult};
+use crate::session::SharedSession;
+use crate::shutdown::Shutdown;
+use auth::{parse_response, Token};
+
+pub const DEFAULT_API_URL: &str = "https://api.twitch.tv/helix";
+pub const DEFAULT_AUTH_URL: &str = "https://id.twitch.tv/oauth2";
+pub const DEFAULT_CATEGORY: &str = "Books & Literature";
+pub const DEFAULT_TITLE: &str = "Reading: {book} — Cap. {chapter}";
+
+const TIMEOUT_SECONDS: u64 = 10;
+/// Twitch limit for titles and marker descriptions
+const MAX_TITLE_LEN: usize = 140;
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+/// Waits between failed attempts, doubling up to the max
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct HelixConfig {
+    /// User access token of the broadcaster with `channel:manage:broadcast`.
+    /// Not the stream key.
+    pub token: String,
+    pub refresh_token: Option<String>,
+    /// Taken from the token when not set
+    pub client_id: Option<String>,
+    /// Only needed to refresh the token
+    pub client_secret: Option<String>,
+    /// Category (game) name
+    pub category: String,
+    /// Stream title, `{book}` and `{chapter}` are replaced. Before the
+    /// first chapter everything from `{chapter}`'s separator on is dropped.
+    pub title: String,
+    /// Base URLs, a local mock server works too
+    pub api_url: String,
+    pub auth_url: String,
+}
+
+impl fmt::Display for HelixConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} on {}", self.category, self.api_url)
+    }
+}
+
+/// Just what the reader needs from the Helix API
+#[derive(Debug)]
+pub struct HelixClient {
+    config: HelixConfig,
+    token: Token,
+}
+
+impl HelixClient {
+    pub fn connect(config: HelixConfig) -> EbookResult<Self> {
+        let token = Token::validate(&config)?;
+        info!("Twitch API as {}", token.login);
+
+        Ok(Self { config, token })
+    }
+
+    /// Id of the category called `name`
+    pub fn category_id(&mut self, name: &str) -> EbookResult<Option<String>> {
+        let name = utf8_percent_encode(name, NON_ALPHANUMERIC).to_string();
+
+        let body = self.request(Method::Get, &format!("/games?name={name}"), None)?;
+        if let Some(id) = body["data"][0]["id"].as_str() {
+            return Ok(Some(id.to_owned()));
+        }
+
+        // Not an exact name, take the closest one
+        let body = self.request(
+            Method::Get,
+            &format!("/search/categories?query={name}"),
+            None,
+        )?;
+        Ok(body["data"][0]["id"].as_str().map(str::to_owned))
+    }
+
+    pub fn update_channel(&mut self, title: &str, category_id: Option<&str>) -> EbookResult<()> {
+        let mut body = json!({ "title": truncate(title, MAX_TITLE_LEN) });
+        if let Some(category_id) = category_id {
+            body["game_id"] = json!(category_id);
+        }
+
+        let path = format!("/channels?broadcaster_id={}", self.token.user_id);
+        self.request(Method::Patch, &path, Some(body))?;
+        Ok(())
+    }
+
+    /// Only works while live
+    pub fn create_marker(&mut self, description: &str) -> EbookResult<()> {
+        let body = json!({
+            "user_id": self.token.user_id,
+            "description": truncate(description, MAX_TITLE_LEN),
+        });
+        self.request(Method::Post, "/streams/markers", Some(body))?;
+        Ok(())
+    }
+
+    /// Subscribe the EventSub WebSocket `session_id` to `kind` events of
+    /// the broadcaster
+    pub fn subscribe(&mut self, kind: &str, version: &str, session_id: &str) -> EbookResult<()> {
+        let body = json!({
+            "type": kind,
+            "version": version,
+            "condition": { "broadcaster_user_id": self.token.user_id },
+            "transport": { "method": "websocket", "session_id": session_id },
+        });
+        self.request(Method::Post, "/eventsub/subscriptions", Some(body))?;
+        Ok(())
+    }
+
+    /// Send the request, refreshing the token once if it expired
+    fn request(&mut self, method: Method, path: &str, body: Option<Value>) -> EbookResult<Value> {
+        let response = self.send(method.clone(), path, body.as_ref())?;
+        if response.status_code != 401 || self.token.refresh.is_none() {
+            return parse_response(&response);
+        }
+
+        debug!("Refreshing Twitch API token");
+        self.token.refresh(&self.config)?;
+        parse_response(&self.send(method, path, body.as_ref())?)
+    }
+
+    fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&Value>,
+    ) -> EbookResult<minreq::Response> {
+        let mut request = minreq::Request::new(method, format!("{}{path}", self.config.api_url))
+            .with_header("Authorization", format!("Bearer {}", self.token.access))
+            .with_header("Client-Id", &self.token.client_id)
+            .with_timeout(TIMEOUT_SECONDS);
+        if let Some(body) = body {
+            request = request
+                .with_header("Content-Type", "application/json")
+                .with_body(body.to_string());
+        }
+
+        request
+            .send()
+            .map_err(|err| EbookError::Helix(err.to_string()))
+    }
+}
+
+/// Keep the stream title and category in sync with the book, with a stream
+/// marker at every chapter. Connecting and updating are tried again with a
+/// growing wait until they work.
+pub fn spawn(config: HelixConfig, session: SharedSession, shutdown: Shutdown) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let title_template = config.title.clone();
+        let category = config.category.clone();
+
+        let mut backoff = MIN_BACKOFF;
+        let mut client = loop {
+            match HelixClient::connect(config.clone()) {
+                Ok(client) => break client,
+                Err(err) => warn!(
+                    "Cannot connect to the Twitch API, retrying in {}s: {err}",
+                    backoff.as_secs()
+                ),
+            }
+            if !wait(backoff, &shutdown) {
+                return;
+            }
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        };
+
+        let category_id = client.category_id(&category).unwrap_or_else(|err| {
+            warn!("{err}");
+            None
+        });
+        if category_id.is_none() {
+            warn!("Category {category:?} not found");
+        }
+
+        let mut last: Option<(PathBuf, usize)> = None;
+        let mut backoff = MIN_BACKOFF;
+        let mut retry_at = Instant::now();
+
+        while !shutdown.is_requested() {
+            let (current, title, marker) = {
+                let session = session.lock().unwrap();
+                let book = session.book();
+                let chapter = session.current_chapter();
+                let number = book
+                    .chapters
+                    .get(chapter)
+                    .and_then(|chapter| chapter.number.as_deref());
+
+                (
+                    (book.path.clone(), chapter),
+                    stream_title(&title_template, &book.title, number),
+                    book.chapters
+                        .get(chapter)
+                        .map(|chapter| chapter.to_string())
+                        .unwrap_or_default(),
+                )
+            };
+
+            if last.as_ref() != Some(&current) && Instant::now() >= retry_at {
+                debug!("Stream title {title:?}");
+                match client.update_channel(&title, category_id.as_deref()) {
+                    Ok(()) => {
+                        // The first chapter of the run is not a boundary
+                        if last.is_some() {
+                            if let Err(err) = client.create_marker(&marker) {
+                                warn!("Cannot create stream marker: {err}");
+                            }
+                        }
+
+                        last = Some(current);
+                        backoff = MIN_BACKOFF;
+                    }
+                    Err(err) => {
+                        warn!(
+                            "Cannot update stream title, retrying in {}s: {err}",
+                            backoff.as_secs()
+                        );
+                        retry_at = Instant::now() + backoff;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+
+            thread::sleep(WATCH_INTERVAL);
+        }
+    })
+}
+
+/// Sleep for `duration`, `false` when a shutdown is requested meanwhile
+fn wait(duration: Duration, shutdown: &Shutdown) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if shutdown.is_requested() {
+            return false;
+        }
+        thread::sleep(WATCH_INTERVAL.min(deadline - Instant::now()));
+    }
+    !shutdown.is_requested()
+}
+
+/// Fill `template`. Without chapter number the text between `{book}` and
+/// `{chapter}` is dropped too, `"Reading: {book} — Cap. {chapter}"` becomes
+/// `"Reading: Zalacaín"`.
+fn stream_title(template: &str, book: &str, chapter: Option<&str>) -> String {
+    let template = match (chapter, template.find("{book}"), template.find("{chapter}")) {
+        (None, Some(book), Some(chapter)) if book < chapter => {
+            let book_end = book + "{book}".len();
+            let chapter_end = chapter + "{chapter}".len();
+            format!("{}{}", &template[..book_end], &template[chapter_end..])
+        }
+        _ => template.to_owned(),
+    };
+
+    template
+        .replace("{book}", book)
+        .replace("{chapter}", chapter.unwrap_or_default())
+        .trim()
+        .to_owned()
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    text.chars().take(max_chars).collect()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::mpsc::Receiver;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::session::ReadingSession;
+    use crate::testing::{self, HttpRequest};
+
+    /// The API and the auth server on the same mock server, answering
+    /// `responses` in order
+    pub fn mock_server(
+        responses: Vec<(u16, &'static str)>,
+    ) -> (HelixConfig, Receiver<HttpRequest>) {
+        let (addr, requests) = testing::mock_http(responses);
+        let config = HelixConfig {
+            token: "old-token".to_owned(),
+            refresh_token: Some("refresh-token".to_owned()),
+            client_id: None,
+            client_secret: Some("client-secret".to_owned()),
+            category: DEFAULT_CATEGORY.to_owned(),
+            title: DEFAULT_TITLE.to_owned(),
+            api_url: format!("http://{addr}/helix"),
+            auth_url: format!("http://{addr}/oauth2"),
+        };
+        (config, requests)
+    }
+
+    pub const VALIDATE: (u16, &str) = (
+        200,
+        r#"{"client_id":"client","login":"lector","user_id":"42","expires_in":3600}"#,
+    );
+
+    #[test]
+    fn update_title_and_category() {
+        let (config, requests) = mock_server(vec![VALIDATE, (204, "")]);
+
+        let mut client = HelixClient::connect(config).unwrap();
+        let validate = requests.recv().unwrap();
+        assert_eq!(validate.method, "GET");
+        assert_eq!(validate.path, "/oauth2/validate");
+        assert_eq!(validate.authorization, "OAuth old-token");
+
+        client
+            .update_channel("Reading: Zalacaín — Cap. II", Some("509"))
+            .unwrap();
+        let update = requests.recv().unwrap();
+        assert_eq!(update.method, "PATCH");
+        assert_eq!(update.path, "/helix/channels?broadcaster_id=42");
+        assert_eq!(update.authorization, "Bearer old-token");
+        assert_eq!(
+            serde_json::from_str::<Value>(&update.body).unwrap(),
+            json!({ "title": "Reading: Zalacaín — Cap. II", "game_id": "509" })
+        );
+    }
+
+    #[test]
+    fn create_marker() {
+        let (config, requests) = mock_server(vec![
+            VALIDATE,
+            (200, r#"{"data":[{"id":"1","position_seconds":60}]}"#),
+        ]);
+
+        let mut client = HelixClient::connect(config).unwrap();
+        requests.recv().unwrap();
+
+        client.create_marker("II. El bosque").unwrap();
+        let marker = requests.recv().unwrap();
+        assert_eq!(marker.method, "POST");
+        assert_eq!(marker.path, "/helix/streams/markers");
+        assert_eq!(
+            serde_json::from_str::<Value>(&marker.body).unwrap(),
+            json!({ "user_id": "42", "description": "II. El bosque" })
+        );
+    }
+
+    #[test]
+    fn refresh_expired_token_and_retry() {
+        let (config, requests) = mock_server(vec![
+            VALIDATE,
+            (401, r#"{"status":401,"message":"Invalid OAuth token"}"#),
+            (
+                200,
+                r#"{"access_token":"new-token","refresh_token":"new-refresh"}"#,
+            ),
+            (200, r#"{"data":[{"id":"1"}]}"#),
+        ]);
+
+        let mut client = HelixClient::connect(config).unwrap();
+        requests.recv().unwrap();
+
+        client.create_marker("II").unwrap();
+
+        let expired = requests.recv().unwrap();
+        assert_eq!(expired.path, "/helix/streams/markers");
+        assert_eq!(expired.authorization, "Bearer old-token");
+
+        let refresh = requests.recv().unwrap();
+        assert_eq!(refresh.method, "POST");
+        assert_eq!(refresh.path, "/oauth2/token");
+        assert_eq!(
+            refresh.body,
+            "grant_type=refresh%5Ftoken&refresh_token=refresh%2Dtoken\
+            &client_id=client&client_secret=client%2Dsecret"
+        );
+
+        let retry = requests.recv().unwrap();
+        assert_eq!(retry.path, "/helix/streams/markers");
+        assert_eq!(retry.authorization, "Bearer new-token");
+        assert_eq!(client.token.refresh.as_deref(), Some("new-refresh"));
+    }
+
+    #[test]
+    fn retry_until_updated() {
+        let (config, requests) = mock_server(vec![
+            (500, r#"{"message":"Internal Server Error"}"#),
+            VALIDATE,
+            (200, r#"{"data":[{"id":"509"}]}"#),
+            (503, r#"{"message":"Service Unavailable"}"#),
+            (204, ""),
+        ]);
+        let session = ReadingSession::open(&testing::book(testing::BOOK)).unwrap();
+        let shutdown = Shutdown::default();
+        let thread = spawn(config, Arc::new(Mutex::new(session)), shutdown.clone());
+
+        let paths = requests
+            .iter()
+            .take(5)
+            .map(|request| request.path)
+            .collect::<Vec<_>>();
+        shutdown.request();
+        thread.join().unwrap();
+
+        assert_eq!(
+            paths,
+            [
+                "/oauth2/validate",
+                "/oauth2/validate",
+                "/helix/games?name=Books%20%26%20Literature",
+                "/helix/channels?broadcaster_id=42",
+                "/helix/channels?broadcaster_id=42",
+            ]
+        );
+        // Nothing else was sent, the first chapter is not marked
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn fill_stream_title() {
+        assert_eq!(
+            stream_title(DEFAULT_TITLE, "Zalacaín", Some("II")),
+            "Reading: Zalacaín — Cap. II"
+        );
+        assert_eq!(
+            stream_title(DEFAULT_TITLE, "Zalacaín", None),
+            "Reading: Zalacaín"
+        );
+    }
+}
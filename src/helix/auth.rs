@@ -0,0 +1,130 @@
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::Value;
+
+use crate::error::{EbookError, EbookResult};
+use crate::helix::{HelixConfig, TIMEOUT_SECONDS};
+use crate::logger;
+
+/// User access token of the broadcaster, with what Twitch tells about it
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub access: String,
+    pub refresh: Option<String>,
+    pub client_id: String,
+    pub user_id: String,
+    pub login: String,
+}
+
+impl Token {
+    /// Check the token against the auth server, refreshing it first if it
+    /// already expired
+    pub fn validate(config: &HelixConfig) -> EbookResult<Self> {
+        let mut token = Self {
+            access: config.token.clone(),
+            refresh: config.refresh_token.clone(),
+            client_id: config.client_id.clone().unwrap_or_default(),
+            user_id: String::new(),
+            login: String::new(),
+        };
+
+        if !token.try_validate(config)? {
+            token.refresh(config)?;
+            if !token.try_validate(config)? {
+                return Err(EbookError::Helix("Token is invalid".to_owned()));
+            }
+        }
+
+        Ok(token)
+    }
+
+    /// `false` if the token is not valid anymore
+    fn try_validate(&mut self, config: &HelixConfig) -> EbookResult<bool> {
+        let response = minreq::get(format!("{}/validate", config.auth_url))
+            .with_header("Authorization", format!("OAuth {}", self.access))
+            .with_timeout(TIMEOUT_SECONDS)
+            .send()
+            .map_err(|err| EbookError::Helix(err.to_string()))?;
+
+        if response.status_code == 401 {
+            return Ok(false);
+        }
+
+        let body = parse_response(&response)?;
+        let field = |name: &str| body[name].as_str().unwrap_or_default().to_owned();
+
+        self.client_id = field("client_id");
+        self.user_id = field("user_id");
+        self.login = field("login");
+
+        Ok(true)
+    }
+
+    /// Get a new access token with the refresh token. Only possible with
+    /// the client secret.
+    pub fn refresh(&mut self, config: &HelixConfig) -> EbookResult<()> {
+        let (Some(refresh), Some(secret)) = (&self.refresh, &config.client_secret) else {
+            return Err(EbookError::Helix(
+                "Token expired and it cannot be refreshed without TWITCH_REFRESH_TOKEN and TWITCH_CLIENT_SECRET".to_owned(),
+            ));
+        };
+
+        let client_id = config.client_id.as_deref().unwrap_or(&self.client_id);
+        let form = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh),
+            ("client_id", client_id),
+            ("client_secret", secret),
+        ]
+        .iter()
+        .map(|(key, value)| format!("{key}={}", utf8_percent_encode(value, NON_ALPHANUMERIC)))
+        .collect::<Vec<_>>()
+        .join("&");
+
+        let response = minreq::post(format!("{}/token", config.auth_url))
+            .with_header("Content-Type", "application/x-www-form-urlencoded")
+            .with_body(form)
+            .with_timeout(TIMEOUT_SECONDS)
+            .send()
+            .map_err(|err| EbookError::Helix(err.to_string()))?;
+        let body = parse_response(&response)?;
+
+        let Some(access) = body["access_token"].as_str() else {
+            return Err(EbookError::Helix(
+                "No access token in refresh response".to_owned(),
+            ));
+        };
+        logger::register_secret(access);
+        self.access = access.to_owned();
+        // Twitch may rotate the refresh token too
+        if let Some(refresh) = body["refresh_token"].as_str() {
+            logger::register_secret(refresh);
+            self.refresh = Some(refresh.to_owned());
+        }
+
+        Ok(())
+    }
+}
+
+/// The JSON body of a successful response, the error message otherwise
+pub fn parse_response(response: &minreq::Response) -> EbookResult<Value> {
+    let text = response
+        .as_str()
+        .map_err(|err| EbookError::Helix(err.to_string()))?;
+
+    if !(200..300).contains(&response.status_code) {
+        let message = serde_json::from_str::<Value>(text)
+            .ok()
+            .and_then(|body| body["message"].as_str().map(str::to_owned))
+            .unwrap_or_else(|| response.reason_phrase.clone());
+        return Err(EbookError::Helix(format!(
+            "{} {message}",
+            response.status_code
+        )));
+    }
+
+    if text.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+
+    serde_json::from_str(text).map_err(|err| EbookError::Helix(err.to_string()))
+}
@@ -1,13 +1,74 @@
+use std::path::Path;
+use std::sync::RwLock;
+
 use env_logger::Builder;
 use log::{Level, LevelFilter, Log, Metadata, Record};
 
 use crate::config::EbookConfig;
+use crate::dashboard::Feed;
 use crate::error::{EbookError, EbookResult};
 
+/// Shorter values would mask unrelated words
+const MIN_SECRET_LEN: usize = 4;
+const REDACTED: &str = "{REDACTED}";
+
+/// Masked in every log target, registered as they are loaded
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Mask `secret` in every log line from now on
+pub fn register_secret(secret: &str) {
+    let secret = secret.trim();
+    if secret.len() < MIN_SECRET_LEN {
+        return;
+    }
+
+    let mut secrets = SECRETS.write().unwrap();
+    if !secrets.iter().any(|known| known == secret) {
+        secrets.push(secret.to_owned());
+    }
+}
+
+/// `text` with every registered secret masked, for what is printed before
+/// the [`Logger`] is installed
+pub fn redacted(text: &str) -> String {
+    redact(text).unwrap_or_else(|| text.to_owned())
+}
+
+/// `None` when there is nothing to mask. Secrets that overlap are masked
+/// together, none of them is left half visible.
+fn redact(text: &str) -> Option<String> {
+    let secrets = SECRETS.read().unwrap();
+    let mut found = secrets
+        .iter()
+        .flat_map(|secret| {
+            text.match_indices(secret.as_str())
+                .map(|(start, secret)| start..start + secret.len())
+        })
+        .collect::<Vec<_>>();
+    if found.is_empty() {
+        return None;
+    }
+    found.sort_by_key(|range| range.start);
+
+    let mut redacted = String::with_capacity(text.len());
+    let mut masked_to = 0;
+    for range in found {
+        if range.start >= masked_to {
+            redacted.push_str(&text[masked_to..range.start]);
+            redacted.push_str(REDACTED);
+        }
+        masked_to = masked_to.max(range.end);
+    }
+    redacted.push_str(&text[masked_to..]);
+    Some(redacted)
+}
+
 pub struct Logger {
     level: Level,
     stdout_logger: env_logger::Logger,
     file_logger: Option<env_logger::Logger>,
+    /// Scrollback of the dashboard, stdout is used while it is closed
+    dashboard: Option<Feed>,
 }
 
 impl Logger {
@@ -17,10 +78,10 @@ impl Logger {
         builder
     }
 
-    pub fn new(config: &EbookConfig) -> Self {
+    pub fn new(log_file: Option<&Path>, dashboard: Option<Feed>) -> Self {
         let level = Level::Trace;
         let a = Self::new_builder().build();
-        let b = if let Some(log_file) = &config.log_file {
+        let b = if let Some(log_file) = log_file {
             Some(
                 Self::new_builder()
                     .filter(None, LevelFilter::Trace)
@@ -37,11 +98,12 @@ impl Logger {
             level,
             stdout_logger: a,
             file_logger: b,
+            dashboard,
         }
     }
 
-    pub fn init(config: &EbookConfig) -> EbookResult<()> {
-        let logger = Self::new(config);
+    pub fn init(config: &EbookConfig, dashboard: Option<Feed>) -> EbookResult<()> {
+        let logger = Self::new(config.log_file.as_deref(), dashboard);
         log::set_max_level(LevelFilter::Trace);
         if let Err(..) = log::set_boxed_logger(Box::from(logger)) {
             Err(EbookError::LoggerAlreadyInitialized)
@@ -49,6 +111,26 @@ impl Logger {
             Ok(())
         }
     }
+
+    /// Every target gets the same, already redacted, record
+    fn write(&self, record: &Record<'_>) {
+        match &self.dashboard {
+            Some(feed) if feed.is_open() => {
+                if self.stdout_logger.matches(record) {
+                    feed.push(format!(
+                        "{:<5} {}: {}",
+                        record.level(),
+                        strip_ansi(record.target()),
+                        record.args()
+                    ));
+                }
+            }
+            _ => self.stdout_logger.log(record),
+        }
+        if let Some(file_logger) = &self.file_logger {
+            file_logger.log(record);
+        }
+    }
 }
 
 impl Log for Logger {
@@ -57,11 +139,15 @@ impl Log for Logger {
     }
 
     fn log(&self, record: &Record<'_>) {
-        if self.enabled(record.metadata()) {
-            self.stdout_logger.log(record);
-            if let Some(file_logger) = &self.file_logger {
-                file_logger.log(record);
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        match redact(&record.args().to_string()) {
+            Some(message) => {
+                self.write(&record.to_builder().args(format_args!("{message}")).build())
             }
+            None => self.write(record),
         }
     }
 
@@ -72,3 +158,74 @@ impl Log for Logger {
         }
     }
 }
+
+/// The log targets are colored for the terminal, `"\x1b[1;32mCHAT\x1b[0m"`
+/// is `"CHAT"`
+fn strip_ansi(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Up to the final letter of the escape sequence
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing;
+
+    // Secrets are global, every test uses its own
+
+    #[test]
+    fn short_secrets_are_ignored() {
+        register_secret(" ab1 ");
+        assert_eq!(redact("tab1e"), None);
+
+        register_secret("  s3cr3t-token\n");
+        assert_eq!(redacted("key=s3cr3t-token;"), "key={REDACTED};");
+        assert_eq!(redacted("nothing here"), "nothing here");
+    }
+
+    #[test]
+    fn overlapping_secrets() {
+        // One inside the other, and two sharing a part
+        register_secret("4567");
+        register_secret("live_1234567890");
+        assert_eq!(
+            redacted("live_1234567890 and 4567"),
+            "{REDACTED} and {REDACTED}"
+        );
+
+        register_secret("oauth:abcdefgh");
+        register_secret("efghijkl");
+        assert_eq!(redacted("[oauth:abcdefghijkl]"), "[{REDACTED}]");
+    }
+
+    #[test]
+    fn file_target() {
+        register_secret("file-secret-999");
+        let path = testing::temp_dir().join("log.txt");
+        let logger = Logger::new(Some(&path), None);
+
+        logger.log(
+            &Record::builder()
+                .level(Level::Info)
+                .target("test")
+                .args(format_args!("token file-secret-999 loaded"))
+                .build(),
+        );
+        logger.flush();
+
+        let log = fs::read_to_string(&path).unwrap();
+        assert!(log.contains("token {REDACTED} loaded"));
+        assert!(!log.contains("file-secret-999"));
+    }
+}
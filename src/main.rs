@@ -1,15 +1,39 @@
+mod api;
+mod audio;
+mod book;
+mod captions;
+mod chat;
+mod cli;
 pub mod config;
+mod control;
+mod dashboard;
+mod dialogue;
+mod dictionary;
+pub mod encoder;
 pub mod error;
+mod eventsub;
+mod helix;
 mod logger;
+mod narrator;
+mod poll;
+pub mod preview;
 mod render;
 mod renderizer;
-// mod streamer;
+mod session;
+mod shutdown;
+mod stats;
+#[cfg(test)]
+mod testing;
 mod tts;
 mod utils;
+mod video;
 
+use std::env;
 use std::io::Write;
 use std::ops;
+use std::process;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use glib::object::ObjectExt;
 use gst::message::StreamsSelected;
@@ -19,8 +43,25 @@ use gstreamer_video as gst_video;
 use gst::prelude::*;
 use pango::prelude::*;
 
-use error::EbookResult;
-use log::{error, info};
+use api::{ControlApi, SharedHealth};
+use audio::{AudioQueue, Mixer, Sounds, AUDIO_SRC_NAME};
+use captions::{Captions, CAPTIONS_SRC_NAME};
+use cli::Cli;
+use config::{ConfigSource, EbookConfig};
+use control::ReaderControl;
+use dashboard::{Dashboard, Feed};
+use dialogue::Cast;
+use dictionary::{Definitions, Dictionary};
+use error::{EbookError, EbookResult};
+use logger::Logger;
+use log::{error, info, warn};
+use poll::Polls;
+use render::{SharedScene, Theme};
+use session::{ReadingSession, SharedSession};
+use shutdown::Shutdown;
+use stats::{OutputStats, MUXER_NAME};
+use tts::{Voice, TTS};
+use video::VIDEO_SRC_NAME;
 
 fn main() {
     if let Err(err) = run() {
@@ -28,32 +69,204 @@ fn main() {
             env_logger::init();
         }
 
-        error!("{err}");
+        // Secrets may be in it and the logger may not be installed yet
+        error!("{}", logger::redacted(&err.to_string()));
+        process::exit(1);
     }
 }
 
 fn run() -> EbookResult<()> {
-    env_logger::init();
+    let cli = Cli::parse(env::args().skip(1))?;
+    if cli.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    let config = EbookConfig::load(&ConfigSource::new(&cli)?)?;
+    if cli.check_config {
+        config.check()?;
+        println!("{config}");
+        return Ok(());
+    }
+
+    let shutdown = Shutdown::install()?;
+    let logs = config.dashboard.then(Feed::default);
+    Logger::init(&config, logs.clone())?;
+    info!("\n{config}");
+
+    let dictionary = config
+        .dictionary
+        .as_deref()
+        .map(Dictionary::open)
+        .transpose()?;
+    let cast = Cast::open(config.dialogue.clone(), config.characters.as_deref())?;
+    let session: SharedSession = Arc::new(Mutex::new(ReadingSession::open(&config.book)?));
+    let scene = SharedScene::default();
+    session.lock().unwrap().update_scene(&mut scene.lock());
+    let queue = AudioQueue::new(&config.encoder.audio);
+    let voice = Arc::new(Mutex::new(Voice {
+        language: config.language.language,
+        detect: config.detect_language,
+        tld: config.voice.clone(),
+        speed: config.speed,
+        pitch: config.pitch,
+        volume: config.volume,
+    }));
+    let theme = Theme::new(config.font.clone());
+    let health = SharedHealth::default();
+    let stats = OutputStats::default();
 
     info!("PIPELINE CREATING");
 
-    let pipeline = create_pipeline()?;
+    let pipeline = create_pipeline(&config)?;
+    let music = config
+        .music
+        .clone()
+        .map(|music| audio::play_music(music, config.encoder.audio, shutdown.clone()));
+    let sounds = Sounds::new(config.sounds.clone(), config.encoder.audio, theme.clone());
+    let mixer = Mixer::new(queue.clone(), sounds.queue(), music, &config.encoder.audio);
+    let captions = Captions::spawn(
+        &config.captions,
+        queue.clone(),
+        &config.encoder.audio,
+        scene.clone(),
+        &pipeline,
+    )?;
+    audio::attach_mixer(&pipeline, &config, mixer, captions.clone())?;
+    video::attach_renderer(
+        &pipeline,
+        &config,
+        scene.clone(),
+        theme.clone(),
+        stats.clone(),
+    )?;
+    stats.attach(&pipeline);
 
     info!("PIPELINE CREATED");
 
+    let preview = preview::attach(&pipeline, &config, shutdown.clone())?;
+    if let Some(preview) = preview.clone().filter(|_| !config.dashboard) {
+        // The dashboard has its own key for it
+        preview.toggle_on_stdin();
+    }
+
     pipeline.set_state(gst::State::Playing).unwrap();
 
     info!("PIPELINE PLAYING");
 
+    let narrator = narrator::spawn(
+        session.clone(),
+        scene.clone(),
+        queue.clone(),
+        TTS::new(config.language.language, None),
+        voice.clone(),
+        cast,
+        config.encoder.audio,
+        config.mastering,
+        sounds.clone(),
+        captions,
+        shutdown.clone(),
+    );
+
+    let control = ReaderControl::new(
+        session.clone(),
+        scene.clone(),
+        queue.clone(),
+        voice.clone(),
+        sounds.clone(),
+    );
+    let definitions = dictionary.map(|dictionary| {
+        Definitions::new(
+            dictionary,
+            scene.clone(),
+            control.clone(),
+            config.define_aloud,
+        )
+    });
+    let chat_feed = config.dashboard.then(Feed::default);
+    let chat = config.chat.clone().map(|chat| {
+        let overlay = (chat.overlay_lines > 0).then(|| {
+            let overlay =
+                chat::ChatOverlay::new(scene.clone(), chat.overlay_lines, &chat.banned_words);
+            overlay.spawn(shutdown.clone());
+            overlay
+        });
+        let polls = Polls::new(
+            control.clone(),
+            scene.clone(),
+            &config.playlist,
+            Duration::from_secs(config.poll_seconds),
+        );
+        polls.spawn(shutdown.clone());
+        chat::spawn(
+            chat,
+            control.clone(),
+            overlay,
+            Some(polls),
+            definitions,
+            chat_feed.clone(),
+            shutdown.clone(),
+        )
+    });
+    if config.chat.is_none() {
+        // Nobody to vote, read the playlist in order
+        session.lock().unwrap().enqueue_playlist(&config.playlist);
+    }
+
+    if let Some(helix) = config.helix.clone() {
+        if let Some(eventsub) = config.eventsub.clone() {
+            eventsub::spawn(eventsub, helix.clone(), control.clone(), shutdown.clone());
+        }
+        helix::spawn(helix, session.clone(), shutdown.clone());
+    }
+
+    if let Some(api) = config.control_api.clone() {
+        ControlApi::new(
+            control.clone(),
+            queue.clone(),
+            theme,
+            health.clone(),
+            config.encoder.audio,
+            &[&[config.book.clone()], config.playlist.as_slice()].concat(),
+        )
+        .serve(api)?;
+    }
+
+    // Closed when dropped, also when returning early
+    let _dashboard = logs.and_then(|logs| {
+        let dashboard = Dashboard {
+            control: control.clone(),
+            queue: queue.clone(),
+            audio: config.encoder.audio,
+            health: health.clone(),
+            stats: stats.clone(),
+            preview,
+            logs,
+            chat: chat_feed.unwrap_or_default(),
+        };
+        dashboard
+            .spawn(shutdown.clone())
+            .map_err(|err| warn!("Cannot open the dashboard: {err}"))
+            .ok()
+    });
+
     let bus = pipeline
         .bus()
         .expect("Pipeline without bus. Shouldn't happen!");
 
-    for msg in bus.iter_timed(gst::ClockTime::SECOND) {
+    let mut eos = false;
+    while !shutdown.is_requested() {
         use gst::MessageView;
 
+        let Some(msg) = bus.timed_pop(gst::ClockTime::from_mseconds(100)) else {
+            continue;
+        };
+
         match msg.view() {
-            MessageView::Eos(..) => break,
+            MessageView::Eos(..) => {
+                eos = true;
+                break;
+            }
             MessageView::Error(err) => {
                 pipeline.set_state(gst::State::Null).unwrap();
 
@@ -63,36 +276,90 @@ fn run() -> EbookResult<()> {
                     .unwrap_or_else(|| glib::GString::from("UNKNOWN"));
                 let error = err.error();
                 let debug = err.debug();
-                error!("Received error from {src}: {error} (debug: {debug:?})");
 
-                return Ok(()); // FIXME: Should be error
+                shutdown.request();
+                _ = narrator.join();
+                session.lock().unwrap().save_bookmark()?;
+
+                return Err(EbookError::Pipeline(format!("{src}: {error} (debug: {debug:?})")));
+            }
+            MessageView::StateChanged(state)
+                if msg.src() == Some(pipeline.upcast_ref::<gst::Object>()) =>
+            {
+                health.lock().unwrap().state = format!("{:?}", state.current());
+            }
+            MessageView::Warning(warning) => {
+                let warning = warning.error().to_string();
+                warn!("{warning}");
+
+                let mut health = health.lock().unwrap();
+                health.warnings += 1;
+                health.last_warning = Some(warning);
             }
             msg => info!("TICK {msg:#?}"),
         }
     }
 
-    pipeline.set_state(gst::State::Null).unwrap();
+    shutdown.request();
+    let stopped = shutdown::stop(&pipeline, &session, &scene, &queue, narrator, eos);
+
+    if let Some(chat) = chat {
+        _ = chat.join();
+    }
 
-    Ok(())
+    stopped
 }
 
-fn create_pipeline() -> EbookResult<gst::Pipeline> {
+/// The RTMP sink
+const OUTPUT_NAME: &str = "output";
+
+fn create_pipeline(config: &EbookConfig) -> EbookResult<gst::Pipeline> {
     if let Err(err) = gst::init() {
         return Err(error::EbookError::Glib(err));
     };
 
-    let stream = {
-        let stream_key = std::env::var("TWITCH_STREAM_KEY").expect("No Stream Key");
-        format!("rtmpsink location=rtmp://live.twitch.tv/app/{stream_key}")
+    let encoder = &config.encoder;
+
+    let audio_caps = encoder.audio_caps();
+    let audio_encoder = encoder.audio_encoder();
+
+    let preview = config.preview.pipeline_branch("raw");
+    // CEA-608 rides on the frames as meta, x264enc turns it into SEI
+    let (combiner, captions) = if config.captions.embed {
+        (
+            "cccombiner name=cc ! ",
+            format!(
+                "appsrc name={CAPTIONS_SRC_NAME} is-live=true format=time caps=text/x-raw,format=utf8 \
+        ! tttocea608 mode=roll-up2 \
+        ! ccconverter \
+        ! cc.caption"
+            ),
+        )
+    } else {
+        ("", String::new())
+    };
+    let video = match (encoder.video_caps(), encoder.video_encoder()) {
+        (Some(video_caps), Some(video_encoder)) => format!(
+            "appsrc name={VIDEO_SRC_NAME} is-live=true block=true \
+        ! {video_caps} \
+        ! tee name=raw \
+          raw. ! queue \
+        ! videoconvert \
+        ! {combiner}{video_encoder} \
+        ! {MUXER_NAME}. \
+        {captions} \
+        {preview}"
+        ),
+        _ => String::new(),
     };
 
-    let pipeline = format!("flvmux name=mux ! {stream} \
-          audiotestsrc samplesperbuffer=44100 \
-        ! mux. \
-          videotestsrc is-live=true pattern=ball \
-        ! video/x-raw,framerate=25/1 \
-        ! x264enc \
-        ! mux. \
+    let pipeline = format!("flvmux name={MUXER_NAME} streamable=true ! rtmpsink name={OUTPUT_NAME} \
+          appsrc name={AUDIO_SRC_NAME} is-live=true block=true \
+        ! {audio_caps} \
+        ! audioconvert \
+        ! {audio_encoder} \
+        ! {MUXER_NAME}. \
+        {video}
     ");
 
     let mut context = gst::ParseContext::new();
@@ -114,6 +381,13 @@ fn create_pipeline() -> EbookResult<gst::Pipeline> {
         .downcast::<gst::Pipeline>()
         .expect("Expected a gst::Pipeline");
 
+    // The location has the stream key, it is kept out of the description
+    // so it cannot leak in parse errors
+    pipeline
+        .by_name(OUTPUT_NAME)
+        .expect("Output element not found")
+        .set_property("location", format!("{}/{}", config.rtmp_url, config.stream_key));
+
     // let overlay = pipeline.by_name("overlay").expect("Sink element not found");
 
     // The PangoFontMap represents the set of fonts available for a particular rendering system.
@@ -418,3 +692,5 @@ unsafe impl Send for LayoutWrapper {}
 pub const PREVIEW_LOG: &str = "\x1b[1;36mPREVIEW\x1b[0m";
 pub const VIDEO_LOG: &str = "\x1b[1;35mVIDEO\x1b[0m";
 pub const AUDIO_LOG: &str = "\x1b[1;34mAUDIO\x1b[0m";
+pub const CHAT_LOG: &str = "\x1b[1;32mCHAT\x1b[0m";
+pub const API_LOG: &str = "\x1b[1;33mAPI\x1b[0m";
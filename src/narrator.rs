@@ -0,0 +1,269 @@
+use std::iter;
+use std::ops::Range;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{error, info, trace, warn};
+
+use crate::audio::{self, AudioQueue, Effects, Mastering, Pause, Sound, Sounds};
+use crate::book::Book;
+use crate::captions::Captions;
+use crate::dialogue::Cast;
+use crate::encoder::AudioSettings;
+use crate::error::{EbookError, EbookResult};
+use crate::render::SharedScene;
+use crate::session::SharedSession;
+use crate::shutdown::Shutdown;
+use crate::tts::{normalize, tokenize, SharedVoice, GOOGLE_TTS_MAX_CHARS, TTS};
+use crate::AUDIO_LOG;
+
+/// Audio left in the queue when the next chunk is pushed. The next chunk is
+/// synthesized while the current one plays, so this only covers the jitter.
+const QUEUE_LOW_SECONDS: f32 = 0.2;
+const IDLE_WAIT: Duration = Duration::from_millis(50);
+/// Tries for every line before the TTS is given up on, the wait doubles
+/// after every failure: about a minute in total
+const TTS_ATTEMPTS: u32 = 7;
+const TTS_RETRY_WAIT: Duration = Duration::from_secs(1);
+
+/// Read the session aloud, one chunk at a time, until the book ends or a
+/// shutdown is requested. The chunk in the audio queue is always the one
+/// shown in the scene.
+pub fn spawn(
+    session: SharedSession,
+    scene: SharedScene,
+    queue: AudioQueue,
+    mut tts: TTS,
+    voice: SharedVoice,
+    cast: Cast,
+    audio: AudioSettings,
+    mastering: Mastering,
+    sounds: Sounds,
+    captions: Captions,
+    shutdown: Shutdown,
+) -> JoinHandle<()> {
+    let low_mark = ((audio.sample_rate * audio.channels) as f32 * QUEUE_LOW_SECONDS) as usize;
+
+    thread::spawn(move || loop {
+        if shutdown.is_requested() {
+            break;
+        }
+
+        let next = {
+            let mut session = session.lock().unwrap();
+            if session.is_paused() {
+                queue.set_idle();
+                None
+            } else if let Some(aside) = session.take_aside() {
+                let lines = vec![(0..aside.len(), None)];
+                Some((None, aside, None, lines, Pause::Sentence, (None, None)))
+            } else if let Some(chunk) = session.next_chunk() {
+                let lines = cast.lines(chunk);
+                let language = chunk.language;
+                let pause = pause_after(session.book(), session.position());
+                let sounds = sounds_around(session.book(), session.position());
+                Some((
+                    Some(session.position()),
+                    chunk.text.clone(),
+                    language,
+                    lines,
+                    pause,
+                    sounds,
+                ))
+            } else if !queue.is_empty() {
+                // The last sentence of the book plays, what comes next is
+                // decided once it is heard
+                queue.set_idle();
+                None
+            } else if !session.upcoming().is_empty() {
+                // The last sentence was heard, go on with the next book
+                session.finish_playing();
+                if let Err(err) = session.next_book() {
+                    warn!(target: AUDIO_LOG, "Skipping book: {err}");
+                }
+                session.update_scene(&mut scene.lock());
+                continue;
+            } else if session.book_vote() {
+                // Viewers are still choosing the next book
+                session.finish_playing();
+                queue.set_idle();
+                None
+            } else {
+                info!(target: AUDIO_LOG, "Book finished");
+                session.finish_playing();
+                queue.set_idle();
+                shutdown.request();
+                break;
+            }
+        };
+
+        let Some((position, text, detected, lines, pause, (before, after))) = next else {
+            thread::sleep(IDLE_WAIT);
+            continue;
+        };
+
+        trace!(target: AUDIO_LOG, "Synthesizing {position:?}: {text:?}");
+        let mut samples = vec![];
+        if let Some(clip) = before.and_then(|sound| sounds.clip(sound)) {
+            samples.extend_from_slice(&clip);
+        }
+        let speech_start = samples.len();
+        for (range, role) in lines {
+            let line = &text[range];
+            let (effects, spoken) = {
+                let voice = voice.lock().unwrap();
+                let language = match detected {
+                    Some(language) if voice.detect => language,
+                    _ => voice.language,
+                };
+                let role = role.unwrap_or_default();
+                tts.set_language(language);
+                tts.set_tld(role.tld.as_deref().unwrap_or(&voice.tld));
+                let effects = Effects {
+                    speed: voice.speed,
+                    pitch: voice.pitch * role.pitch.unwrap_or(1.),
+                    volume: voice.volume,
+                };
+                (effects, normalize(line, language))
+            };
+
+            for span in &spoken.spans {
+                trace!(
+                    target: AUDIO_LOG,
+                    "Reading {:?} as {:?}",
+                    &line[span.display.clone()],
+                    &spoken.text[span.spoken.clone()]
+                );
+            }
+            // Nothing is pushed nor marked as read until every line is
+            // synthesized, the bookmark stays on what was not heard
+            match synthesize_retrying(&tts, &spoken.text, &audio, &shutdown) {
+                Some(Ok(line)) => {
+                    let line = mastering.apply(line, &audio);
+                    samples.extend(effects.apply(line, &audio));
+                }
+                Some(Err(err)) => {
+                    error!(target: AUDIO_LOG, "Giving up on the TTS, stopping: {err}");
+                    shutdown.request();
+                    return;
+                }
+                None => return,
+            }
+        }
+        let speech = speech_start as u64..samples.len() as u64;
+        samples.extend(mastering.pause(pause, &audio));
+        if let Some(clip) = after.and_then(|sound| sounds.clip(sound)) {
+            samples.extend_from_slice(&clip);
+        }
+
+        while queue.len() > low_mark && !shutdown.is_requested() {
+            thread::sleep(IDLE_WAIT);
+        }
+
+        if shutdown.is_requested() {
+            break;
+        }
+
+        let mut session = session.lock().unwrap();
+        if session.is_paused() {
+            // The aside is read on resume, the chunk is taken again anyway
+            if position.is_none() {
+                let pieces = iter::once(text)
+                    .chain(iter::from_fn(|| session.take_aside()))
+                    .collect();
+                session.set_aside(pieces);
+            }
+            continue;
+        }
+
+        // Asides are heard after the chunk being played, the scene stays
+        let Some(position) = position else {
+            push(&queue, &captions, &samples, &text, speech);
+            continue;
+        };
+
+        if session.position() != position {
+            // Seeked while synthesizing
+            continue;
+        }
+
+        push(&queue, &captions, &samples, &text, speech);
+        session.start_playing(position);
+        session.update_scene(&mut scene.lock());
+    })
+}
+
+/// `text` is captioned while the `speech` samples are heard
+fn push(queue: &AudioQueue, captions: &Captions, samples: &[f32], text: &str, speech: Range<u64>) {
+    let start = queue.pushed();
+    captions.cue(text, start + speech.start, start + speech.end);
+    queue.push(samples);
+}
+
+/// [`synthesize`], trying again with a growing wait while it fails. `None`
+/// when a shutdown is requested meanwhile.
+fn synthesize_retrying(
+    tts: &TTS,
+    text: &str,
+    audio: &AudioSettings,
+    shutdown: &Shutdown,
+) -> Option<EbookResult<Vec<f32>>> {
+    let mut wait = TTS_RETRY_WAIT;
+    let mut attempt = 1;
+    loop {
+        match synthesize(tts, text, audio) {
+            Ok(samples) => return Some(Ok(samples)),
+            Err(err) if attempt == TTS_ATTEMPTS => return Some(Err(err)),
+            Err(err) => {
+                warn!(target: AUDIO_LOG, "Cannot synthesize {text:?}, retrying in {wait:?}: {err}")
+            }
+        }
+
+        let until = Instant::now() + wait;
+        while Instant::now() < until {
+            if shutdown.is_requested() {
+                return None;
+            }
+            thread::sleep(IDLE_WAIT);
+        }
+        wait *= 2;
+        attempt += 1;
+    }
+}
+
+/// Numbers read aloud are longer than written, so the text may need more
+/// than one request
+fn synthesize(tts: &TTS, text: &str, audio: &AudioSettings) -> EbookResult<Vec<f32>> {
+    let mut samples = vec![];
+    for piece in tokenize(text, GOOGLE_TTS_MAX_CHARS) {
+        let encoded = tts.generate_audio(&piece).map_err(EbookError::Tts)?;
+        samples.extend(audio::decode(encoded, audio)?);
+    }
+    Ok(samples)
+}
+
+/// What ends with the chunk at `position`, the end of the book is the end of
+/// a chapter
+fn pause_after(book: &Book, position: usize) -> Pause {
+    let chunk = &book.chunks[position];
+    match book.chunks.get(position + 1) {
+        Some(next) if next.paragraph == chunk.paragraph => Pause::Sentence,
+        Some(next) if next.chapter == chunk.chapter => Pause::Paragraph,
+        _ => Pause::Chapter,
+    }
+}
+
+/// Sounds heard before and after the chunk at `position`
+fn sounds_around(book: &Book, position: usize) -> (Option<Sound>, Option<Sound>) {
+    let chunk = &book.chunks[position];
+    let starts_chapter = book
+        .chapters
+        .get(chunk.chapter)
+        .is_some_and(|chapter| chapter.first_chunk == position);
+    let ends_book = position + 1 == book.chunks.len();
+
+    (
+        starts_chapter.then_some(Sound::Chapter),
+        ends_book.then_some(Sound::BookEnd),
+    )
+}
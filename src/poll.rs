@@ -0,0 +1,452 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::audio::Sound;
+use crate::book::same_book;
+use crate::control::ReaderControl;
+use crate::render::SharedScene;
+use crate::renderizer::hot_lib::PollView;
+use crate::shutdown::Shutdown;
+
+/// Share of the book read when the next book is put to a vote
+const BOOK_POLL_AT: f32 = 0.9;
+const MAX_OPTIONS: usize = 4;
+/// A chapter that started fewer chunks ago than this counts as not started,
+/// the re-read option is for the previous one
+const CHAPTER_GRACE: usize = 5;
+/// Time the result stays on screen
+const RESULT: Duration = Duration::from_secs(8);
+const TICK: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollKind {
+    /// Next book from the playlist
+    Book,
+    /// Continue or read the last chapter again
+    Chapter,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Choice {
+    Book(PathBuf),
+    Continue,
+    Reread(usize),
+}
+
+#[derive(Debug)]
+struct Poll {
+    title: String,
+    options: Vec<(String, Choice)>,
+    /// login -> option
+    votes: HashMap<String, usize>,
+    ends_at: Instant,
+    winner: Option<usize>,
+}
+
+impl Poll {
+    fn tally(&self) -> Vec<usize> {
+        let mut tally = vec![0; self.options.len()];
+        for option in self.votes.values() {
+            tally[*option] += 1;
+        }
+        tally
+    }
+
+    /// Most voted option, the first one wins ties and polls without votes
+    fn winner(&self) -> usize {
+        // `max_by_key` keeps the last maximum, so reversed it is the first
+        self.tally()
+            .into_iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, votes)| *votes)
+            .map_or(0, |(idx, _)| idx)
+    }
+
+    fn view(&self, now: Instant) -> PollView {
+        PollView {
+            title: self.title.clone(),
+            options: self
+                .options
+                .iter()
+                .map(|(label, _)| label.clone())
+                .zip(self.tally())
+                .collect(),
+            seconds_left: self.ends_at.saturating_duration_since(now).as_secs(),
+            winner: self.winner,
+        }
+    }
+
+    fn announcement(&self, duration: Duration) -> String {
+        let options = self
+            .options
+            .iter()
+            .enumerate()
+            .map(|(idx, (label, _))| format!("!vote {} {label}", idx + 1))
+            .collect::<Vec<_>>()
+            .join(" | ");
+        format!("{} ({}s): {options}", self.title, duration.as_secs())
+    }
+}
+
+/// Viewer polls, one at a time. The next book is put to a vote on its own
+/// near the end of every book, the winner is applied to the reading session
+/// when the time is up. With a playlist the stream does not end with the
+/// book while that vote is pending.
+#[derive(Debug, Clone)]
+pub struct Polls {
+    current: Arc<Mutex<Option<Poll>>>,
+    /// Book whose successor was already put to a vote
+    book_polled: Arc<Mutex<Option<PathBuf>>>,
+    /// Of the polls started on their own, for the chat
+    announcements: Arc<Mutex<Vec<String>>>,
+    control: ReaderControl,
+    scene: SharedScene,
+    playlist: Arc<[PathBuf]>,
+    duration: Duration,
+}
+
+impl Polls {
+    pub fn new(
+        control: ReaderControl,
+        scene: SharedScene,
+        playlist: &[PathBuf],
+        duration: Duration,
+    ) -> Self {
+        control.session().set_book_vote(!playlist.is_empty());
+
+        Self {
+            current: Arc::default(),
+            book_polled: Arc::default(),
+            announcements: Arc::default(),
+            control,
+            scene,
+            playlist: playlist.into(),
+            duration,
+        }
+    }
+
+    /// Returns the announcement for the chat, `None` if a poll is already
+    /// running or there is nothing to choose from
+    pub fn start(&self, kind: PollKind) -> Option<String> {
+        let mut current = self.current.lock().unwrap();
+        if current.as_ref().is_some_and(|poll| poll.winner.is_none()) {
+            return None;
+        }
+
+        if kind == PollKind::Book {
+            let book = self.control.session().book().path.clone();
+            *self.book_polled.lock().unwrap() = Some(book);
+        }
+
+        let (title, options) = match kind {
+            PollKind::Book => ("Next book".to_owned(), self.book_options()),
+            PollKind::Chapter => self.chapter_options()?,
+        };
+        if options.is_empty() {
+            if kind == PollKind::Book {
+                // No other book to read, the stream ends with this one
+                self.control.session().set_book_vote(false);
+            }
+            return None;
+        }
+
+        let poll = Poll {
+            title,
+            options,
+            votes: HashMap::new(),
+            ends_at: Instant::now() + self.duration,
+            winner: None,
+        };
+        info!("Poll started: {}", poll.title);
+        let announcement = poll.announcement(self.duration);
+        *current = Some(poll);
+
+        Some(announcement)
+    }
+
+    /// `option` starts at 1, like in `!vote 1`. Voting again changes the
+    /// vote.
+    pub fn vote(&self, login: &str, option: usize) -> bool {
+        let mut current = self.current.lock().unwrap();
+        let Some(poll) = current.as_mut().filter(|poll| poll.winner.is_none()) else {
+            return false;
+        };
+
+        if option == 0 || option > poll.options.len() {
+            return false;
+        }
+
+        poll.votes.insert(login.to_owned(), option - 1);
+        true
+    }
+
+    /// Announcements of the polls nobody asked for, to be said in the chat
+    pub fn take_announcements(&self) -> Vec<String> {
+        std::mem::take(&mut *self.announcements.lock().unwrap())
+    }
+
+    /// Close polls on time and start the book ones, until shutdown
+    pub fn spawn(&self, shutdown: Shutdown) -> JoinHandle<()> {
+        let polls = self.clone();
+        thread::spawn(move || {
+            while !shutdown.is_requested() {
+                polls.tick();
+                thread::sleep(TICK);
+            }
+        })
+    }
+
+    fn tick(&self) {
+        if self.should_poll_book() {
+            if let Some(announcement) = self.start(PollKind::Book) {
+                self.announcements.lock().unwrap().push(announcement);
+            }
+        }
+
+        let now = Instant::now();
+        let mut current = self.current.lock().unwrap();
+        let mut finished = false;
+
+        if let Some(poll) = current.as_mut() {
+            if poll.winner.is_none() && now >= poll.ends_at {
+                let winner = poll.winner();
+                poll.winner = Some(winner);
+                poll.ends_at = now + RESULT;
+
+                let (label, choice) = &poll.options[winner];
+                info!("Poll finished: {label}");
+                self.apply(choice.clone());
+                finished = true;
+            } else if poll.winner.is_some() && now >= poll.ends_at {
+                *current = None;
+            }
+        }
+
+        let view = current.as_ref().map(|poll| poll.view(now));
+        drop(current);
+        if finished {
+            self.control.play_sound(Sound::Poll);
+        }
+
+        let mut scene = self.scene.lock();
+        if scene.poll != view {
+            scene.poll = view;
+        }
+    }
+
+    fn apply(&self, choice: Choice) {
+        match choice {
+            Choice::Book(path) => self.control.play_next(path),
+            Choice::Reread(chapter) => self.control.seek_chapter(chapter),
+            Choice::Continue => {}
+        }
+    }
+
+    fn should_poll_book(&self) -> bool {
+        if self.playlist.is_empty() {
+            return false;
+        }
+
+        let session = self.control.session();
+        let polled = self.book_polled.lock().unwrap();
+
+        session.progress() >= BOOK_POLL_AT
+            && session.upcoming().is_empty()
+            && !polled
+                .as_deref()
+                .is_some_and(|polled| same_book(polled, &session.book().path))
+    }
+
+    /// The books after the current one in the playlist
+    fn book_options(&self) -> Vec<(String, Choice)> {
+        let current = self.control.session().book().path.clone();
+        let start = self
+            .playlist
+            .iter()
+            .position(|path| same_book(path, &current))
+            .map_or(0, |idx| idx + 1);
+
+        self.playlist
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(self.playlist.len())
+            .filter(|path| !same_book(path, &current))
+            .take(MAX_OPTIONS)
+            .map(|path| (book_label(path), Choice::Book(path.clone())))
+            .collect()
+    }
+
+    fn chapter_options(&self) -> Option<(String, Vec<(String, Choice)>)> {
+        let session = self.control.session();
+        let chapters = &session.book().chapters;
+
+        let mut chapter = session.current_chapter();
+        let started = session
+            .current_position()
+            .saturating_sub(chapters.get(chapter)?.first_chunk);
+        if started < CHAPTER_GRACE && chapter > 0 {
+            chapter -= 1;
+        }
+
+        let label = chapters[chapter].to_string();
+        Some((
+            format!("Re-read {label}?"),
+            vec![
+                ("Continue".to_owned(), Choice::Continue),
+                (format!("Re-read {label}"), Choice::Reread(chapter)),
+            ],
+        ))
+    }
+}
+
+fn book_label(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn poll(options: usize, votes: &[(&str, usize)]) -> Poll {
+        Poll {
+            title: "Poll".to_owned(),
+            options: (0..options)
+                .map(|idx| (idx.to_string(), Choice::Reread(idx)))
+                .collect(),
+            votes: votes
+                .iter()
+                .map(|(login, option)| (login.to_string(), *option))
+                .collect(),
+            ends_at: Instant::now(),
+            winner: None,
+        }
+    }
+
+    /// Polls over a playlist with the current book in the middle
+    fn polls(control: ReaderControl) -> Polls {
+        let dir = testing::temp_dir();
+        let current = control.session().book().path.clone();
+        let playlist = ["a.txt", "b.txt", "", "c.txt", "d.txt", "e.txt"].map(|name| match name {
+            "" => current.clone(),
+            name => dir.join(name),
+        });
+        Polls::new(
+            control,
+            SharedScene::default(),
+            &playlist,
+            Duration::from_secs(60),
+        )
+    }
+
+    fn labels(options: &[(String, Choice)]) -> Vec<&str> {
+        options.iter().map(|(label, _)| label.as_str()).collect()
+    }
+
+    #[test]
+    fn winner() {
+        assert_eq!(poll(3, &[]).winner(), 0);
+        assert_eq!(poll(3, &[("ana", 2), ("bea", 1)]).winner(), 1);
+        assert_eq!(poll(3, &[("ana", 2), ("bea", 2), ("eva", 1)]).winner(), 2);
+        assert_eq!(poll(3, &[("ana", 2), ("bea", 0)]).winner(), 0);
+    }
+
+    #[test]
+    fn votes() {
+        let polls = polls(testing::control());
+        assert!(!polls.vote("ana", 1));
+
+        polls.start(PollKind::Book).unwrap();
+        assert!(polls.vote("ana", 1));
+        assert!(polls.vote("bea", 1));
+        // Voting again moves the vote
+        assert!(polls.vote("ana", 4));
+        for option in [0, 5, usize::MAX] {
+            assert!(!polls.vote("eva", option), "{option}");
+        }
+
+        let current = polls.current.lock().unwrap();
+        assert_eq!(current.as_ref().unwrap().tally(), [1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn book_options_wrap_around() {
+        let polls = polls(testing::control());
+        // Only the other books, starting after the current one
+        assert_eq!(labels(&polls.book_options()), ["c", "d", "e", "a"]);
+
+        // Not in the playlist, from the start
+        let mut playlist = polls.playlist.to_vec();
+        playlist.remove(2);
+        let polls = Polls::new(
+            polls.control.clone(),
+            SharedScene::default(),
+            &playlist[..3],
+            Duration::from_secs(60),
+        );
+        assert_eq!(labels(&polls.book_options()), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn chapter_grace() {
+        // One sentence per paragraph, every one is a chunk
+        let chapter = (1..=CHAPTER_GRACE + 2)
+            .map(|n| format!("Frase número {n}."))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let control = testing::control();
+        {
+            let mut session = control.session();
+            session.enqueue(testing::book(&format!(
+                "Prólogo.\n\nI.\n\n{chapter}\n\nII.\n\n{chapter}\n"
+            )));
+            session.next_book().unwrap();
+        }
+        let polls = polls(control.clone());
+        let chapters = control.session().book().chapters.clone();
+        let reread = |position: usize| {
+            control.seek(position);
+            match polls.chapter_options().unwrap().1.as_slice() {
+                [(_, Choice::Continue), (_, Choice::Reread(chapter))] => *chapter,
+                options => panic!("{options:?}"),
+            }
+        };
+
+        // Just started, the one before is offered
+        assert_eq!(reread(chapters[2].first_chunk), 1);
+        assert_eq!(reread(chapters[2].first_chunk + CHAPTER_GRACE - 1), 1);
+        assert_eq!(reread(chapters[2].first_chunk + CHAPTER_GRACE), 2);
+        // Nothing before the first one
+        assert_eq!(reread(0), 0);
+    }
+
+    #[test]
+    fn book_poll_is_announced() {
+        let control = testing::control();
+        let polls = polls(control.clone());
+        polls.tick();
+        assert!(polls.take_announcements().is_empty());
+
+        let chunks = control.session().book().chunks.len();
+        control.seek(chunks);
+        polls.tick();
+        assert_eq!(
+            polls.take_announcements(),
+            ["Next book (60s): !vote 1 c | !vote 2 d | !vote 3 e | !vote 4 a"]
+        );
+        assert!(polls.scene.lock().poll.is_some());
+
+        // Once per book
+        polls.tick();
+        assert!(polls.take_announcements().is_empty());
+    }
+}
@@ -0,0 +1,311 @@
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use log::{debug, error, info, warn};
+
+use crate::config::EbookConfig;
+use crate::error::{EbookError, EbookResult};
+use crate::shutdown::Shutdown;
+use crate::PREVIEW_LOG;
+
+pub const DEFAULT_PREVIEW_ADDR: &str = "127.0.0.1:8090";
+
+const PREVIEW_VALVE_NAME: &str = "preview-valve";
+const PREVIEW_SINK_NAME: &str = "preview-sink";
+const PREVIEW_HTTP_FPS: u32 = 10;
+const BOUNDARY: &str = "ebookframe";
+/// Clients served at once, the rest are turned away. Every one of them
+/// gets every frame.
+const MAX_CLIENTS: usize = 4;
+/// Clients waiting for a frame look for a shutdown or a closed socket this
+/// often, no frames come while the valve is closed
+const FRAME_WAIT: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewMode {
+    Off,
+    /// Local window through `autovideosink`
+    Window,
+    /// MJPEG over HTTP, for headless machines. Only on loopback addresses,
+    /// there is no authentication.
+    Http(SocketAddr),
+}
+
+impl PreviewMode {
+    /// Parse the `PREVIEW` environment variable. `addr` is only used by the
+    /// http mode.
+    pub fn parse(value: &str, addr: Option<&str>) -> EbookResult<Self> {
+        match value {
+            "" | "0" | "false" | "off" => Ok(Self::Off),
+            "1" | "true" | "window" => Ok(Self::Window),
+            "http" => {
+                let addr = addr.unwrap_or(DEFAULT_PREVIEW_ADDR);
+                let socket = SocketAddr::from_str(addr)
+                    .map_err(|_| EbookError::InvalidPreviewAddress(addr.to_owned()))?;
+                if !socket.ip().is_loopback() {
+                    return Err(EbookError::PublicPreviewAddress(addr.to_owned()));
+                }
+                Ok(Self::Http(socket))
+            }
+            _ => Err(EbookError::InvalidPreviewMode(value.to_owned())),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        *self != Self::Off
+    }
+
+    /// Pipeline branch tapping the raw video `tee` named `tee`
+    pub fn pipeline_branch(&self, tee: &str) -> String {
+        let sink = match self {
+            Self::Off => return String::new(),
+            Self::Window => "videoconvert ! autovideosink sync=false async=false".to_owned(),
+            Self::Http(_) => format!(
+                "videorate drop-only=true \
+                ! video/x-raw,framerate={PREVIEW_HTTP_FPS}/1 \
+                ! videoconvert \
+                ! jpegenc quality=70 \
+                ! appsink name={PREVIEW_SINK_NAME} sync=false async=false max-buffers=1 drop=true"
+            ),
+        };
+
+        format!(
+            "{tee}. ! queue leaky=downstream max-size-buffers=2 \
+            ! valve name={PREVIEW_VALVE_NAME} drop=false \
+            ! {sink}"
+        )
+    }
+}
+
+impl fmt::Display for PreviewMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => f.write_str("off"),
+            Self::Window => f.write_str("window"),
+            Self::Http(addr) => write!(f, "http://{addr}"),
+        }
+    }
+}
+
+/// Runtime switch of the preview branch. Closing the valve stops the
+/// conversion and the preview sink, the stream itself is not touched.
+#[derive(Debug, Clone)]
+pub struct PreviewHandle {
+    valve: gst::Element,
+}
+
+impl PreviewHandle {
+    pub fn is_enabled(&self) -> bool {
+        !self.valve.property::<bool>("drop")
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.valve.set_property("drop", !enabled);
+        info!(target: PREVIEW_LOG, "Preview {}", if enabled { "enabled" } else { "disabled" });
+    }
+
+    pub fn toggle(&self) {
+        self.set_enabled(!self.is_enabled());
+    }
+
+    /// Toggle the preview every time `preview` is written to stdin
+    pub fn toggle_on_stdin(self) {
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                if line.trim() == "preview" {
+                    self.toggle();
+                }
+            }
+        });
+    }
+}
+
+/// Last encoded JPEG, with a counter so clients only send new frames
+type SharedFrame = Arc<(Mutex<(u64, Arc<Vec<u8>>)>, Condvar)>;
+
+/// Connect the preview branch created by [`PreviewMode::pipeline_branch`].
+/// Returns `None` when the preview is disabled or there is no video.
+pub fn attach(
+    pipeline: &gst::Pipeline,
+    config: &EbookConfig,
+    shutdown: Shutdown,
+) -> EbookResult<Option<PreviewHandle>> {
+    if !config.preview.is_enabled() || config.encoder.video.is_none() {
+        return Ok(None);
+    }
+
+    let valve = pipeline
+        .by_name(PREVIEW_VALVE_NAME)
+        .expect("Preview valve element not found");
+
+    if let PreviewMode::Http(addr) = config.preview {
+        let appsink = pipeline
+            .by_name(PREVIEW_SINK_NAME)
+            .expect("Preview sink element not found")
+            .downcast::<gst_app::AppSink>()
+            .expect("Expected a gst_app::AppSink");
+
+        let frame: SharedFrame = Arc::default();
+        serve_mjpeg(addr, frame.clone(), shutdown)?;
+
+        appsink.set_callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |appsink| {
+                    let sample = appsink.pull_sample().map_err(|_| gst::FlowError::Eos)?;
+                    let buffer = sample.buffer().ok_or(gst::FlowError::Error)?;
+                    let map = buffer.map_readable().map_err(|_| gst::FlowError::Error)?;
+
+                    let (lock, cvar) = &*frame;
+                    let mut frame = lock.lock().unwrap();
+                    *frame = (frame.0 + 1, Arc::new(map.to_vec()));
+                    cvar.notify_all();
+
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .build(),
+        );
+    }
+
+    info!(target: PREVIEW_LOG, "Preview on {}", config.preview);
+
+    Ok(Some(PreviewHandle { valve }))
+}
+
+fn serve_mjpeg(addr: SocketAddr, frame: SharedFrame, shutdown: Shutdown) -> EbookResult<()> {
+    let listener =
+        TcpListener::bind(addr).map_err(|err| EbookError::PreviewServer(err.to_string()))?;
+
+    let clients = Arc::new(AtomicUsize::new(0));
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(mut stream) => {
+                    if clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+                        clients.fetch_sub(1, Ordering::SeqCst);
+                        debug!(target: PREVIEW_LOG, "Too many clients");
+                        _ = stream.write_all(
+                            b"HTTP/1.1 503 Service Unavailable\r\n\
+                            Connection: close\r\nContent-Length: 0\r\n\r\n",
+                        );
+                        continue;
+                    }
+
+                    let frame = frame.clone();
+                    let shutdown = shutdown.clone();
+                    let clients = clients.clone();
+                    thread::spawn(move || {
+                        let peer = stream.peer_addr().ok();
+                        debug!(target: PREVIEW_LOG, "Client connected {peer:?}");
+                        if let Err(err) = send_mjpeg(stream, frame, shutdown) {
+                            debug!(target: PREVIEW_LOG, "Client {peer:?} disconnected: {err}");
+                        }
+                        clients.fetch_sub(1, Ordering::SeqCst);
+                    });
+                }
+                Err(err) => warn!(target: PREVIEW_LOG, "{err}"),
+            }
+        }
+
+        error!(target: PREVIEW_LOG, "Preview server stopped");
+    });
+
+    Ok(())
+}
+
+fn send_mjpeg(mut stream: TcpStream, frame: SharedFrame, shutdown: Shutdown) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\n\
+        Cache-Control: no-cache\r\n\
+        Connection: close\r\n\
+        Content-Type: multipart/x-mixed-replace; boundary={BOUNDARY}\r\n\r\n"
+    )?;
+
+    let (lock, cvar) = &*frame;
+    let mut last_sent = 0;
+
+    loop {
+        let jpeg = {
+            let mut current = lock.lock().unwrap();
+            while current.0 == last_sent {
+                let (guard, timeout) = cvar.wait_timeout(current, FRAME_WAIT).unwrap();
+                current = guard;
+                if !timeout.timed_out() {
+                    continue;
+                }
+                if shutdown.is_requested() {
+                    return Ok(());
+                }
+                if is_closed(&mut stream)? {
+                    return Err(io::ErrorKind::ConnectionReset.into());
+                }
+            }
+            last_sent = current.0;
+            current.1.clone()
+        };
+
+        write!(
+            stream,
+            "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            jpeg.len()
+        )?;
+        stream.write_all(&jpeg)?;
+        stream.write_all(b"\r\n")?;
+        stream.flush()?;
+    }
+}
+
+/// The client hung up. Whatever it sent is read and ignored, nothing else
+/// is expected from it.
+fn is_closed(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut buffer = [0; 1024];
+    let closed = loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => break Ok(true),
+            Ok(_) => continue,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break Ok(false),
+            Err(err) => break Err(err),
+        }
+    };
+    stream.set_nonblocking(false)?;
+
+    closed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_only_on_loopback() {
+        assert_eq!(
+            PreviewMode::parse("http", None).unwrap(),
+            PreviewMode::Http(DEFAULT_PREVIEW_ADDR.parse().unwrap())
+        );
+        assert!(PreviewMode::parse("http", Some("[::1]:8090")).is_ok());
+        for addr in ["0.0.0.0:8090", "192.168.1.10:8090", "[::]:8090"] {
+            assert!(matches!(
+                PreviewMode::parse("http", Some(addr)),
+                Err(EbookError::PublicPreviewAddress(_))
+            ));
+        }
+        assert!(matches!(
+            PreviewMode::parse("http", Some("localhost")),
+            Err(EbookError::InvalidPreviewAddress(_))
+        ));
+    }
+}
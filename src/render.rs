@@ -1,25 +1,119 @@
+use std::fs;
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
 
 use flo_render::initialize_offscreen_rendering;
 use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
-use futures::{executor, SinkExt};
-use log::{error, trace};
+use futures::{executor, SinkExt, StreamExt};
+use log::{error, info, trace, warn};
 
+use crate::encoder::VideoSettings;
+use crate::renderizer::hot_lib::Scene;
 use crate::renderizer::Renderizer;
 use crate::utils::get_last_message;
 use crate::VIDEO_LOG;
 
+/// The scene every thread draws into. Changing it bumps a generation, so
+/// static frames are only drawn again when something moved.
+#[derive(Debug, Clone, Default)]
+pub struct SharedScene {
+    scene: Arc<Mutex<Scene>>,
+    generation: Arc<AtomicU64>,
+}
+
+impl SharedScene {
+    pub fn lock(&self) -> SceneGuard<'_> {
+        SceneGuard {
+            scene: self.scene.lock().unwrap(),
+            generation: &self.generation,
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+}
+
+/// Access to the scene, borrowing it mutably counts as a change
+pub struct SceneGuard<'a> {
+    scene: MutexGuard<'a, Scene>,
+    generation: &'a AtomicU64,
+}
+
+impl Deref for SceneGuard<'_> {
+    type Target = Scene;
+
+    fn deref(&self) -> &Scene {
+        &self.scene
+    }
+}
+
+impl DerefMut for SceneGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Scene {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        &mut self.scene
+    }
+}
+
+/// Assets of the scene, they can be read again while streaming
+#[derive(Debug, Clone)]
+pub struct Theme {
+    font: Option<PathBuf>,
+    /// Bumped on every reload
+    generation: Arc<AtomicU64>,
+}
+
+impl Theme {
+    pub fn new(font: Option<PathBuf>) -> Self {
+        Self {
+            font,
+            generation: Arc::default(),
+        }
+    }
+
+    /// Read the assets again before the next frame
+    pub fn reload(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        info!(target: VIDEO_LOG, "Reloading theme");
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    fn load_font(&self) -> Option<Vec<u8>> {
+        let path = self.font.as_ref()?;
+        match fs::read(path) {
+            Ok(font) => Some(font),
+            Err(err) => {
+                warn!(target: VIDEO_LOG, "Cannot load font {}, no text will be shown: {err}", path.display());
+                None
+            }
+        }
+    }
+}
+
 pub struct EbookRenderer {
     frame_rx: UnboundedReceiver<Vec<u8>>,
     tick_tx: UnboundedSender<()>,
 }
 
 impl EbookRenderer {
-    pub fn new() -> Self {
+    /// With `static_frames` a frame is only sent when the drawing changed,
+    /// [`EbookRenderer::recv`] returns `None` while the page stays the same.
+    pub fn new(
+        video: VideoSettings,
+        static_frames: bool,
+        scene: SharedScene,
+        theme: Theme,
+    ) -> Self {
         let (frame_tx, frame_rx) = mpsc::unbounded::<Vec<u8>>();
         let (tick_tx, tick_rx) = mpsc::unbounded::<()>();
 
-        Self::start_thread(frame_tx, tick_rx);
+        Self::start_thread(video, static_frames, scene, theme, frame_tx, tick_rx);
 
         Self { frame_rx, tick_tx }
     }
@@ -34,23 +128,45 @@ impl EbookRenderer {
         get_last_message(&mut self.frame_rx)
     }
 
-    fn start_thread(frame_tx: UnboundedSender<Vec<u8>>, mut tick_rx: UnboundedReceiver<()>) {
+    fn start_thread(
+        video: VideoSettings,
+        static_frames: bool,
+        scene: SharedScene,
+        theme: Theme,
+        frame_tx: UnboundedSender<Vec<u8>>,
+        mut tick_rx: UnboundedReceiver<()>,
+    ) {
         thread::spawn(move || {
             // Create an offscreen context
             let render_context = initialize_offscreen_rendering().unwrap();
-            let mut renderizer = Renderizer::new(render_context);
+            let mut generation = theme.generation();
+            let font = theme.load_font();
+            let mut renderizer = Renderizer::new(render_context, &video, scene, font.as_deref());
 
             let mut tx = frame_tx;
 
-            loop {
-                let Some(_) = get_last_message(&mut tick_rx) else {
-                    continue;
-                };
+            // Ticks that piled up while rendering are a single one, the
+            // video feeder is gone when the sender is dropped
+            while executor::block_on(tick_rx.next()).is_some() {
+                get_last_message(&mut tick_rx);
+
+                if theme.generation() != generation {
+                    generation = theme.generation();
+                    renderizer.set_font(theme.load_font().as_deref());
+                }
 
                 trace!(target: VIDEO_LOG, "RENDERING");
 
                 executor::block_on(async {
-                    let buf = renderizer.render_async().await;
+                    let buf = if static_frames {
+                        let Some(buf) = renderizer.render_if_changed_async().await else {
+                            return;
+                        };
+                        buf
+                    } else {
+                        renderizer.render_async().await
+                    };
+
                     if let Err(err) = tx.send(buf).await {
                         error!(target: VIDEO_LOG, "{err}");
                     }
@@ -59,3 +175,47 @@ impl EbookRenderer {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn changes_bump_the_generation() {
+        let scene = SharedScene::default();
+        let clone = scene.clone();
+        assert_eq!(scene.generation(), 0);
+
+        // Reading is not a change
+        assert_eq!(scene.lock().title, "");
+        assert!(!scene.lock().ending);
+        assert_eq!(scene.generation(), 0);
+
+        scene.lock().title = "Don Quijote".to_owned();
+        assert_eq!(scene.generation(), 1);
+        clone.lock().progress = 0.5;
+        assert_eq!(scene.generation(), 2);
+        assert_eq!(clone.lock().title, "Don Quijote");
+
+        // Counted even when nothing moved, the drawing is compared then
+        let mut guard = scene.lock();
+        guard.progress = 0.5;
+        guard.progress = 0.5;
+        drop(guard);
+        assert_eq!(clone.generation(), 4);
+    }
+
+    #[test]
+    fn theme_reloads_bump_the_generation() {
+        let theme = Theme::new(None);
+        assert_eq!(theme.generation(), 0);
+        assert_eq!(theme.load_font(), None);
+
+        theme.clone().reload();
+        assert_eq!(theme.generation(), 1);
+
+        let missing = Theme::new(Some(testing::temp_dir().join("font.ttf")));
+        assert_eq!(missing.load_font(), None);
+    }
+}
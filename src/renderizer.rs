@@ -1,15 +1,19 @@
 use std::borrow::BorrowMut;
 use std::sync::{Arc, Mutex};
 
+use flo_canvas::Draw;
 use flo_render::OffscreenRenderContext;
 use flo_render_canvas::render_canvas_offscreen;
 use futures::stream;
 
+use crate::encoder::VideoSettings;
+use crate::render::SharedScene;
+
 #[cfg(feature = "hot-reload")]
 #[hot_lib_reloader::hot_module(dylib = "lib")]
 pub mod hot_lib {
     use flo_canvas::Draw;
-    pub use lib::EbookContext;
+    pub use lib::{ChatLine, ChatPart, DefinitionCard, EbookContext, PollView, Scene};
 
     hot_functions_from_file!("lib/src/lib.rs");
 }
@@ -19,13 +23,15 @@ pub mod hot_lib {
     pub use lib::*;
 }
 
-pub const WIDTH: usize = 1280;
-pub const HEIGHT: usize = 720;
-pub const RESOLUTION: &str = "1280x720";
-
 pub struct Renderizer<T> {
     render_context: Arc<Mutex<T>>,
     context: Arc<Mutex<hot_lib::EbookContext>>,
+    scene: SharedScene,
+    width: usize,
+    height: usize,
+    last_drawing: Option<Vec<Draw>>,
+    /// Scene generation of `last_drawing`
+    last_generation: Option<u64>,
 }
 
 // unsafe impl<T> Send for Renderizer<T> {}
@@ -35,39 +41,148 @@ impl<T> Clone for Renderizer<T> {
         Self {
             render_context: self.render_context.clone(),
             context: self.context.clone(),
+            scene: self.scene.clone(),
+            width: self.width,
+            height: self.height,
+            last_drawing: self.last_drawing.clone(),
+            last_generation: self.last_generation,
         }
     }
 }
 
 impl Renderizer<()> {
-    pub fn new<T: OffscreenRenderContext>(render_context: T) -> Renderizer<T> {
-        let context = Arc::new(Mutex::new(hot_lib::init()));
+    pub fn new<T: OffscreenRenderContext>(
+        render_context: T,
+        video: &VideoSettings,
+        scene: SharedScene,
+        font: Option<&[u8]>,
+    ) -> Renderizer<T> {
+        let (width, height) = (video.width as usize, video.height as usize);
+        let context = Arc::new(Mutex::new(hot_lib::init(width as f32, height as f32, font)));
         let render_context = Arc::new(Mutex::new(render_context));
 
         Renderizer {
             render_context,
             context,
+            scene,
+            width,
+            height,
+            last_drawing: None,
+            last_generation: None,
         }
     }
 }
 
+impl<T> Renderizer<T> {
+    fn draw(&mut self) -> Vec<Draw> {
+        let scene = self.scene.lock().clone();
+        let mut context = self.context.lock().unwrap();
+        let context = context.borrow_mut();
+        hot_lib::render(vec![], context, &scene)
+    }
+
+    /// `None` while the scene and its drawing stay the same
+    fn changed_drawing(&mut self) -> Option<Vec<Draw>> {
+        let generation = self.scene.generation();
+        if self.last_generation == Some(generation) {
+            return None;
+        }
+        self.last_generation = Some(generation);
+
+        let drawing = self.draw();
+        if self.last_drawing.as_ref() == Some(&drawing) {
+            return None;
+        }
+
+        self.last_drawing = Some(drawing.clone());
+        Some(drawing)
+    }
+}
+
 impl<T: OffscreenRenderContext> Renderizer<T> {
+    /// Start over with `font`, the next frame is drawn even if the scene
+    /// did not change
+    pub fn set_font(&mut self, font: Option<&[u8]>) {
+        *self.context.lock().unwrap() = hot_lib::init(self.width as f32, self.height as f32, font);
+        self.last_drawing = None;
+        self.last_generation = None;
+    }
+
     pub fn render(&mut self) -> Vec<u8> {
         futures::executor::block_on(self.render_async())
     }
 
     pub async fn render_async(&mut self) -> Vec<u8> {
-        let mut context = self.context.lock().unwrap();
-        let context = context.borrow_mut();
-        let drawing = hot_lib::render(vec![], context);
+        let drawing = self.draw();
+        self.rasterize(drawing).await
+    }
+
+    /// Same as [`Renderizer::render_async`] but skips the rasterization when
+    /// the drawing is the same as the last one, so static pages cost nothing.
+    /// The scene is not even drawn while it did not change.
+    pub async fn render_if_changed_async(&mut self) -> Option<Vec<u8>> {
+        let drawing = self.changed_drawing()?;
+        Some(self.rasterize(drawing).await)
+    }
 
+    async fn rasterize(&mut self, drawing: Vec<Draw>) -> Vec<u8> {
         // Render an image to bytes
         let mut render_context = self.render_context.lock().unwrap();
         let render_context = render_context.borrow_mut() as &mut T;
-        let image =
-            render_canvas_offscreen(render_context, WIDTH, HEIGHT, 1.0, stream::iter(drawing))
-                .await;
+        let image = render_canvas_offscreen(
+            render_context,
+            self.width,
+            self.height,
+            1.0,
+            stream::iter(drawing),
+        )
+        .await;
 
         image
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Draws without rasterizing, no font so only the progress bar shows
+    fn renderizer(scene: &SharedScene) -> Renderizer<()> {
+        Renderizer {
+            render_context: Arc::new(Mutex::new(())),
+            context: Arc::new(Mutex::new(hot_lib::init(1280., 720., None))),
+            scene: scene.clone(),
+            width: 1280,
+            height: 720,
+            last_drawing: None,
+            last_generation: None,
+        }
+    }
+
+    #[test]
+    fn only_changes_are_drawn() {
+        let scene = SharedScene::default();
+        let mut renderizer = renderizer(&scene);
+
+        assert!(renderizer.changed_drawing().is_some());
+        assert!(renderizer.changed_drawing().is_none());
+
+        // Reading the scene is not a change
+        assert_eq!(scene.lock().progress, 0.);
+        assert!(renderizer.changed_drawing().is_none());
+
+        scene.lock().progress = 0.5;
+        let drawing = renderizer.changed_drawing().unwrap();
+        assert_eq!(drawing, renderizer.draw());
+        assert!(renderizer.changed_drawing().is_none());
+
+        // Changed, but drawn the same
+        scene.lock().text = "Sin fuente no se ve.".to_owned();
+        assert!(renderizer.changed_drawing().is_none());
+        scene.lock().progress = 0.5;
+        assert!(renderizer.changed_drawing().is_none());
+
+        scene.lock().progress = 0.75;
+        assert!(renderizer.changed_drawing().is_some());
+    }
+}
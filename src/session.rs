@@ -0,0 +1,267 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+
+use crate::book::{same_book, Book, Chunk};
+use crate::error::{EbookError, EbookResult};
+use crate::renderizer::hot_lib::Scene;
+
+pub type SharedSession = Arc<Mutex<ReadingSession>>;
+
+/// State of the book being read. The narrator moves it forward, everything
+/// else (chat, control API) only looks at it or seeks.
+#[derive(Debug)]
+pub struct ReadingSession {
+    book: Book,
+    /// Next chunk to be queued
+    position: usize,
+    /// Chunk in the audio queue right now
+    playing: Option<usize>,
+    paused: bool,
+    bookmark: PathBuf,
+    /// Books to read after this one
+    upcoming: VecDeque<PathBuf>,
+    /// Text read between two chunks, not part of the book. Only one aside
+    /// is kept, a new one replaces what is left of the last.
+    aside: VecDeque<String>,
+    /// The next book is put to a vote, the end of the book waits for it
+    /// instead of ending the stream
+    book_vote: bool,
+}
+
+impl ReadingSession {
+    /// Open `path` and continue from its bookmark, if any
+    pub fn open(path: &Path) -> EbookResult<Self> {
+        let (book, bookmark, position) = load_book(path)?;
+
+        Ok(Self {
+            book,
+            position,
+            playing: None,
+            paused: false,
+            bookmark,
+            upcoming: VecDeque::new(),
+            aside: VecDeque::new(),
+            book_vote: false,
+        })
+    }
+
+    /// Read `path` after the current book and the ones already enqueued
+    pub fn enqueue(&mut self, path: PathBuf) {
+        info!("Enqueued {}", path.display());
+        self.upcoming.push_back(path);
+    }
+
+    /// Read `path` right after the current book
+    pub fn play_next(&mut self, path: PathBuf) {
+        info!("Next book {}", path.display());
+        self.upcoming.push_front(path);
+    }
+
+    /// Enqueue the books after the current one in `playlist`, or all of them
+    /// if the current book is not there
+    pub fn enqueue_playlist(&mut self, playlist: &[PathBuf]) {
+        let start = playlist
+            .iter()
+            .position(|path| same_book(path, &self.book.path))
+            .map_or(0, |idx| idx + 1);
+
+        for path in &playlist[start..] {
+            self.enqueue(path.clone());
+        }
+    }
+
+    pub fn upcoming(&self) -> &VecDeque<PathBuf> {
+        &self.upcoming
+    }
+
+    pub fn book_vote(&self) -> bool {
+        self.book_vote
+    }
+
+    pub fn set_book_vote(&mut self, book_vote: bool) {
+        self.book_vote = book_vote;
+    }
+
+    /// Save the bookmark and open the next enqueued book. Returns `false`
+    /// when there is none.
+    pub fn next_book(&mut self) -> EbookResult<bool> {
+        let Some(path) = self.upcoming.pop_front() else {
+            return Ok(false);
+        };
+
+        if let Err(err) = self.save_bookmark() {
+            warn!("{err}");
+        }
+
+        let (book, bookmark, position) = load_book(&path)?;
+        info!("Now reading {}", book.title);
+        self.book = book;
+        self.bookmark = bookmark;
+        self.position = position;
+        self.playing = None;
+
+        Ok(true)
+    }
+
+    /// Read `pieces` after the chunk being queued, before the next one,
+    /// dropping the aside still pending
+    pub fn set_aside(&mut self, pieces: Vec<String>) {
+        self.aside = pieces.into();
+    }
+
+    pub fn take_aside(&mut self) -> Option<String> {
+        self.aside.pop_front()
+    }
+
+    pub fn book(&self) -> &Book {
+        &self.book
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Next chunk to be queued, `None` when the book is finished
+    pub fn next_chunk(&self) -> Option<&Chunk> {
+        self.book.chunks.get(self.position)
+    }
+
+    /// Chunk being read aloud
+    pub fn playing(&self) -> Option<&Chunk> {
+        self.playing.and_then(|idx| self.book.chunks.get(idx))
+    }
+
+    /// The chunk at `position` was queued, the next one is `position + 1`
+    pub fn start_playing(&mut self, position: usize) {
+        self.playing = Some(position);
+        self.position = position + 1;
+    }
+
+    /// The chunk being played was heard completely
+    pub fn finish_playing(&mut self) {
+        self.playing = None;
+    }
+
+    /// Chunk being played, or the next one when nothing is playing
+    pub fn current_position(&self) -> usize {
+        self.playing.unwrap_or(self.position)
+    }
+
+    /// Continue from `position`, the chunk being played is dropped
+    pub fn seek(&mut self, position: usize) {
+        self.position = position.min(self.book.chunks.len());
+        self.playing = None;
+    }
+
+    /// Index in [`Book::chapters`] of the current chunk
+    pub fn current_chapter(&self) -> usize {
+        self.book
+            .chunks
+            .get(self.current_position())
+            .map(|chunk| chunk.chapter)
+            .unwrap_or(self.book.chapters.len().saturating_sub(1))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.playing.is_none() && self.position >= self.book.chunks.len()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Between 0 and 1
+    pub fn progress(&self) -> f32 {
+        let total = self.book.chunks.len().max(1);
+        self.current_position() as f32 / total as f32
+    }
+
+    pub fn update_scene(&self, scene: &mut Scene) {
+        let position = self.current_position();
+
+        scene.title = self.book.title.clone();
+        scene.chapter = self
+            .book
+            .chapter_at(position)
+            .filter(|chapter| chapter.number.is_some())
+            .map(|chapter| chapter.to_string())
+            .unwrap_or_default();
+        scene.text = self
+            .playing()
+            .map(|chunk| chunk.text.clone())
+            .unwrap_or_default();
+        scene.progress = self.progress();
+    }
+
+    /// Save where to continue next time. A sentence that was cut is read
+    /// again.
+    pub fn save_bookmark(&self) -> EbookResult<()> {
+        let position = self.current_position();
+
+        fs::write(&self.bookmark, format!("{position}\n"))
+            .map_err(|err| EbookError::Bookmark(self.bookmark.clone(), err.to_string()))?;
+
+        info!("Bookmark saved at chunk {position}");
+
+        Ok(())
+    }
+}
+
+/// The book, its bookmark and where to start. Finished books start again.
+fn load_book(path: &Path) -> EbookResult<(Book, PathBuf, usize)> {
+    let book = Book::open(path)?;
+    let bookmark = bookmark_path(path);
+    let position = match load_bookmark(&bookmark) {
+        position if position >= book.chunks.len() => 0,
+        position => position,
+    };
+
+    if position > 0 {
+        info!("Continuing {} from chunk {position}", book.title);
+    }
+
+    Ok((book, bookmark, position))
+}
+
+/// `book.txt` -> `book.txt.bookmark`
+fn bookmark_path(book: &Path) -> PathBuf {
+    let mut path = book.as_os_str().to_owned();
+    path.push(".bookmark");
+    path.into()
+}
+
+fn load_bookmark(path: &Path) -> usize {
+    let Ok(content) = fs::read_to_string(path) else {
+        return 0;
+    };
+
+    content.trim().parse().unwrap_or_else(|_| {
+        warn!("Ignoring invalid bookmark {}", path.display());
+        0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn new_aside_replaces_the_pending_one() {
+        let mut session = ReadingSession::open(&testing::book(testing::BOOK)).unwrap();
+        session.set_aside(vec!["yelmo: Casco.".to_owned(), "Más.".to_owned()]);
+        assert_eq!(session.take_aside().as_deref(), Some("yelmo: Casco."));
+
+        session.set_aside(vec!["adarga: Escudo.".to_owned()]);
+        assert_eq!(session.take_aside().as_deref(), Some("adarga: Escudo."));
+        assert_eq!(session.take_aside(), None);
+    }
+}
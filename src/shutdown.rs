@@ -0,0 +1,242 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use gst::prelude::*;
+use gstreamer as gst;
+use log::{info, warn};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+
+use crate::audio::AudioQueue;
+use crate::error::{EbookError, EbookResult};
+use crate::render::SharedScene;
+use crate::session::SharedSession;
+
+/// Longest wait for the current sentence to finish before cutting it
+const SENTENCE_GRACE: Duration = Duration::from_secs(10);
+/// Time the "stream ending" card stays on screen
+const ENDING_CARD: Duration = Duration::from_secs(5);
+/// Longest wait for the muxer to flush after EOS
+const EOS_TIMEOUT_SECONDS: u64 = 5;
+const POLL_WAIT: Duration = Duration::from_millis(50);
+
+/// Set by SIGINT/SIGTERM, or by the reader when the book ends. A second
+/// signal exits right away, for when the orderly shutdown gets stuck.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    pub fn install() -> EbookResult<Self> {
+        let shutdown = Self::default();
+
+        for signal in [SIGINT, SIGTERM] {
+            // Order matters, the conditional one must see the flag before
+            // the first signal sets it
+            flag::register_conditional_shutdown(signal, 1, shutdown.requested.clone())
+                .map_err(|err| EbookError::Signal(err.to_string()))?;
+            flag::register(signal, shutdown.requested.clone())
+                .map_err(|err| EbookError::Signal(err.to_string()))?;
+        }
+
+        Ok(shutdown)
+    }
+
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+/// Orderly end of the stream, once [`Shutdown::is_requested`]:
+///
+/// 1. Wait for the narrator to stop queueing sentences.
+/// 2. Let the current sentence finish, or cut it after [`SENTENCE_GRACE`].
+/// 3. Show the "stream ending" card.
+/// 4. Send EOS so the muxer closes the stream properly.
+/// 5. Save the bookmark.
+///
+/// With `eos` the pipeline already reached its end: nothing is heard or
+/// shown anymore, so the sentence is cut and only the bookmark is saved.
+pub fn stop(
+    pipeline: &gst::Pipeline,
+    session: &SharedSession,
+    scene: &SharedScene,
+    queue: &AudioQueue,
+    narrator: JoinHandle<()>,
+    eos: bool,
+) -> EbookResult<()> {
+    info!("Shutting down");
+
+    if narrator.join().is_err() {
+        warn!("Narrator panicked");
+    }
+
+    let grace = if eos { Duration::ZERO } else { SENTENCE_GRACE };
+    let deadline = Instant::now() + grace;
+    while !queue.is_empty() && Instant::now() < deadline {
+        thread::sleep(POLL_WAIT);
+    }
+
+    if queue.is_empty() {
+        session.lock().unwrap().finish_playing();
+    } else {
+        info!("Cutting the current sentence");
+        queue.clear();
+    }
+
+    if !eos {
+        {
+            let session = session.lock().unwrap();
+            let mut scene = scene.lock();
+            session.update_scene(&mut scene);
+            scene.ending = true;
+        }
+        thread::sleep(ENDING_CARD);
+
+        send_eos(pipeline);
+    }
+
+    pipeline.set_state(gst::State::Null).unwrap();
+
+    session.lock().unwrap().save_bookmark()
+}
+
+/// Wait for the muxer to close the stream
+fn send_eos(pipeline: &gst::Pipeline) {
+    info!("Sending EOS");
+    pipeline.send_event(gst::event::Eos::new());
+
+    let bus = pipeline
+        .bus()
+        .expect("Pipeline without bus. Shouldn't happen!");
+    let eos = bus.timed_pop_filtered(
+        gst::ClockTime::from_seconds(EOS_TIMEOUT_SECONDS),
+        &[gst::MessageType::Eos, gst::MessageType::Error],
+    );
+    if !matches!(
+        eos.as_ref().map(|msg| msg.view()),
+        Some(gst::MessageView::Eos(..))
+    ) {
+        warn!("Pipeline did not finish cleanly: {eos:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::encoder::EncoderConfig;
+    use crate::session::ReadingSession;
+    use crate::testing;
+
+    /// A session reading [`testing::BOOK`] from its second chunk, and where
+    /// its bookmark is saved
+    fn session() -> (SharedSession, PathBuf) {
+        let book = testing::book(testing::BOOK);
+        let mut session = ReadingSession::open(&book).unwrap();
+        session.start_playing(1);
+
+        let mut bookmark = book.into_os_string();
+        bookmark.push(".bookmark");
+        (Arc::new(Mutex::new(session)), bookmark.into())
+    }
+
+    fn pipeline() -> gst::Pipeline {
+        gst::init().unwrap();
+        gst::parse::launch("audiotestsrc is-live=true ! fakesink name=sink")
+            .unwrap()
+            .downcast::<gst::Pipeline>()
+            .unwrap()
+    }
+
+    #[test]
+    fn stop_in_order() {
+        let audio = EncoderConfig::default().audio;
+        let (session, bookmark) = session();
+        let scene = SharedScene::default();
+        let queue = AudioQueue::new(&audio);
+        let pipeline = pipeline();
+
+        // The card must be up when EOS reaches the sink
+        let ending_at_eos = Arc::new(AtomicBool::new(false));
+        let sink = pipeline
+            .by_name("sink")
+            .unwrap()
+            .static_pad("sink")
+            .unwrap();
+        sink.add_probe(gst::PadProbeType::EVENT_DOWNSTREAM, {
+            let (scene, ending_at_eos) = (scene.clone(), ending_at_eos.clone());
+            move |_, info| {
+                if let Some(gst::PadProbeData::Event(event)) = &info.data {
+                    if event.type_() == gst::EventType::Eos {
+                        ending_at_eos.store(scene.lock().ending, Ordering::SeqCst);
+                    }
+                }
+                gst::PadProbeReturn::Ok
+            }
+        });
+        pipeline.set_state(gst::State::Playing).unwrap();
+
+        // The last sentence is queued after the shutdown started, it is
+        // still heard to the end
+        let narrator = thread::spawn({
+            let queue = queue.clone();
+            let samples = vec![0.1; (audio.sample_rate * audio.channels / 2) as usize];
+            move || {
+                thread::sleep(Duration::from_millis(100));
+                queue.push(&samples);
+            }
+        });
+        let heard = Arc::new(AtomicBool::new(false));
+        let mixer = thread::spawn({
+            let (queue, heard) = (queue.clone(), heard.clone());
+            move || {
+                let mut out = vec![0.; 1024];
+                while !heard.load(Ordering::SeqCst) {
+                    queue.pull(&mut out, false);
+                    thread::sleep(Duration::from_millis(5));
+                }
+            }
+        });
+
+        stop(&pipeline, &session, &scene, &queue, narrator, false).unwrap();
+        heard.store(true, Ordering::SeqCst);
+        mixer.join().unwrap();
+
+        assert!(queue.is_empty());
+        assert!(scene.lock().ending);
+        assert!(ending_at_eos.load(Ordering::SeqCst));
+        assert_eq!(pipeline.current_state(), gst::State::Null);
+        // The sentence was finished, the next one is where to continue
+        assert_eq!(fs::read_to_string(bookmark).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn stop_after_eos_only_saves_the_bookmark() {
+        let audio = EncoderConfig::default().audio;
+        let (session, bookmark) = session();
+        let scene = SharedScene::default();
+        let queue = AudioQueue::new(&audio);
+        queue.push(&[0.1; 1024]);
+
+        let start = Instant::now();
+        let narrator = thread::spawn(|| {});
+        stop(&pipeline(), &session, &scene, &queue, narrator, true).unwrap();
+
+        assert!(start.elapsed() < ENDING_CARD);
+        assert!(queue.is_empty());
+        assert!(!scene.lock().ending);
+        // Nobody heard the end of the sentence, it is read again
+        assert_eq!(fs::read_to_string(bookmark).unwrap(), "1\n");
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use gst::prelude::*;
+use gstreamer as gst;
+
+/// Name of the muxer in the pipeline, its output is what gets streamed
+pub const MUXER_NAME: &str = "mux";
+
+/// Counters of what reached the output, updated from the pipeline threads
+#[derive(Debug, Clone, Default)]
+pub struct OutputStats {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    frames: AtomicU64,
+    late_frames: AtomicU64,
+    bytes: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatsSnapshot {
+    pub frames: u64,
+    /// Frames repeated because the renderer was not ready in time
+    pub late_frames: u64,
+    /// Bytes out of the muxer
+    pub bytes: u64,
+}
+
+impl OutputStats {
+    pub fn add_frame(&self, late: bool) {
+        self.counters.frames.fetch_add(1, Ordering::Relaxed);
+        if late {
+            self.counters.late_frames.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            frames: self.counters.frames.load(Ordering::Relaxed),
+            late_frames: self.counters.late_frames.load(Ordering::Relaxed),
+            bytes: self.counters.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count the bytes leaving the muxer
+    pub fn attach(&self, pipeline: &gst::Pipeline) {
+        let pad = pipeline
+            .by_name(MUXER_NAME)
+            .expect("Muxer element not found")
+            .static_pad("src")
+            .expect("Muxer without src pad");
+
+        let counters = self.counters.clone();
+        pad.add_probe(gst::PadProbeType::BUFFER, move |_, info| {
+            if let Some(gst::PadProbeData::Buffer(buffer)) = &info.data {
+                counters
+                    .bytes
+                    .fetch_add(buffer.size() as u64, Ordering::Relaxed);
+            }
+            gst::PadProbeReturn::Ok
+        });
+    }
+}
+
+impl StatsSnapshot {
+    /// Frames per second and kbps since `earlier`
+    pub fn rates_since(&self, earlier: &StatsSnapshot, elapsed: Duration) -> (f32, f32) {
+        let seconds = elapsed.as_secs_f32().max(f32::EPSILON);
+        let frames = self.frames.saturating_sub(earlier.frames) as f32;
+        let bits = self.bytes.saturating_sub(earlier.bytes) as f32 * 8.;
+
+        (frames / seconds, bits / 1000. / seconds)
+    }
+}
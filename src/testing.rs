@@ -0,0 +1,151 @@
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::audio::{AudioQueue, Sounds};
+use crate::control::ReaderControl;
+use crate::encoder::EncoderConfig;
+use crate::render::{SharedScene, Theme};
+use crate::session::ReadingSession;
+use crate::tts::{Languages, Voice};
+
+pub const BOOK: &str = "\
+Prólogo del autor.
+
+I.
+
+EL COMIENZO
+
+La primera frase del libro. La segunda frase del libro.
+
+II.
+
+Otra frase en el segundo capítulo.
+";
+
+/// Empty directory of its own for every call
+pub fn temp_dir() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    let dir = env::temp_dir().join(format!(
+        "ebook-reader-test-{}-{}",
+        process::id(),
+        COUNT.fetch_add(1, Ordering::SeqCst)
+    ));
+    _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `text` saved as `book.txt` in a new directory
+pub fn book(text: &str) -> PathBuf {
+    let path = temp_dir().join("book.txt");
+    fs::write(&path, text).unwrap();
+    path
+}
+
+/// Control of a session reading [`BOOK`], without sounds
+pub fn control() -> ReaderControl {
+    let audio = EncoderConfig::default().audio;
+    let session = ReadingSession::open(&book(BOOK)).unwrap();
+
+    ReaderControl::new(
+        Arc::new(Mutex::new(session)),
+        SharedScene::default(),
+        AudioQueue::new(&audio),
+        Arc::new(Mutex::new(Voice {
+            language: Languages::Spanish,
+            detect: false,
+            tld: "com".to_owned(),
+            speed: 1.0,
+            pitch: 1.0,
+            volume: 0.0,
+        })),
+        Sounds::new(None, audio, Theme::new(None)),
+    )
+}
+
+/// Wait up to five seconds for `condition`, what other threads do takes a
+/// moment
+pub fn wait_until(mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    condition()
+}
+
+/// A request received by [`mock_http`]
+#[derive(Debug)]
+pub struct HttpRequest {
+    pub method: String,
+    /// With the query
+    pub path: String,
+    pub authorization: String,
+    pub body: String,
+}
+
+/// Local HTTP server answering every request with the next of `responses`,
+/// a status and a body. The requests it got are sent to the receiver.
+pub fn mock_http(responses: Vec<(u16, &'static str)>) -> (SocketAddr, Receiver<HttpRequest>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        for (status, body) in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let mut parts = line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_owned();
+            let path = parts.next().unwrap_or_default().to_owned();
+
+            let mut authorization = String::new();
+            let mut length = 0;
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let Some((name, value)) = line.trim_end().split_once(": ") else {
+                    break;
+                };
+                match name.to_lowercase().as_str() {
+                    "authorization" => authorization = value.to_owned(),
+                    "content-length" => length = value.parse().unwrap(),
+                    _ => {}
+                }
+            }
+            let mut request_body = vec![0; length];
+            reader.read_exact(&mut request_body).unwrap();
+
+            _ = tx.send(HttpRequest {
+                method,
+                path,
+                authorization,
+                body: String::from_utf8(request_body).unwrap(),
+            });
+
+            write!(
+                stream,
+                "HTTP/1.1 {status} Mock\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        }
+    });
+
+    (addr, rx)
+}
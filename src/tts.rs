@@ -1,14 +1,60 @@
+mod detector;
 mod languages;
+mod locale;
+mod normalizer;
 mod tokenizer;
 mod url;
 // mod wav;
 
+use std::sync::{Arc, Mutex};
+
+pub use detector::{detect, detect_with_margin};
 pub use languages::Languages;
+pub use locale::Locale;
+pub use normalizer::normalize;
+pub use tokenizer::tokenize;
 use url::UrlTTS;
 // pub use wav::mp3_to_wav;
 
 pub const GOOGLE_TTS_MAX_CHARS: usize = 100;
 
+/// gTTS servers with a different accent, cycled by [`Voice::next`]
+pub const VOICES: &[&str] = &["com", "com.mx", "es", "us", "co.uk", "com.au"];
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 2.0;
+pub const MIN_PITCH: f32 = 0.5;
+pub const MAX_PITCH: f32 = 2.0;
+/// Gain in dB
+pub const MIN_VOLUME: f32 = -30.0;
+pub const MAX_VOLUME: f32 = 12.0;
+
+pub type SharedVoice = Arc<Mutex<Voice>>;
+
+/// How the narrator sounds, can change while reading
+#[derive(Debug, Clone)]
+pub struct Voice {
+    pub language: Languages,
+    /// Read every sentence in the language detected in the book,
+    /// [`Voice::language`] is only the fallback
+    pub detect: bool,
+    /// Top-level domain of the gTTS server, it picks the accent
+    pub tld: String,
+    /// Tempo, 1 is the normal speed
+    pub speed: f32,
+    /// 1 is the pitch of the engine
+    pub pitch: f32,
+    /// Gain in dB
+    pub volume: f32,
+}
+
+impl Voice {
+    /// The voice after the current one in [`VOICES`]
+    pub fn next(&mut self) {
+        let idx = VOICES.iter().position(|tld| *tld == self.tld);
+        self.tld = VOICES[idx.map_or(0, |idx| (idx + 1) % VOICES.len())].to_owned();
+    }
+}
+
 pub struct TTS {
     /// The language of the gTTS client (ISO code)
     ///
@@ -17,15 +63,26 @@ pub struct TTS {
     /// top-level domain of the gTTS client
     ///
     /// example: "com"
-    tld: &'static str,
+    tld: String,
 }
 
 impl TTS {
-    /// Creates a new gTTS client with the given volume and language.
-    pub fn new(language: Languages, tld: Option<&'static str>) -> Self {
+    /// Creates a gTTS client for `language` on the `tld` server, `com` by
+    /// default. Speed, pitch and volume are applied after decoding.
+    pub fn new(language: Languages, tld: Option<&str>) -> Self {
         TTS {
             language,
-            tld: tld.unwrap_or("com"),
+            tld: tld.unwrap_or("com").to_owned(),
+        }
+    }
+
+    pub fn set_language(&mut self, language: Languages) {
+        self.language = language;
+    }
+
+    pub fn set_tld(&mut self, tld: &str) {
+        if self.tld != tld {
+            self.tld = tld.to_owned();
         }
     }
 
@@ -37,7 +94,7 @@ impl TTS {
                 GOOGLE_TTS_MAX_CHARS
             ));
         }
-        let language = Languages::as_code(self.language.clone());
+        let language = self.language.as_code();
         let text = UrlTTS::fragmenter(text)?;
         let url = format!("https://translate.google.{}/translate_tts?ie=UTF-8&q={}&tl={}&total=1&idx=0&textlen={}&tl={}&client=tw-ob", self.tld, text.encoded, language, len, language);
 
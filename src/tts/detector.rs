@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use super::Languages;
+
+/// N-grams of 1 to this many letters, words are padded with spaces
+const MAX_NGRAM: usize = 3;
+/// Most frequent n-grams kept of every text
+const PROFILE_LEN: usize = 300;
+/// Fewer letters are not enough to tell the language
+const MIN_LETTERS: usize = 40;
+/// How much closer the best language must be than the second one
+const MIN_MARGIN: f32 = 0.02;
+/// Only the beginning of long texts is looked at
+const MAX_SAMPLE_LEN: usize = 20_000;
+
+/// Text of every detected language, its n-grams are the profile
+const SAMPLES: &[(Languages, &str)] = &[
+    (
+        Languages::Spanish,
+        "Cuando llegó a la ciudad por primera vez, el joven no conocía a nadie y pasaba las \
+        tardes caminando por las calles que bajaban hacia el puerto. Había dejado en el pueblo \
+        a su madre y a sus hermanos, y les escribía cada semana para contarles que todo iba \
+        bien, aunque la verdad era que apenas tenía dinero para comer. Con el tiempo encontró \
+        trabajo en una librería pequeña, donde el dueño, un hombre mayor de barba blanca, le \
+        enseñó a distinguir las buenas ediciones de las malas. Por las noches leía todos los \
+        libros que podía llevarse a casa, y poco a poco la ciudad dejó de parecerle tan grande. \
+        Sin embargo, nunca olvidó el olor de la tierra mojada ni la voz de su padre, que siempre \
+        decía que un hombre sin memoria es como un árbol sin raíces. Años después, cuando ya era \
+        conocido en toda la región, volvió al pueblo y se sentó en la misma plaza donde había \
+        jugado de niño.",
+    ),
+    (
+        Languages::English,
+        "When he first arrived in the city, the young man did not know anyone and spent his \
+        afternoons walking along the streets that ran down towards the harbour. He had left his \
+        mother and his brothers in the village, and he wrote to them every week to tell them \
+        that everything was fine, although the truth was that he hardly had enough money to \
+        eat. In time he found work in a small bookshop, where the owner, an old man with a white \
+        beard, taught him how to tell the good editions from the bad ones. At night he read \
+        every book he could take home, and little by little the city stopped looking so large. \
+        However, he never forgot the smell of the wet earth or the voice of his father, who \
+        always said that a man without memory is like a tree without roots. Years later, when \
+        he was already known throughout the region, he went back to the village and sat in the \
+        same square where he had played as a child.",
+    ),
+    (
+        Languages::French,
+        "Lorsqu'il arriva dans la ville pour la première fois, le jeune homme ne connaissait \
+        personne et passait ses après-midi à marcher dans les rues qui descendaient vers le \
+        port. Il avait laissé au village sa mère et ses frères, et il leur écrivait chaque \
+        semaine pour leur dire que tout allait bien, même si la vérité était qu'il avait à peine \
+        de quoi manger. Avec le temps, il trouva du travail dans une petite librairie, où le \
+        patron, un vieil homme à la barbe blanche, lui apprit à distinguer les bonnes éditions \
+        des mauvaises. Le soir, il lisait tous les livres qu'il pouvait emporter chez lui, et peu \
+        à peu la ville cessa de lui paraître si grande. Pourtant, il n'oublia jamais l'odeur de \
+        la terre mouillée ni la voix de son père, qui disait toujours qu'un homme sans mémoire \
+        est comme un arbre sans racines. Des années plus tard, quand il était déjà connu dans \
+        toute la région, il revint au village et s'assit sur la même place où il avait joué \
+        enfant.",
+    ),
+    (
+        Languages::Portuguese,
+        "Quando chegou à cidade pela primeira vez, o jovem não conhecia ninguém e passava as \
+        tardes caminhando pelas ruas que desciam até o porto. Tinha deixado na aldeia a sua mãe \
+        e os seus irmãos, e escrevia-lhes todas as semanas para contar que tudo corria bem, \
+        embora a verdade fosse que mal tinha dinheiro para comer. Com o tempo encontrou trabalho \
+        numa pequena livraria, onde o dono, um homem velho de barba branca, lhe ensinou a \
+        distinguir as boas edições das más. À noite lia todos os livros que podia levar para \
+        casa, e pouco a pouco a cidade deixou de lhe parecer tão grande. No entanto, nunca \
+        esqueceu o cheiro da terra molhada nem a voz do seu pai, que sempre dizia que um homem \
+        sem memória é como uma árvore sem raízes. Anos depois, quando já era conhecido em toda a \
+        região, voltou à aldeia e sentou-se na mesma praça onde tinha brincado em criança.",
+    ),
+    (
+        Languages::Italian,
+        "Quando arrivò in città per la prima volta, il giovane non conosceva nessuno e passava \
+        i pomeriggi camminando per le strade che scendevano verso il porto. Aveva lasciato al \
+        paese sua madre e i suoi fratelli, e scriveva loro ogni settimana per raccontare che \
+        tutto andava bene, anche se la verità era che aveva appena i soldi per mangiare. Col \
+        tempo trovò lavoro in una piccola libreria, dove il proprietario, un uomo anziano con la \
+        barba bianca, gli insegnò a distinguere le buone edizioni da quelle cattive. La sera \
+        leggeva tutti i libri che poteva portare a casa, e a poco a poco la città smise di \
+        sembrargli così grande. Tuttavia non dimenticò mai l'odore della terra bagnata né la \
+        voce di suo padre, che diceva sempre che un uomo senza memoria è come un albero senza \
+        radici. Anni dopo, quando ormai era conosciuto in tutta la regione, tornò al paese e si \
+        sedette nella stessa piazza dove aveva giocato da bambino.",
+    ),
+    (
+        Languages::German,
+        "Als er zum ersten Mal in die Stadt kam, kannte der junge Mann niemanden und verbrachte \
+        die Nachmittage damit, durch die Straßen zu gehen, die zum Hafen hinunterführten. Seine \
+        Mutter und seine Brüder hatte er im Dorf zurückgelassen, und er schrieb ihnen jede \
+        Woche, um ihnen zu erzählen, dass alles gut sei, obwohl er in Wahrheit kaum genug Geld \
+        zum Essen hatte. Mit der Zeit fand er Arbeit in einer kleinen Buchhandlung, wo der \
+        Besitzer, ein alter Mann mit weißem Bart, ihm beibrachte, die guten Ausgaben von den \
+        schlechten zu unterscheiden. Nachts las er alle Bücher, die er mit nach Hause nehmen \
+        konnte, und nach und nach kam ihm die Stadt nicht mehr so groß vor. Trotzdem vergaß er \
+        nie den Geruch der nassen Erde und die Stimme seines Vaters, der immer sagte, dass ein \
+        Mann ohne Erinnerung wie ein Baum ohne Wurzeln sei. Jahre später, als er in der ganzen \
+        Gegend bekannt war, kehrte er in das Dorf zurück und setzte sich auf denselben Platz, \
+        auf dem er als Kind gespielt hatte.",
+    ),
+    (
+        Languages::Catalan,
+        "Quan va arribar a la ciutat per primera vegada, el jove no coneixia ningú i passava \
+        les tardes caminant pels carrers que baixaven cap al port. Havia deixat al poble la seva \
+        mare i els seus germans, i els escrivia cada setmana per explicar-los que tot anava bé, \
+        tot i que la veritat era que amb prou feines tenia diners per menjar. Amb el temps va \
+        trobar feina en una llibreria petita, on l'amo, un home gran de barba blanca, li va \
+        ensenyar a distingir les bones edicions de les dolentes. A la nit llegia tots els \
+        llibres que podia endur-se a casa, i a poc a poc la ciutat va deixar de semblar-li tan \
+        gran. Tanmateix, mai no va oblidar l'olor de la terra mullada ni la veu del seu pare, \
+        que sempre deia que un home sense memòria és com un arbre sense arrels. Anys després, \
+        quan ja era conegut a tota la comarca, va tornar al poble i es va asseure a la mateixa \
+        plaça on havia jugat de petit.",
+    ),
+];
+
+/// Rank of every n-gram, the most frequent first
+struct Profile {
+    language: Languages,
+    ranks: HashMap<String, usize>,
+}
+
+fn profiles() -> &'static [Profile] {
+    static PROFILES: OnceLock<Vec<Profile>> = OnceLock::new();
+    PROFILES.get_or_init(|| {
+        SAMPLES
+            .iter()
+            .map(|(language, sample)| Profile {
+                language: *language,
+                ranks: ranked(sample)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(rank, ngram)| (ngram, rank))
+                    .collect(),
+            })
+            .collect()
+    })
+}
+
+/// Language of `text`, `None` when it is too short or too close between
+/// two languages to tell
+pub fn detect(text: &str) -> Option<Languages> {
+    detect_with_margin(text, MIN_MARGIN)
+}
+
+/// [`detect`] asking the best language to be `min_margin` closer than the
+/// second one, short texts of a close language are often within a few
+/// hundredths
+pub fn detect_with_margin(text: &str, min_margin: f32) -> Option<Languages> {
+    let sample = match text.char_indices().nth(MAX_SAMPLE_LEN) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    };
+    if sample.chars().filter(|c| c.is_alphabetic()).count() < MIN_LETTERS {
+        return None;
+    }
+
+    // Out-of-place distance: how far every n-gram is from its rank in the
+    // profile, the maximum when it is not there
+    let ranked = ranked(sample);
+    let max_distance = (ranked.len() * PROFILE_LEN) as f32;
+    let mut distances = profiles()
+        .iter()
+        .map(|profile| {
+            let distance: usize = ranked
+                .iter()
+                .enumerate()
+                .map(|(rank, ngram)| {
+                    profile
+                        .ranks
+                        .get(ngram)
+                        .map_or(PROFILE_LEN, |profile_rank| profile_rank.abs_diff(rank))
+                })
+                .sum();
+            (distance as f32 / max_distance, &profile.language)
+        })
+        .collect::<Vec<_>>();
+    distances.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    match distances[..] {
+        [(best, language), (second, _), ..] if second - best >= min_margin => Some(*language),
+        _ => None,
+    }
+}
+
+/// The [`PROFILE_LEN`] most frequent n-grams of `text`
+fn ranked(text: &str) -> Vec<String> {
+    let mut counts = HashMap::<String, usize>::new();
+
+    let words = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty());
+    for word in words {
+        let padded = format!(" {} ", word.to_lowercase())
+            .chars()
+            .collect::<Vec<_>>();
+        for len in 1..=MAX_NGRAM {
+            for ngram in padded.windows(len) {
+                if ngram != [' '] {
+                    *counts.entry(ngram.iter().collect()).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    let mut ranked = counts.into_iter().collect::<Vec<_>>();
+    ranked.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    ranked.truncate(PROFILE_LEN);
+    ranked.into_iter().map(|(ngram, _)| ngram).collect()
+}
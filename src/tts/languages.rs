@@ -1,7 +1,10 @@
+use std::fmt;
 use std::str::FromStr;
 
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
 /// Enum containing all the languages supported by the GTTS API
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Languages {
     /// ISO code: af
     Afrikaans,
@@ -123,6 +126,8 @@ pub enum Languages {
     Vietnamese,
     /// ISO code: zh-CN
     Chinese,
+    /// ISO code: zh-TW
+    ChineseTraditional,
 }
 impl FromStr for Languages {
     type Err = String;
@@ -154,7 +159,7 @@ impl FromStr for Languages {
             "is" => Ok(Languages::Icelandic),
             "it" => Ok(Languages::Italian),
             "ja" => Ok(Languages::Japanese),
-            "jw" => Ok(Languages::Javanese),
+            "jv" | "jw" => Ok(Languages::Javanese),
             "km" => Ok(Languages::Khmer),
             "kn" => Ok(Languages::Kannada),
             "ko" => Ok(Languages::Korean),
@@ -166,7 +171,7 @@ impl FromStr for Languages {
             "my" => Ok(Languages::MyanmarAKABurmese),
             "ne" => Ok(Languages::Nepali),
             "nl" => Ok(Languages::Dutch),
-            "no" => Ok(Languages::Norwegian),
+            "nb" | "nn" | "no" => Ok(Languages::Norwegian),
             "pl" => Ok(Languages::Polish),
             "pt" => Ok(Languages::Portuguese),
             "ro" => Ok(Languages::Romanian),
@@ -181,12 +186,13 @@ impl FromStr for Languages {
             "ta" => Ok(Languages::Tamil),
             "te" => Ok(Languages::Telugu),
             "th" => Ok(Languages::Thai),
-            "tl" => Ok(Languages::Filipino),
+            "fil" | "tl" => Ok(Languages::Filipino),
             "tr" => Ok(Languages::Turkish),
             "uk" => Ok(Languages::Ukrainian),
             "ur" => Ok(Languages::Urdu),
             "vi" => Ok(Languages::Vietnamese),
-            "zh-CN" => Ok(Languages::Chinese),
+            "zh" | "zh-CN" => Ok(Languages::Chinese),
+            "zh-TW" => Ok(Languages::ChineseTraditional),
             _ => Err(format!(
                 "Unknown language: {}. Make sure to use all the supported languages",
                 s
@@ -196,8 +202,8 @@ impl FromStr for Languages {
 }
 
 impl Languages {
-    pub fn as_code(l: Languages) -> &'static str {
-        match l {
+    pub fn as_code(self) -> &'static str {
+        match self {
             Languages::Afrikaans => "af",
             Languages::Albanian => "sq",
             Languages::Arabic => "ar",
@@ -207,6 +213,7 @@ impl Languages {
             Languages::Bulgarian => "bg",
             Languages::Catalan => "ca",
             Languages::Chinese => "zh-CN",
+            Languages::ChineseTraditional => "zh-TW",
             Languages::Croatian => "hr",
             Languages::Czech => "cs",
             Languages::Danish => "da",
@@ -260,3 +267,24 @@ impl Languages {
         }
     }
 }
+
+impl fmt::Display for Languages {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_code())
+    }
+}
+
+/// The code, as the control API shows it
+impl Serialize for Languages {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Languages {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::Languages;
+
+/// gTTS server with the accent of every region, the ones it does not have
+/// keep the configured voice
+const ACCENTS: &[(Languages, &str, &str)] = &[
+    (Languages::English, "US", "us"),
+    (Languages::English, "GB", "co.uk"),
+    (Languages::English, "AU", "com.au"),
+    (Languages::English, "CA", "ca"),
+    (Languages::English, "IN", "co.in"),
+    (Languages::English, "IE", "ie"),
+    (Languages::English, "ZA", "co.za"),
+    (Languages::French, "FR", "fr"),
+    (Languages::French, "CA", "ca"),
+    (Languages::Portuguese, "BR", "com.br"),
+    (Languages::Portuguese, "PT", "pt"),
+    (Languages::Spanish, "ES", "es"),
+    (Languages::Spanish, "MX", "com.mx"),
+    (Languages::Spanish, "US", "us"),
+    (Languages::Spanish, "419", "com.mx"),
+];
+/// Regions written in traditional characters
+const TRADITIONAL_CHINESE: &[&str] = &["TW", "HK", "MO"];
+
+/// Language and region of a BCP-47 tag, like `pt-BR` or `en-GB`. Scripts,
+/// variants and extensions are only used to tell the Chinese apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    pub language: Languages,
+    region: Option<Region>,
+}
+
+/// Two letters or three digits, padded with zeros
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Region([u8; 3]);
+
+impl Region {
+    fn parse(subtag: &str) -> Option<Self> {
+        let is_region = match subtag.len() {
+            2 => subtag.bytes().all(|b| b.is_ascii_alphabetic()),
+            3 => subtag.bytes().all(|b| b.is_ascii_digit()),
+            _ => false,
+        };
+        if !is_region {
+            return None;
+        }
+
+        let mut region = [0; 3];
+        region[..subtag.len()].copy_from_slice(subtag.to_ascii_uppercase().as_bytes());
+        Some(Self(region))
+    }
+
+    fn as_str(&self) -> &str {
+        let len = self.0.iter().position(|b| *b == 0).unwrap_or(3);
+        std::str::from_utf8(&self.0[..len]).unwrap_or_default()
+    }
+}
+
+impl Locale {
+    fn region(&self) -> Option<&str> {
+        self.region.as_ref().map(Region::as_str)
+    }
+
+    /// gTTS server with the accent of the region, `None` when it has no
+    /// own one
+    pub fn tld(&self) -> Option<&'static str> {
+        let region = self.region()?;
+        ACCENTS
+            .iter()
+            .find(|(language, accent, _)| *language == self.language && *accent == region)
+            .map(|(_, _, tld)| *tld)
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    /// Tags are case insensitive and `_` is taken as `-`, so POSIX locales
+    /// like `en_GB` work too
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid language tag: {s}. Use one like es, en-GB or pt-BR");
+
+        let mut subtags = s.split(['-', '_']);
+        let primary = subtags.next().unwrap_or_default().to_ascii_lowercase();
+        if !(2..=3).contains(&primary.len()) || !primary.bytes().all(|b| b.is_ascii_alphabetic()) {
+            return Err(invalid());
+        }
+
+        let mut script = None;
+        let mut region = None;
+        for subtag in subtags {
+            if subtag.is_empty()
+                || subtag.len() > 8
+                || !subtag.bytes().all(|b| b.is_ascii_alphanumeric())
+            {
+                return Err(invalid());
+            }
+            // Private use and extensions go until the end
+            if subtag.len() == 1 {
+                break;
+            }
+            let is_script = subtag.len() == 4 && subtag.bytes().all(|b| b.is_ascii_alphabetic());
+            if is_script && script.is_none() && region.is_none() {
+                script = Some(subtag.to_ascii_lowercase());
+            } else if region.is_none() {
+                region = Region::parse(subtag);
+            }
+        }
+
+        let mut language = primary.parse::<Languages>()?;
+        if language == Languages::Chinese {
+            let region = region.as_ref().map(Region::as_str);
+            let traditional = match script.as_deref() {
+                Some("hant") => true,
+                Some("hans") => false,
+                _ => region.is_some_and(|region| TRADITIONAL_CHINESE.contains(&region)),
+            };
+            if traditional {
+                language = Languages::ChineseTraditional;
+            }
+        }
+
+        Ok(Self { language, region })
+    }
+}
+
+/// The shortest tag, the Chinese are always `zh-CN` or `zh-TW` so the
+/// script is not lost
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.language.as_code();
+        match self.region() {
+            Some(region) if !code.contains('-') => write!(f, "{code}-{region}"),
+            _ => f.write_str(code),
+        }
+    }
+}
+
+impl Serialize for Locale {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Locale {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(tag: &str) -> Locale {
+        tag.parse().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for (tag, shown) in [
+            ("es", "es"),
+            ("pt-BR", "pt-BR"),
+            ("en_GB", "en-GB"),
+            ("EN-us", "en-US"),
+            ("es-419", "es-419"),
+            ("zh-Hant-HK", "zh-TW"),
+            ("zh-Hans", "zh-CN"),
+            ("zh-TW", "zh-TW"),
+            ("en-GB-u-ca-gregory", "en-GB"),
+        ] {
+            let locale = parse(tag);
+            assert_eq!(locale.to_string(), shown, "{tag}");
+            // The Chinese keep the script, not the region
+            assert_eq!(parse(shown).language, locale.language, "{tag}");
+            assert_eq!(parse(shown).to_string(), shown, "{tag}");
+        }
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let table: toml::Table =
+            toml::from_str("locale = \"en_GB\"\nlanguage = \"pt\"\nbad = \"xx-GB\"").unwrap();
+        let locale: Locale = table["locale"].clone().try_into().unwrap();
+        let language: Languages = table["language"].clone().try_into().unwrap();
+        assert_eq!(locale, parse("en-GB"));
+        assert_eq!(language, Languages::Portuguese);
+        assert!(table["bad"].clone().try_into::<Locale>().is_err());
+        assert_eq!(
+            toml::Value::try_from(locale).unwrap(),
+            toml::Value::String("en-GB".into())
+        );
+
+        let json = serde_json::to_string(&[parse("zh-Hant-HK"), parse("es-419")]).unwrap();
+        assert_eq!(json, r#"["zh-TW","es-419"]"#);
+        assert_eq!(
+            serde_json::from_str::<Vec<Locale>>(&json).unwrap(),
+            [parse("zh-TW"), parse("es-419")]
+        );
+        assert_eq!(
+            serde_json::to_string(&Languages::Portuguese).unwrap(),
+            r#""pt""#
+        );
+        assert_eq!(
+            serde_json::from_str::<Languages>(r#""pt""#).unwrap(),
+            Languages::Portuguese
+        );
+        assert!(serde_json::from_str::<Languages>(r#""klingon""#).is_err());
+    }
+
+    #[test]
+    fn language_and_accent() {
+        assert_eq!(parse("pt-BR").language, Languages::Portuguese);
+        assert_eq!(parse("pt-BR").tld(), Some("com.br"));
+        assert_eq!(parse("en_GB").tld(), Some("co.uk"));
+        assert_eq!(parse("es-419").tld(), Some("com.mx"));
+        assert_eq!(parse("zh-Hant-HK").language, Languages::ChineseTraditional);
+        assert_eq!(parse("zh-HK").language, Languages::ChineseTraditional);
+        assert_eq!(parse("zh-SG").language, Languages::Chinese);
+        assert_eq!(parse("en_GB"), parse("en-gb"));
+        assert_eq!(parse("es").tld(), None);
+        assert_eq!(parse("de-AT").tld(), None);
+    }
+
+    #[test]
+    fn reject_bad_tags() {
+        for tag in [
+            "",
+            "e",
+            "english",
+            "es-",
+            "es--ES",
+            "es-ES!",
+            "1a",
+            "xx-GB",
+            "es-ABCDEFGHI",
+        ] {
+            assert!(tag.parse::<Locale>().is_err(), "{tag:?}");
+        }
+    }
+}
@@ -0,0 +1,587 @@
+mod english;
+mod spanish;
+
+use std::ops::Range;
+
+use super::Languages;
+use english::English;
+use spanish::Spanish;
+
+/// Larger numbers are read digit by digit, like phone numbers
+const MAX_CARDINAL: u64 = 999_999_999_999;
+/// Kept at the end of a word, "3.º" and "50%"
+const WORD_SUFFIXES: &[char] = &['º', 'ª', '°', '%'];
+/// Roman numerals of a single letter, only after a name or a trigger word
+const SINGLE_ROMANS: &str = "IVX";
+/// Valid numerals far more often written as acronyms or words, only read
+/// as numbers after a trigger word
+const ROMAN_ACRONYMS: &[&str] = &[
+    "CC", "CD", "CI", "CM", "CV", "DC", "DI", "LI", "MC", "MD", "MI", "MIX", "XL", "XXL",
+];
+
+/// The text as it is read aloud, see [`normalize`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Normalized {
+    pub text: String,
+    /// Rewritten pieces, in order. The text between them is the same in
+    /// both, so any position of [`Normalized::text`] maps back to the
+    /// displayed text.
+    pub spans: Vec<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    /// Bytes of the displayed text
+    pub display: Range<usize>,
+    /// Bytes of [`Normalized::text`]
+    pub spoken: Range<usize>,
+}
+
+/// How a language reads what is not a plain word
+trait Rules: Sized {
+    /// Thousands and decimal separators
+    const SEPARATORS: (char, char);
+    const DECIMAL_WORD: &'static str;
+    const PERCENT_WORD: &'static str;
+    /// Words followed by a roman numeral read as a number, like "chapter"
+    const ROMAN_TRIGGERS: &'static [&'static str];
+    /// "I" after a name is a numeral, not the pronoun
+    const SINGLE_I: bool;
+
+    fn cardinal(n: u64) -> String;
+
+    /// `n` followed by the word `next`, "veintiún años"
+    fn cardinal_before(n: u64, _next: &str) -> String {
+        Self::cardinal(n)
+    }
+
+    /// Four digits without separators
+    fn year(n: u64) -> String {
+        Self::cardinal(n)
+    }
+
+    /// Decimals after the separator
+    fn fraction(digits: &str) -> String {
+        read_digits::<Self>(digits)
+    }
+
+    /// Numbers with an ordinal suffix, like "3.º" or "21st"
+    fn ordinal(word: &str) -> Option<String>;
+
+    /// `dd/mm/yyyy` or the order of the language, with the year already
+    /// read
+    fn date(first: u64, second: u64, year: &str) -> Option<String>;
+
+    fn roman(n: u64, after_name: bool) -> String;
+
+    /// `word` as written, with its dot. `next` is the following word.
+    fn abbreviation(word: &str, next: Option<&str>) -> Option<&'static str>;
+
+    /// Abbreviations written in two words, like "a. C."
+    fn abbreviation_pair(first: &str, second: &str) -> Option<&'static str>;
+}
+
+/// Rewrite numbers, dates, roman numerals and abbreviations the way they
+/// are read in `language`. Languages without rules are left as they are.
+pub fn normalize(text: &str, language: Languages) -> Normalized {
+    match language {
+        Languages::Spanish => normalize_with::<Spanish>(text),
+        Languages::English => normalize_with::<English>(text),
+        _ => Normalized {
+            text: text.to_owned(),
+            spans: vec![],
+        },
+    }
+}
+
+fn normalize_with<R: Rules>(text: &str) -> Normalized {
+    let words = words(text);
+    let mut normalized = Normalized::default();
+    let mut copied = 0;
+
+    let mut idx = 0;
+    while idx < words.len() {
+        let Some((end, mut spoken, consumed)) = rewrite::<R>(&words, idx) else {
+            idx += 1;
+            continue;
+        };
+
+        // The dot of an abbreviation closing the text ends the sentence too
+        if text[..end].ends_with('.') && !text[end..].contains(char::is_alphanumeric) {
+            spoken.push('.');
+        }
+
+        let start = words[idx].start;
+        normalized.text.push_str(&text[copied..start]);
+        let spoken_start = normalized.text.len();
+        normalized.text.push_str(&spoken);
+        normalized.spans.push(Span {
+            display: start..end,
+            spoken: spoken_start..normalized.text.len(),
+        });
+
+        copied = end;
+        idx += consumed;
+    }
+    normalized.text.push_str(&text[copied..]);
+
+    normalized
+}
+
+/// A whitespace separated word, without the punctuation around it
+struct Word<'a> {
+    /// Byte offset in the text
+    start: usize,
+    bare: &'a str,
+    /// [`Word::bare`] and the dot after it, if any
+    dotted: &'a str,
+    /// Followed by punctuation, so the next word is not part of the phrase
+    closed: bool,
+}
+
+impl<'a> Word<'a> {
+    fn new(word: &'a str, start: usize) -> Option<Self> {
+        let lead = word.len()
+            - word
+                .trim_start_matches(|c: char| !c.is_alphanumeric())
+                .len();
+        let bare = word[lead..]
+            .trim_end_matches(|c: char| !c.is_alphanumeric() && !WORD_SUFFIXES.contains(&c));
+        if bare.is_empty() {
+            return None;
+        }
+
+        let end = lead + bare.len();
+        let dotted = if word[end..].starts_with('.') {
+            &word[lead..end + 1]
+        } else {
+            bare
+        };
+
+        Some(Self {
+            start: start + lead,
+            bare,
+            dotted,
+            closed: end < word.len(),
+        })
+    }
+
+    fn end(&self) -> usize {
+        self.start + self.bare.len()
+    }
+
+    fn is_name(&self) -> bool {
+        let mut chars = self.bare.chars();
+        chars.next().is_some_and(char::is_uppercase)
+            && self.bare.chars().count() > 1
+            && chars.all(char::is_lowercase)
+    }
+
+    /// A word of a title in capitals, like "MI" in "LA CASA DE MI PADRE"
+    fn in_capitals(&self) -> bool {
+        self.bare.chars().count() > 1
+            && self.bare.chars().all(char::is_uppercase)
+            && parse_roman(self.bare).is_none()
+    }
+}
+
+fn words(text: &str) -> Vec<Word<'_>> {
+    let mut words = vec![];
+    let mut offset = 0;
+
+    while let Some(start) = text[offset..].find(|c: char| !c.is_whitespace()) {
+        let start = offset + start;
+        let end = text[start..]
+            .find(char::is_whitespace)
+            .map_or(text.len(), |len| start + len);
+
+        words.extend(Word::new(&text[start..end], start));
+        offset = end;
+    }
+
+    words
+}
+
+/// The end of what was rewritten in the text, how it is read and how many
+/// words it took
+fn rewrite<R: Rules>(words: &[Word], idx: usize) -> Option<(usize, String, usize)> {
+    let word = &words[idx];
+    let previous = idx
+        .checked_sub(1)
+        .map(|idx| &words[idx])
+        .filter(|previous| !previous.closed);
+    let next = words.get(idx + 1);
+    let next_in_phrase = next.filter(|_| !word.closed);
+
+    if let Some(next) = next {
+        if let Some(spoken) = R::abbreviation_pair(word.dotted, next.dotted) {
+            return Some((next.start + next.dotted.len(), spoken.to_owned(), 2));
+        }
+    }
+
+    let next_bare = next.map(|next| next.bare);
+    if let Some(spoken) = R::abbreviation(word.dotted, next_bare) {
+        return Some((word.start + word.dotted.len(), spoken.to_owned(), 1));
+    }
+    if let Some(spoken) = R::abbreviation(word.bare, next_bare) {
+        return Some((word.end(), spoken.to_owned(), 1));
+    }
+
+    let spoken = R::ordinal(word.bare)
+        .or_else(|| percent::<R>(word.bare))
+        .or_else(|| date::<R>(word.bare))
+        .or_else(|| number::<R>(word.bare, next_in_phrase.map(|next| next.bare)))
+        .or_else(|| roman::<R>(words, idx))?;
+
+    Some((word.end(), spoken, 1))
+}
+
+fn percent<R: Rules>(word: &str) -> Option<String> {
+    let number = number::<R>(word.strip_suffix('%')?, None)?;
+    Some(format!("{number} {}", R::PERCENT_WORD))
+}
+
+fn date<R: Rules>(word: &str) -> Option<String> {
+    let parts = word.split('/').collect::<Vec<_>>();
+    let [first, second, year] = parts[..] else {
+        return None;
+    };
+
+    let all_digits = parts
+        .iter()
+        .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+    if !all_digits || first.len() > 2 || second.len() > 2 || ![2, 4].contains(&year.len()) {
+        return None;
+    }
+
+    let year = match year.parse().ok()? {
+        year if year >= 1000 => R::year(year),
+        year => R::cardinal(year),
+    };
+    R::date(first.parse().ok()?, second.parse().ok()?, &year)
+}
+
+fn number<R: Rules>(word: &str, next: Option<&str>) -> Option<String> {
+    if !word.starts_with(|c: char| c.is_ascii_digit())
+        || !word
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+    {
+        return None;
+    }
+
+    let (thousands, decimal) = R::SEPARATORS;
+    let (integer, fraction) = split_number(word, thousands, decimal)?;
+
+    let integer_words = match integer.parse::<u64>() {
+        // Leading zeros are read, "007"
+        _ if integer.len() > 1 && integer.starts_with('0') => read_digits::<R>(&integer),
+        Ok(n) if n > MAX_CARDINAL => read_digits::<R>(&integer),
+        Ok(n) if fraction.is_none() && word.len() == 4 => R::year(n),
+        Ok(n) => match (fraction, next) {
+            (None, Some(next)) => R::cardinal_before(n, next),
+            _ => R::cardinal(n),
+        },
+        Err(_) => read_digits::<R>(&integer),
+    };
+
+    Some(match fraction {
+        Some(fraction) => format!(
+            "{integer_words} {} {}",
+            R::DECIMAL_WORD,
+            R::fraction(fraction)
+        ),
+        None => integer_words,
+    })
+}
+
+/// The integer digits and the decimals. The thousands separator only
+/// counts between groups of three digits, otherwise it is taken as the
+/// decimal one ("3.5" in Spanish).
+fn split_number(word: &str, thousands: char, decimal: char) -> Option<(String, Option<&str>)> {
+    let (integer, fraction) = match word.rsplit_once(decimal) {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (word, None),
+    };
+    if integer.contains(decimal) || fraction.is_some_and(str::is_empty) {
+        return None;
+    }
+
+    let groups = integer.split(thousands).collect::<Vec<_>>();
+    let grouped =
+        (1..=3).contains(&groups[0].len()) && groups[1..].iter().all(|group| group.len() == 3);
+
+    match (groups.len(), grouped, fraction) {
+        (1, _, _) => Some((integer.to_owned(), fraction)),
+        (_, true, _) => Some((groups.concat(), fraction)),
+        (2, false, None) if !groups[0].is_empty() && !groups[1].is_empty() => {
+            Some((groups[0].to_owned(), Some(groups[1])))
+        }
+        _ => None,
+    }
+}
+
+fn read_digits<R: Rules>(digits: &str) -> String {
+    digits
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|digit| R::cardinal(digit as u64))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Only numerals after a trigger word ("siglo XIX") or a name ("Luis XIV"),
+/// or a heading made of the numeral alone. Anywhere else capitals are more
+/// likely an acronym.
+fn roman<R: Rules>(words: &[Word], idx: usize) -> Option<String> {
+    let word = &words[idx];
+    let n = parse_roman(word.bare)?;
+    let previous = idx
+        .checked_sub(1)
+        .map(|idx| &words[idx])
+        .filter(|previous| !previous.closed);
+    let next = words.get(idx + 1);
+
+    let trigger = previous
+        .is_some_and(|previous| R::ROMAN_TRIGGERS.contains(&previous.bare.to_lowercase().as_str()));
+    if trigger {
+        return Some(R::roman(n, false));
+    }
+
+    if ROMAN_ACRONYMS.contains(&word.bare)
+        || previous.is_some_and(Word::in_capitals)
+        || next.is_some_and(Word::in_capitals)
+    {
+        return None;
+    }
+
+    let heading = words.len() == 1 && word.bare.len() > 1;
+    let after_name = previous.is_some_and(Word::is_name)
+        && (word.bare.len() > 1
+            || SINGLE_ROMANS.contains(word.bare) && (word.bare != "I" || R::SINGLE_I));
+
+    match (heading, after_name) {
+        (true, _) => Some(R::roman(n, false)),
+        (_, true) => Some(R::roman(n, true)),
+        _ => None,
+    }
+}
+
+/// Only canonical numerals, "IIII" or "VX" are not numbers
+fn parse_roman(word: &str) -> Option<u64> {
+    const NUMERALS: &[(u64, &str)] = &[
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+
+    let mut rest = word;
+    let mut value = 0;
+    for (numeral_value, numeral) in NUMERALS {
+        // Only "M", "C", "X" and "I" can be repeated, three times at most
+        let max = if numeral.len() == 1 && "MCXI".contains(numeral) {
+            3
+        } else {
+            1
+        };
+        for _ in 0..max {
+            match rest.strip_prefix(numeral) {
+                Some(stripped) => {
+                    rest = stripped;
+                    value += numeral_value;
+                }
+                None => break,
+            }
+        }
+    }
+
+    (rest.is_empty() && value > 0 && to_roman_len(value) == word.len()).then_some(value)
+}
+
+/// Length of the canonical numeral, "XCX" parses but it is "C"
+fn to_roman_len(mut value: u64) -> usize {
+    const LENGTHS: &[(u64, usize)] = &[
+        (1000, 1),
+        (900, 2),
+        (500, 1),
+        (400, 2),
+        (100, 1),
+        (90, 2),
+        (50, 1),
+        (40, 2),
+        (10, 1),
+        (9, 2),
+        (5, 1),
+        (4, 2),
+        (1, 1),
+    ];
+
+    let mut len = 0;
+    for (numeral_value, numeral_len) in LENGTHS {
+        while value >= *numeral_value {
+            value -= numeral_value;
+            len += numeral_len;
+        }
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_table(language: Languages, table: &[(&str, &str)]) {
+        for (text, spoken) in table {
+            assert_eq!(normalize(text, language).text, *spoken, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn spanish() {
+        assert_table(
+            Languages::Spanish,
+            &[
+                ("Nació en 1830.", "Nació en mil ochocientos treinta."),
+                ("Hay 1.000.000 de libros.", "Hay un millón de libros."),
+                ("Tiene 21 años.", "Tiene veintiún años."),
+                ("Rodríguez, 18 de mayo.", "Rodríguez, dieciocho de mayo."),
+                ("Agente 007.", "Agente cero cero siete."),
+                ("Pesa 3,5 kilos.", "Pesa tres coma cinco kilos."),
+                ("Pesa 3,05 kilos.", "Pesa tres coma cero cinco kilos."),
+                ("Pesa 3.5 kilos.", "Pesa tres coma cinco kilos."),
+                ("El 50% de ellos.", "El cincuenta por ciento de ellos."),
+                ("Quedó 3.º en la carrera.", "Quedó tercero en la carrera."),
+                ("La 2.ª vez.", "La segunda vez."),
+                (
+                    "El 12/10/1492.",
+                    "El doce de octubre de mil cuatrocientos noventa y dos.",
+                ),
+                ("Vino el Sr. D. Pedro.", "Vino el señor don Pedro."),
+                ("Ganó el D.", "Ganó el D."),
+                ("Año 50 a. C.", "Año cincuenta antes de Cristo."),
+            ],
+        );
+    }
+
+    #[test]
+    fn spanish_roman_numerals() {
+        assert_table(
+            Languages::Spanish,
+            &[
+                ("XIV", "catorce"),
+                ("El siglo XIV fue largo.", "El siglo catorce fue largo."),
+                ("Tomó el tomo I.", "Tomó el tomo uno."),
+                ("Luis XIV reinó.", "Luis catorce reinó."),
+                (
+                    "Felipe II y Felipe III.",
+                    "Felipe segundo y Felipe tercero.",
+                ),
+                // Capitals that are not numbers
+                ("Compré un CD y un DVD.", "Compré un CD y un DVD."),
+                ("Mi CD nuevo.", "Mi CD nuevo."),
+                ("Escuchó el MIX de DC.", "Escuchó el MIX de DC."),
+                ("Talla XL.", "Talla XL."),
+                ("LA CASA DE MI PADRE", "LA CASA DE MI PADRE"),
+                (
+                    "Era el número V en la lista.",
+                    "Era el número V en la lista.",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn english() {
+        assert_table(
+            Languages::English,
+            &[
+                (
+                    "It cost 1,000.5 dollars.",
+                    "It cost one thousand point five dollars.",
+                ),
+                ("She came 21st.", "She came twenty-first."),
+                (
+                    "On 12/25/1999 we met.",
+                    "On December twenty-fifth, nineteen ninety-nine we met.",
+                ),
+                ("Mr. Smith arrived.", "Mister Smith arrived."),
+                ("Chapter XII begins.", "Chapter twelve begins."),
+                ("Henry VIII was king.", "Henry the eighth was king."),
+                ("Then I went home.", "Then I went home."),
+                ("The CD was scratched.", "The CD was scratched."),
+            ],
+        );
+    }
+
+    #[test]
+    fn other_languages_are_left_as_they_are() {
+        let text = "Le 14 juillet 1789.";
+        assert_eq!(
+            normalize(text, Languages::French),
+            Normalized {
+                text: text.to_owned(),
+                spans: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn spans_map_back_to_the_text() {
+        let normalized = normalize("Luis XIV, en 1830.", Languages::Spanish);
+        assert_eq!(normalized.text, "Luis catorce, en mil ochocientos treinta.");
+        assert_eq!(
+            normalized.spans,
+            [
+                Span {
+                    display: 5..8,
+                    spoken: 5..12,
+                },
+                Span {
+                    display: 13..17,
+                    spoken: 17..40,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn split_numbers() {
+        let split = |word| split_number(word, '.', ',');
+
+        assert_eq!(split("1830"), Some(("1830".to_owned(), None)));
+        assert_eq!(split("1.000"), Some(("1000".to_owned(), None)));
+        assert_eq!(
+            split("1.000.000,25"),
+            Some(("1000000".to_owned(), Some("25")))
+        );
+        // Not groups of three, the dot is the decimal separator
+        assert_eq!(split("3.5"), Some(("3".to_owned(), Some("5"))));
+        assert_eq!(split("12.3456"), Some(("12".to_owned(), Some("3456"))));
+
+        assert_eq!(split("1,"), None);
+        assert_eq!(split("1,2,3"), None);
+        assert_eq!(split(".5"), None);
+        assert_eq!(split("1.00.0"), None);
+        assert_eq!(split("3.5,1"), None);
+    }
+
+    #[test]
+    fn canonical_roman_numerals() {
+        assert_eq!(parse_roman("MCMXCIX"), Some(1999));
+        assert_eq!(parse_roman("XIV"), Some(14));
+        assert_eq!(parse_roman("IIII"), None);
+        assert_eq!(parse_roman("VX"), None);
+        assert_eq!(parse_roman("XCX"), None);
+        assert_eq!(parse_roman("Mix"), None);
+    }
+}
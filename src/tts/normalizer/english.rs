@@ -0,0 +1,187 @@
+use super::Rules;
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("Mr.", "Mister"),
+    ("Mrs.", "Missus"),
+    ("Ms.", "Miz"),
+    ("Dr.", "Doctor"),
+    ("Prof.", "Professor"),
+    ("Capt.", "Captain"),
+    ("Col.", "Colonel"),
+    ("Gen.", "General"),
+    ("Lt.", "Lieutenant"),
+    ("Sgt.", "Sergeant"),
+    ("Mt.", "Mount"),
+    ("Jr.", "Junior"),
+    ("Sr.", "Senior"),
+    ("vs.", "versus"),
+    ("etc.", "et cetera"),
+    ("e.g.", "for example"),
+    ("i.e.", "that is"),
+    ("approx.", "approximately"),
+];
+
+pub struct English;
+
+impl Rules for English {
+    const SEPARATORS: (char, char) = (',', '.');
+    const DECIMAL_WORD: &'static str = "point";
+    const PERCENT_WORD: &'static str = "percent";
+    const ROMAN_TRIGGERS: &'static [&'static str] = &[
+        "chapter", "part", "book", "volume", "act", "scene", "canto", "section", "appendix",
+    ];
+    const SINGLE_I: bool = false;
+
+    fn cardinal(n: u64) -> String {
+        match n {
+            0..=19 => ONES[n as usize].to_owned(),
+            20..=99 => match n % 10 {
+                0 => TENS[n as usize / 10].to_owned(),
+                unit => format!("{}-{}", TENS[n as usize / 10], ONES[unit as usize]),
+            },
+            100..=999 => scaled(n, 100, "hundred"),
+            1_000..=999_999 => scaled(n, 1_000, "thousand"),
+            1_000_000..=999_999_999 => scaled(n, 1_000_000, "million"),
+            _ => scaled(n, 1_000_000_000, "billion"),
+        }
+    }
+
+    /// "1830" is "eighteen thirty", "1905" is "nineteen oh five"
+    fn year(n: u64) -> String {
+        if !(1100..=2099).contains(&n) || (2000..=2009).contains(&n) {
+            return Self::cardinal(n);
+        }
+
+        let century = Self::cardinal(n / 100);
+        match n % 100 {
+            0 => format!("{century} hundred"),
+            rest @ 1..=9 => format!("{century} oh {}", Self::cardinal(rest)),
+            rest => format!("{century} {}", Self::cardinal(rest)),
+        }
+    }
+
+    fn ordinal(word: &str) -> Option<String> {
+        let len = word.len() - word.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let suffix = word[len..].to_lowercase();
+        if len == 0 || !["st", "nd", "rd", "th"].contains(&suffix.as_str()) {
+            return None;
+        }
+
+        Some(ordinal(Self::cardinal(word[..len].parse().ok()?)))
+    }
+
+    /// Month first, unless it cannot be a month
+    fn date(first: u64, second: u64, year: &str) -> Option<String> {
+        let (month, day) = if first <= 12 {
+            (first, second)
+        } else {
+            (second, first)
+        };
+        if !(1..=31).contains(&day) || !(1..=12).contains(&month) {
+            return None;
+        }
+
+        Some(format!(
+            "{} {}, {year}",
+            MONTHS[month as usize - 1],
+            ordinal(Self::cardinal(day))
+        ))
+    }
+
+    /// "Louis XIV" is "Louis the fourteenth"
+    fn roman(n: u64, after_name: bool) -> String {
+        if after_name {
+            format!("the {}", ordinal(Self::cardinal(n)))
+        } else {
+            Self::cardinal(n)
+        }
+    }
+
+    fn abbreviation(word: &str, next: Option<&str>) -> Option<&'static str> {
+        let before_name = next.is_some_and(|next| next.starts_with(char::is_uppercase));
+        let before_number = next.is_some_and(|next| next.starts_with(|c: char| c.is_ascii_digit()));
+
+        match word {
+            "St." if before_name => Some("Saint"),
+            "St." => Some("Street"),
+            "No." if before_number => Some("number"),
+            _ => ABBREVIATIONS
+                .iter()
+                .find(|(abbreviation, _)| *abbreviation == word)
+                .map(|(_, spoken)| *spoken),
+        }
+    }
+
+    fn abbreviation_pair(_first: &str, _second: &str) -> Option<&'static str> {
+        None
+    }
+}
+
+fn scaled(n: u64, scale: u64, name: &str) -> String {
+    let head = format!("{} {name}", English::cardinal(n / scale));
+    match n % scale {
+        0 => head,
+        rest => format!("{head} {}", English::cardinal(rest)),
+    }
+}
+
+/// Only the last word changes, "twenty-one" is "twenty-first"
+fn ordinal(cardinal: String) -> String {
+    let split = cardinal.rfind([' ', '-']).map_or(0, |idx| idx + 1);
+    let (head, last) = cardinal.split_at(split);
+
+    let last = match last {
+        "one" => "first".to_owned(),
+        "two" => "second".to_owned(),
+        "three" => "third".to_owned(),
+        "five" => "fifth".to_owned(),
+        "eight" => "eighth".to_owned(),
+        "nine" => "ninth".to_owned(),
+        "twelve" => "twelfth".to_owned(),
+        word => match word.strip_suffix('y') {
+            Some(stem) => format!("{stem}ieth"),
+            None => format!("{word}th"),
+        },
+    };
+
+    format!("{head}{last}")
+}
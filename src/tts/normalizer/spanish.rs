@@ -0,0 +1,307 @@
+use super::Rules;
+
+const UNITS: [&str; 30] = [
+    "cero",
+    "uno",
+    "dos",
+    "tres",
+    "cuatro",
+    "cinco",
+    "seis",
+    "siete",
+    "ocho",
+    "nueve",
+    "diez",
+    "once",
+    "doce",
+    "trece",
+    "catorce",
+    "quince",
+    "dieciséis",
+    "diecisiete",
+    "dieciocho",
+    "diecinueve",
+    "veinte",
+    "veintiuno",
+    "veintidós",
+    "veintitrés",
+    "veinticuatro",
+    "veinticinco",
+    "veintiséis",
+    "veintisiete",
+    "veintiocho",
+    "veintinueve",
+];
+const TENS: [&str; 10] = [
+    "",
+    "",
+    "",
+    "treinta",
+    "cuarenta",
+    "cincuenta",
+    "sesenta",
+    "setenta",
+    "ochenta",
+    "noventa",
+];
+const HUNDREDS: [&str; 10] = [
+    "",
+    "ciento",
+    "doscientos",
+    "trescientos",
+    "cuatrocientos",
+    "quinientos",
+    "seiscientos",
+    "setecientos",
+    "ochocientos",
+    "novecientos",
+];
+const ORDINAL_UNITS: [&str; 10] = [
+    "", "primero", "segundo", "tercero", "cuarto", "quinto", "sexto", "séptimo", "octavo", "noveno",
+];
+const ORDINAL_TENS: [&str; 10] = [
+    "",
+    "décimo",
+    "vigésimo",
+    "trigésimo",
+    "cuadragésimo",
+    "quincuagésimo",
+    "sexagésimo",
+    "septuagésimo",
+    "octogésimo",
+    "nonagésimo",
+];
+const MONTHS: [&str; 12] = [
+    "enero",
+    "febrero",
+    "marzo",
+    "abril",
+    "mayo",
+    "junio",
+    "julio",
+    "agosto",
+    "septiembre",
+    "octubre",
+    "noviembre",
+    "diciembre",
+];
+/// A number before them is not counting them, "1 de enero"
+const NOT_NOUNS: &[&str] = &[
+    "de", "del", "a", "al", "y", "e", "o", "u", "en", "con", "por", "para", "que",
+];
+const ABBREVIATIONS: &[(&str, &str)] = &[
+    ("Sr.", "señor"),
+    ("Sres.", "señores"),
+    ("Sra.", "señora"),
+    ("Sras.", "señoras"),
+    ("Srta.", "señorita"),
+    ("D.", "don"),
+    ("Dña.", "doña"),
+    ("Dª", "doña"),
+    ("Dr.", "doctor"),
+    ("Dra.", "doctora"),
+    ("Ud.", "usted"),
+    ("Uds.", "ustedes"),
+    ("Vd.", "usted"),
+    ("Vds.", "ustedes"),
+    ("S.", "san"),
+    ("Sto.", "santo"),
+    ("Sta.", "santa"),
+    ("Excmo.", "excelentísimo"),
+    ("Excma.", "excelentísima"),
+    ("Ilmo.", "ilustrísimo"),
+    ("Ilma.", "ilustrísima"),
+    ("etc.", "etcétera"),
+    ("pág.", "página"),
+    ("págs.", "páginas"),
+    ("núm.", "número"),
+    ("n.º", "número"),
+    ("nº", "número"),
+    ("aprox.", "aproximadamente"),
+    ("Avda.", "avenida"),
+    ("Av.", "avenida"),
+    ("a.C.", "antes de Cristo"),
+    ("d.C.", "después de Cristo"),
+    ("p.ej.", "por ejemplo"),
+    ("EE.UU.", "Estados Unidos"),
+];
+const ABBREVIATION_PAIRS: &[(&str, &str, &str)] = &[
+    ("a.", "C.", "antes de Cristo"),
+    ("d.", "C.", "después de Cristo"),
+    ("p.", "ej.", "por ejemplo"),
+    ("EE.", "UU.", "Estados Unidos"),
+];
+
+pub struct Spanish;
+
+impl Rules for Spanish {
+    const SEPARATORS: (char, char) = ('.', ',');
+    const DECIMAL_WORD: &'static str = "coma";
+    const PERCENT_WORD: &'static str = "por ciento";
+    const ROMAN_TRIGGERS: &'static [&'static str] = &[
+        "siglo",
+        "siglos",
+        "capítulo",
+        "tomo",
+        "libro",
+        "parte",
+        "acto",
+        "volumen",
+        "canto",
+        "escena",
+    ];
+    const SINGLE_I: bool = true;
+
+    fn cardinal(n: u64) -> String {
+        match n {
+            0..=29 => UNITS[n as usize].to_owned(),
+            30..=99 => match n % 10 {
+                0 => TENS[n as usize / 10].to_owned(),
+                unit => format!("{} y {}", TENS[n as usize / 10], UNITS[unit as usize]),
+            },
+            100 => "cien".to_owned(),
+            101..=999 => followed_by(HUNDREDS[n as usize / 100].to_owned(), n % 100),
+            1_000..=999_999 => {
+                let thousands = match n / 1_000 {
+                    1 => "mil".to_owned(),
+                    thousands => format!("{} mil", apocope(Self::cardinal(thousands))),
+                };
+                followed_by(thousands, n % 1_000)
+            }
+            _ => {
+                let millions = match n / 1_000_000 {
+                    1 => "un millón".to_owned(),
+                    millions => format!("{} millones", apocope(Self::cardinal(millions))),
+                };
+                followed_by(millions, n % 1_000_000)
+            }
+        }
+    }
+
+    fn cardinal_before(n: u64, next: &str) -> String {
+        let is_noun = next.starts_with(char::is_lowercase) && !NOT_NOUNS.contains(&next);
+        if is_noun {
+            apocope(Self::cardinal(n))
+        } else {
+            Self::cardinal(n)
+        }
+    }
+
+    /// "3,5" is "tres coma cinco", "3,05" is "tres coma cero cinco"
+    fn fraction(digits: &str) -> String {
+        match digits.parse() {
+            Ok(n) if digits.len() <= 2 && !digits.starts_with('0') => Self::cardinal(n),
+            _ => super::read_digits::<Self>(digits),
+        }
+    }
+
+    fn ordinal(word: &str) -> Option<String> {
+        let len = word.len() - word.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if len == 0 {
+            return None;
+        }
+
+        let n = word[..len].parse().ok()?;
+        let ordinal = ordinal(n)?;
+        let (dot, suffix) = match word[len..].strip_prefix('.') {
+            Some(suffix) => (true, suffix),
+            None => (false, &word[len..]),
+        };
+
+        match (dot, suffix) {
+            (_, "º" | "°") | (true, "o") => Some(ordinal),
+            (_, "ª") | (true, "a") => Some(inflect(&ordinal, "a")),
+            (true, "os") => Some(inflect(&ordinal, "os")),
+            (true, "as") => Some(inflect(&ordinal, "as")),
+            // "1.er piso", "3.er acto"
+            (_, "er") if [1, 3].contains(&(n % 10)) && n != 11 => {
+                Some(ordinal.trim_end_matches('o').to_owned())
+            }
+            _ => None,
+        }
+    }
+
+    fn date(day: u64, month: u64, year: &str) -> Option<String> {
+        if !(1..=31).contains(&day) || !(1..=12).contains(&month) {
+            return None;
+        }
+
+        Some(format!(
+            "{} de {} de {year}",
+            Self::cardinal(day),
+            MONTHS[month as usize - 1]
+        ))
+    }
+
+    /// "Carlos V" is "Carlos quinto" but "Luis XIV" is "Luis catorce"
+    fn roman(n: u64, after_name: bool) -> String {
+        match ordinal(n) {
+            Some(ordinal) if after_name && n <= 10 => ordinal,
+            _ => Self::cardinal(n),
+        }
+    }
+
+    fn abbreviation(word: &str, next: Option<&str>) -> Option<&'static str> {
+        match word {
+            // Only before a name, otherwise it is an initial closing one
+            "D." | "S." if !next.is_some_and(|next| next.starts_with(char::is_uppercase)) => None,
+            _ => ABBREVIATIONS
+                .iter()
+                .find(|(abbreviation, _)| *abbreviation == word)
+                .map(|(_, spoken)| *spoken),
+        }
+    }
+
+    fn abbreviation_pair(first: &str, second: &str) -> Option<&'static str> {
+        ABBREVIATION_PAIRS
+            .iter()
+            .find(|(a, b, _)| *a == first && *b == second)
+            .map(|(_, _, spoken)| *spoken)
+    }
+}
+
+fn followed_by(head: String, rest: u64) -> String {
+    match rest {
+        0 => head,
+        rest => format!("{head} {}", Spanish::cardinal(rest)),
+    }
+}
+
+/// "uno" is "un" before a noun, also in "veintiún mil"
+fn apocope(words: String) -> String {
+    if let Some(head) = words.strip_suffix("veintiuno") {
+        format!("{head}veintiún")
+    } else if let Some(head) = words.strip_suffix("uno") {
+        format!("{head}un")
+    } else {
+        words
+    }
+}
+
+/// Up to 100, larger ordinals are rarely written and read as cardinals
+fn ordinal(n: u64) -> Option<String> {
+    match n {
+        1..=9 => Some(ORDINAL_UNITS[n as usize].to_owned()),
+        11 => Some("undécimo".to_owned()),
+        12 => Some("duodécimo".to_owned()),
+        10..=99 => match n % 10 {
+            0 => Some(ORDINAL_TENS[n as usize / 10].to_owned()),
+            unit => Some(format!(
+                "{} {}",
+                ORDINAL_TENS[n as usize / 10],
+                ORDINAL_UNITS[unit as usize]
+            )),
+        },
+        100 => Some("centésimo".to_owned()),
+        _ => None,
+    }
+}
+
+/// Change the masculine ending of every word, "vigésima primera"
+fn inflect(ordinal: &str, ending: &str) -> String {
+    ordinal
+        .split(' ')
+        .map(|word| format!("{}{ending}", word.strip_suffix('o').unwrap_or(word)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -1 +1,210 @@
+/// Sentences shorter than this are glued to the previous one
+const MAX_GLUED_LEN: usize = 16;
 
+/// Split `text` in sentences small enough to be sent to the TTS engine.
+///
+/// Sentences are cut at `.`, `?`, `!` and `…`. The ones longer than
+/// `max_len` bytes are cut again at `,`, `;` and `:`, then between words,
+/// and as a last resort inside the words that do not fit alone.
+pub fn tokenize(text: &str, max_len: usize) -> Vec<String> {
+    split_after(text, &['.', '?', '!', '…'])
+        .into_iter()
+        .flat_map(|sentence| {
+            if sentence.len() <= max_len {
+                return vec![sentence];
+            }
+
+            let clauses = split_after(&sentence, &[',', ';', ':'])
+                .into_iter()
+                .flat_map(|clause| split_words(&clause, max_len));
+            pack(clauses, max_len, usize::MAX)
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        // Glue tiny sentences together, "Sr." alone is not worth a request
+        .fold(vec![], |chunks, sentence| {
+            pack_into(chunks, sentence, max_len, MAX_GLUED_LEN)
+        })
+}
+
+/// Join consecutive `pieces` while they fit in `max_len`, only gluing pieces
+/// shorter than `max_piece`
+fn pack(pieces: impl Iterator<Item = String>, max_len: usize, max_piece: usize) -> Vec<String> {
+    pieces.fold(vec![], |chunks, piece| {
+        pack_into(chunks, piece, max_len, max_piece)
+    })
+}
+
+fn pack_into(
+    mut chunks: Vec<String>,
+    piece: String,
+    max_len: usize,
+    max_piece: usize,
+) -> Vec<String> {
+    match chunks.last_mut() {
+        Some(last) if last.len() + 1 + piece.len() <= max_len && piece.len() < max_piece => {
+            last.push(' ');
+            last.push_str(&piece);
+        }
+        _ => chunks.push(piece),
+    }
+    chunks
+}
+
+/// Split after any of `marks` when followed by a whitespace, keeping the mark
+fn split_after(text: &str, marks: &[char]) -> Vec<String> {
+    let mut pieces = vec![];
+    let mut piece = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        piece.push(c);
+
+        let at_boundary = chars.peek().is_none_or(|next| next.is_whitespace());
+        if marks.contains(&c) && at_boundary {
+            push_trimmed(&mut pieces, &piece);
+            piece.clear();
+        }
+    }
+    push_trimmed(&mut pieces, &piece);
+
+    pieces
+}
+
+fn split_words(text: &str, max_len: usize) -> Vec<String> {
+    let mut pieces = vec![];
+    let mut piece = String::new();
+
+    for word in text
+        .split_whitespace()
+        .flat_map(|word| split_long(word, max_len))
+    {
+        if !piece.is_empty() && piece.len() + 1 + word.len() > max_len {
+            pieces.push(std::mem::take(&mut piece));
+        }
+
+        if !piece.is_empty() {
+            piece.push(' ');
+        }
+        piece.push_str(word);
+    }
+
+    if !piece.is_empty() {
+        pieces.push(piece);
+    }
+
+    pieces
+}
+
+/// Cut `word` between characters in pieces of at most `max_len` bytes, URLs
+/// and the like have no better place
+fn split_long(mut word: &str, max_len: usize) -> Vec<&str> {
+    let mut pieces = vec![];
+    while word.len() > max_len {
+        let mut end = max_len;
+        while !word.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            // A single character longer than `max_len`
+            end = word.chars().next().map_or(word.len(), char::len_utf8);
+        }
+
+        let (piece, rest) = word.split_at(end);
+        pieces.push(piece);
+        word = rest;
+    }
+    pieces.push(word);
+    pieces
+}
+
+fn push_trimmed(pieces: &mut Vec<String>, piece: &str) {
+    let piece = piece.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !piece.is_empty() {
+        pieces.push(piece);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tts::GOOGLE_TTS_MAX_CHARS;
+
+    fn fits(chunks: &[String], max_len: usize) -> bool {
+        chunks.iter().all(|chunk| chunk.len() <= max_len)
+    }
+
+    #[test]
+    fn sentences() {
+        assert_eq!(
+            tokenize(
+                "El hidalgo salió de su casa al alba.  ¿Adónde iría?\nNadie lo sabía!",
+                100
+            ),
+            [
+                "El hidalgo salió de su casa al alba.",
+                "¿Adónde iría?",
+                "Nadie lo sabía!"
+            ]
+        );
+        // Marks inside words do not end sentences
+        assert_eq!(tokenize("Costó 3.50 euros.", 100), ["Costó 3.50 euros."]);
+        assert!(tokenize("  \n ", 100).is_empty());
+    }
+
+    #[test]
+    fn short_sentences_are_glued() {
+        // Only the short ones, "Quizás..." fits but goes on its own
+        assert_eq!(
+            tokenize("Sí. No. Quizás mañana, cuando vuelva.", 100),
+            ["Sí. No.", "Quizás mañana, cuando vuelva."]
+        );
+        assert_eq!(tokenize("Sí. No.", 5), ["Sí.", "No."]);
+    }
+
+    #[test]
+    fn long_sentences_fit() {
+        let sentence = "En un lugar de la Mancha, de cuyo nombre no quiero acordarme, \
+            no ha mucho tiempo que vivía un hidalgo de los de lanza en astillero, \
+            adarga antigua, rocín flaco y galgo corredor.";
+        let chunks = tokenize(sentence, GOOGLE_TTS_MAX_CHARS);
+
+        assert!(chunks.len() > 1);
+        assert!(fits(&chunks, GOOGLE_TTS_MAX_CHARS));
+        assert!(chunks[0].ends_with(','));
+        assert_eq!(
+            chunks.join(" "),
+            sentence.split_whitespace().collect::<Vec<_>>().join(" ")
+        );
+    }
+
+    #[test]
+    fn multi_byte_text() {
+        // Two bytes every letter, the limit is in bytes
+        let sentence = "ñandú ".repeat(30);
+        let chunks = tokenize(&sentence, 40);
+
+        assert!(fits(&chunks, 40));
+        assert_eq!(chunks.join(" "), sentence.trim_end());
+    }
+
+    #[test]
+    fn overlong_words_are_cut() {
+        let url = format!("https://example.com/{}", "a".repeat(200));
+        let chunks = tokenize(&format!("Visita {url} ahora."), GOOGLE_TTS_MAX_CHARS);
+        assert!(fits(&chunks, GOOGLE_TTS_MAX_CHARS));
+        assert_eq!(
+            chunks.concat().replace(' ', ""),
+            format!("Visita{url}ahora.")
+        );
+
+        let word = "á".repeat(30);
+        let chunks = tokenize(&word, 15);
+        assert_eq!(chunks.len(), 5);
+        assert!(fits(&chunks, 15));
+        assert_eq!(chunks.concat(), word);
+
+        // A character can not be cut
+        assert_eq!(tokenize("ññ", 1), ["ñ", "ñ"]);
+    }
+}
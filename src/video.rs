@@ -0,0 +1,114 @@
+use std::str::FromStr;
+
+use gst::prelude::*;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
+use log::{error, trace};
+
+use crate::config::EbookConfig;
+use crate::encoder::VideoSettings;
+use crate::error::{EbookError, EbookResult};
+use crate::render::{EbookRenderer, SharedScene, Theme};
+use crate::stats::OutputStats;
+use crate::VIDEO_LOG;
+
+/// Name of the `appsrc` element the renderer pushes frames into
+pub const VIDEO_SRC_NAME: &str = "video";
+
+/// Feeds the `appsrc` with the frames of [`EbookRenderer`].
+///
+/// The last frame is kept and pushed again when the renderer has nothing new,
+/// the copy only shares the memory so repeating a page is almost free.
+struct VideoFeeder {
+    renderer: EbookRenderer,
+    frame_duration: gst::ClockTime,
+    last_frame: gst::Buffer,
+    frame_count: u64,
+    static_frames: bool,
+    stats: OutputStats,
+}
+
+impl VideoFeeder {
+    fn new(
+        video: VideoSettings,
+        static_frames: bool,
+        scene: SharedScene,
+        theme: Theme,
+        stats: OutputStats,
+    ) -> Self {
+        let frame_size = (video.width * video.height * 4) as usize;
+        let frame_duration = gst::ClockTime::from_nseconds(
+            gst::ClockTime::SECOND.nseconds() / video.framerate as u64,
+        );
+
+        Self {
+            renderer: EbookRenderer::new(video, static_frames, scene, theme),
+            frame_duration,
+            last_frame: gst::Buffer::from_mut_slice(vec![0; frame_size]),
+            frame_count: 0,
+            static_frames,
+            stats,
+        }
+    }
+
+    fn next_buffer(&mut self) -> gst::Buffer {
+        self.renderer.send_tick();
+
+        let frame = self.renderer.recv();
+        // Static frames repeat the page on purpose
+        self.stats.add_frame(frame.is_none() && !self.static_frames);
+        if let Some(frame) = frame {
+            trace!(target: VIDEO_LOG, "New frame");
+            self.last_frame = gst::Buffer::from_mut_slice(frame);
+        }
+
+        let mut buffer = self.last_frame.copy();
+        {
+            let buffer = buffer.get_mut().unwrap();
+            buffer.set_pts(self.frame_count * self.frame_duration);
+            buffer.set_duration(self.frame_duration);
+        }
+        self.frame_count += 1;
+
+        buffer
+    }
+}
+
+/// Connect the renderer to the `appsrc` named [`VIDEO_SRC_NAME`]. Does
+/// nothing for audio only presets.
+pub fn attach_renderer(
+    pipeline: &gst::Pipeline,
+    config: &EbookConfig,
+    scene: SharedScene,
+    theme: Theme,
+    stats: OutputStats,
+) -> EbookResult<()> {
+    let (Some(video), Some(caps)) = (config.encoder.video, config.encoder.video_caps()) else {
+        return Ok(());
+    };
+
+    let appsrc = pipeline
+        .by_name(VIDEO_SRC_NAME)
+        .expect("Video source element not found")
+        .downcast::<gst_app::AppSrc>()
+        .expect("Expected a gst_app::AppSrc");
+
+    let caps = gst::Caps::from_str(&caps).map_err(EbookError::GlibBool)?;
+    appsrc.set_caps(Some(&caps));
+    appsrc.set_format(gst::Format::Time);
+
+    let mut feeder = VideoFeeder::new(video, config.encoder.static_frames, scene, theme, stats);
+
+    appsrc.set_callbacks(
+        gst_app::AppSrcCallbacks::builder()
+            .need_data(move |appsrc, _| {
+                let buffer = feeder.next_buffer();
+                if let Err(err) = appsrc.push_buffer(buffer) {
+                    error!(target: VIDEO_LOG, "{err}");
+                }
+            })
+            .build(),
+    );
+
+    Ok(())
+}